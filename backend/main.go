@@ -2,6 +2,8 @@ package main
 
 import (
 	"bytes"
+	"crypto/rand"
+	"encoding/hex"
 	"encoding/json"
 	"log"
 	"net/http"
@@ -95,6 +97,9 @@ var db *gorm.DB
 var (
 	AI_SERVICE_URL  = getEnvWithDefault("RUST_AI_URL", "http://localhost:3001")
 	SNS_SERVICE_URL = getEnvWithDefault("PYTHON_SNS_URL", "http://localhost:3002")
+
+	// philosophy-ai の auth.api_keys に登録したサービス用のAPIキー（空なら送らない）
+	AI_SERVICE_API_KEY = os.Getenv("RUST_AI_API_KEY")
 )
 
 func getEnvWithDefault(key, defaultValue string) string {
@@ -122,13 +127,53 @@ func initDatabase() {
 }
 
 // AI Service Integration
-func callAIService(endpoint string, data interface{}) (map[string]interface{}, error) {
+// philosophy-ai へ渡すトレース用のヘッダー
+type traceHeaders struct {
+	requestID   string
+	traceparent string
+}
+
+// 受け取った X-Request-Id と traceparent を引き継ぎ、無ければ新しく作る
+func traceFrom(c *gin.Context) traceHeaders {
+	trace := traceHeaders{
+		requestID:   c.GetHeader("X-Request-Id"),
+		traceparent: c.GetHeader("traceparent"),
+	}
+	if trace.requestID == "" {
+		trace.requestID = randomHex(16)
+	}
+	if trace.traceparent == "" {
+		trace.traceparent = "00-" + randomHex(16) + "-" + randomHex(8) + "-01"
+	}
+	return trace
+}
+
+func randomHex(n int) string {
+	b := make([]byte, n)
+	rand.Read(b)
+	return hex.EncodeToString(b)
+}
+
+func callAIService(trace traceHeaders, endpoint string, data interface{}) (map[string]interface{}, error) {
 	jsonData, err := json.Marshal(data)
 	if err != nil {
 		return nil, err
 	}
 
-	resp, err := http.Post(AI_SERVICE_URL+endpoint, "application/json", bytes.NewBuffer(jsonData))
+	req, err := http.NewRequest(http.MethodPost, AI_SERVICE_URL+endpoint, bytes.NewBuffer(jsonData))
+	if err != nil {
+		return nil, err
+	}
+
+	req.Header.Set("Content-Type", "application/json")
+	req.Header.Set("X-Request-Id", trace.requestID)
+	req.Header.Set("traceparent", trace.traceparent)
+	if AI_SERVICE_API_KEY != "" {
+		req.Header.Set("X-Api-Key", AI_SERVICE_API_KEY)
+	}
+
+	client := &http.Client{}
+	resp, err := client.Do(req)
 	if err != nil {
 		return nil, err
 	}
@@ -189,12 +234,13 @@ func createArticle(c *gin.Context) {
 	article.CreatedAt = time.Now()
 	db.Create(&article)
 	
-	// AI分析を並行実行
+	// AI分析を並行実行（gin.Context はハンドラーを抜けると使えないので先にヘッダーを取る）
+	trace := traceFrom(c)
 	go func() {
 		analysisData := map[string]interface{}{
 			"text": article.Content,
 		}
-		analysis, err := callAIService("/analyze", analysisData)
+		analysis, err := callAIService(trace, "/analyze", analysisData)
 		if err != nil {
 			log.Printf("AI analysis failed: %v", err)
 		} else {
@@ -243,7 +289,7 @@ func chatWithCharacter(c *gin.Context) {
 		return
 	}
 	
-	response, err := callAIService("/chat", request)
+	response, err := callAIService(traceFrom(c), "/chat", request)
 	if err != nil {
 		c.JSON(http.StatusInternalServerError, gin.H{"error": "AI service unavailable"})
 		return
@@ -262,7 +308,7 @@ func analyzePhilosophy(c *gin.Context) {
 		return
 	}
 	
-	response, err := callAIService("/analyze", request)
+	response, err := callAIService(traceFrom(c), "/analyze", request)
 	if err != nil {
 		c.JSON(http.StatusInternalServerError, gin.H{"error": "AI service unavailable"})
 		return
@@ -272,7 +318,7 @@ func analyzePhilosophy(c *gin.Context) {
 }
 
 func getCharacterPersonalities(c *gin.Context) {
-	response, err := callAIService("/personalities", nil)
+	response, err := callAIService(traceFrom(c), "/personalities", nil)
 	if err != nil {
 		c.JSON(http.StatusInternalServerError, gin.H{"error": "AI service unavailable"})
 		return
@@ -287,7 +333,7 @@ func generateWisdom(c *gin.Context) {
 		theme = "life"
 	}
 	
-	response, err := callAIService("/wisdom?theme="+theme, nil)
+	response, err := callAIService(traceFrom(c), "/wisdom?theme="+theme, nil)
 	if err != nil {
 		c.JSON(http.StatusInternalServerError, gin.H{"error": "AI service unavailable"})
 		return
@@ -128,6 +128,17 @@ CREATE TABLE iot_interactions (
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
+-- 哲学分析結果テーブル（philosophy-ai が保存する）
+CREATE TABLE philosophy_analyses (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    user_id VARCHAR(100),
+    text TEXT NOT NULL,
+    sentiment VARCHAR(20) NOT NULL, -- positive, negative, neutral
+    themes TEXT[] NOT NULL DEFAULT '{}',
+    payload JSONB NOT NULL, -- PhilosophyAnalysisV2 全体
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
 -- インデックス作成
 CREATE INDEX idx_posts_user_id ON posts(user_id);
 CREATE INDEX idx_posts_created_at ON posts(created_at DESC);
@@ -143,6 +154,11 @@ CREATE INDEX idx_ai_conversations_user_id ON ai_conversations(user_id);
 CREATE INDEX idx_ai_conversations_character ON ai_conversations(character_id);
 CREATE INDEX idx_ai_conversations_session ON ai_conversations(session_id);
 
+CREATE INDEX idx_philosophy_analyses_created_at ON philosophy_analyses(created_at DESC);
+CREATE INDEX idx_philosophy_analyses_sentiment ON philosophy_analyses(sentiment);
+CREATE INDEX idx_philosophy_analyses_user_id ON philosophy_analyses(user_id);
+CREATE INDEX idx_philosophy_analyses_themes ON philosophy_analyses USING GIN(themes);
+
 CREATE INDEX idx_philosophy_articles_language ON philosophy_articles(language);
 CREATE INDEX idx_philosophy_articles_difficulty ON philosophy_articles(difficulty);
 
@@ -0,0 +1,50 @@
+//! リクエストごとのエンジン取得コストの比較。
+//!
+//! `deep_clone` は以前の `warp::any().map(move || ai_engine.clone())` と同じく
+//! キャラクター・辞書・知恵データを毎回複製する。`shared` は現在の `Arc` 共有。
+//! `chat_request/deep_clone` はデータの複製とハンドラーだけを測り、
+//! `AIEngine::from_snapshot` がする類似検索の索引の構築は含めない（以前は無かった処理なので）。
+//!
+//! `cargo bench --bench engine`
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use philosophy_ai::AIEngine;
+
+fn engine_clone(c: &mut Criterion) {
+    let engine = AIEngine::new();
+    let snapshot = engine.snapshot();
+
+    let mut group = c.benchmark_group("engine_clone");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("deep_clone", |b| b.iter(|| (*snapshot).clone()));
+    group.bench_function("shared", |b| b.iter(|| engine.clone()));
+    group.finish();
+}
+
+fn chat_request(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let engine = AIEngine::new();
+    let snapshot = engine.snapshot();
+    let message = "技術と哲学について考えると、人生の意味が少し分かる気がする！";
+
+    let mut group = c.benchmark_group("chat_request");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function(BenchmarkId::new("deep_clone", "snowman"), |b| {
+        b.iter(|| {
+            let per_request = black_box((*snapshot).clone());
+            let response = runtime.block_on(engine.chat_with_character("snowman", message, None));
+            drop(per_request);
+            response
+        })
+    });
+    group.bench_function(BenchmarkId::new("shared", "snowman"), |b| {
+        b.iter(|| {
+            let per_request = engine.clone();
+            runtime.block_on(per_request.chat_with_character("snowman", message, None))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, engine_clone, chat_request);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+//! 長文に対する NLPProcessor のスループット。
+//!
+//! `cargo bench --bench nlp`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use philosophy_ai::NLPProcessor;
+
+const PARAGRAPH: &str = "技術と哲学について考えると、人生の意味が少し分かる気がする！\
+    AIやプログラミングは社会を変えるけど、家族や友情とのつながりこそが幸せの源だ。\
+    The meaning of code is not in the algorithm but in the people who read it. \
+    失敗や不安もあるが、成長のための経験として受け入れたい。Rust and Go make systems safer. ";
+
+fn long_document(paragraphs: usize) -> String {
+    PARAGRAPH.repeat(paragraphs)
+}
+
+fn nlp_throughput(c: &mut Criterion) {
+    let nlp = NLPProcessor::new();
+    let mut group = c.benchmark_group("nlp");
+
+    for paragraphs in [10, 100, 1000] {
+        let text = long_document(paragraphs);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("scan", text.len()), &text, |b, text| {
+            b.iter(|| nlp.scan(text))
+        });
+        group.bench_with_input(BenchmarkId::new("sentiment_breakdown", text.len()), &text, |b, text| {
+            b.iter(|| nlp.sentiment_breakdown(text))
+        });
+        group.bench_with_input(BenchmarkId::new("theme_scores", text.len()), &text, |b, text| {
+            b.iter(|| nlp.theme_scores(text))
+        });
+        group.bench_with_input(BenchmarkId::new("extract_keywords", text.len()), &text, |b, text| {
+            b.iter(|| nlp.extract_keywords(text))
+        });
+        group.bench_with_input(BenchmarkId::new("full_analysis", text.len()), &text, |b, text| {
+            b.iter(|| {
+                let matches = nlp.scan(text);
+                (
+                    nlp.sentiment_breakdown_with(text, &matches),
+                    nlp.theme_scores_with(&matches),
+                    nlp.extract_keywords_with(text, &matches),
+                )
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, nlp_throughput);
+criterion_main!(benches);
@@ -1,34 +1,165 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use chrono::{DateTime, Utc};
+use utoipa::ToSchema;
 use uuid::Uuid;
+use crate::argument::ArgumentStructure;
+use crate::cluster::{self, ClusterJob, ClusterJobs, ClusterResult, ClusterSource, Document};
 use crate::character_ai::{Character, CharacterPersonality};
-use crate::nlp::NLPProcessor;
+use crate::conviction::ConvictionScore;
+use crate::fallacy::Fallacy;
+use crate::moderation::{self, ModerationAction, ModerationVerdict, Moderator};
+use crate::nlp::{NLPProcessor, SentimentBreakdown, ThemeScore};
+use crate::redaction::Redactor;
+use crate::question::{self, Question, QuestionType};
+use crate::schools::SchoolScore;
+use crate::similarity::{DocumentKind, SimilarItem, SimilarityIndex};
+use crate::summary::Summary;
+use crate::session::SessionStore;
+use crate::socratic::{self, SocraticDialogue, SocraticTurn};
+use crate::stats::{EngineStats, StatsSnapshot};
+use crate::storage::{AnalysisQuery, AnalysisStore, MemoryStore, StoreError, MAX_PAGE_SIZE};
+use crate::trends::{self, GroupBy, Sample, TrendReport, TrendSpec};
 
+/// キャラクター・NLP辞書・知恵データの不変スナップショット
+#[derive(Clone)]
+pub struct EngineSnapshot {
+    pub characters: HashMap<String, Character>,
+    pub nlp: NLPProcessor,
+    pub wisdom_database: Vec<String>,
+}
+
+/// AIエンジン本体。
+///
+/// クローンは `Arc` のコピーだけで、リクエストごとにデータを複製しない。
+/// 読み取り専用のデータは `EngineSnapshot` にまとめて丸ごと差し替え、
+/// 書き込みが必要なセッションと統計は内部可変なストアに持つ。
+/// 分析結果は `AnalysisStore` に保存する（既定はメモリ内）。
 #[derive(Clone)]
 pub struct AIEngine {
-    characters: HashMap<String, Character>,
-    nlp: NLPProcessor,
-    wisdom_database: Vec<String>,
+    snapshot: Arc<RwLock<Arc<EngineSnapshot>>>,
+    sessions: Arc<SessionStore>,
+    stats: Arc<EngineStats>,
+    store: Arc<dyn AnalysisStore>,
+    similarity: Arc<SimilarityIndex>,
+    cluster_jobs: Arc<ClusterJobs>,
+    moderator: Arc<Moderator>,
+    redactor: Arc<Redactor>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ChatResponse {
     pub character: String,
     pub response: String,
     pub emotion: String,
     pub confidence: f32,
+    /// メッセージが質問だったときの種類
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question_type: Option<QuestionType>,
+    /// ソクラテス式対話モードの進み具合
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socratic: Option<SocraticTurn>,
+    /// メッセージのモデレーション結果（メッセージが通り `context` で止めたときは
+    /// `context` の結果で、位置もそのテキストの中のもの）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<ModerationVerdict>,
     pub timestamp: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PhilosophyAnalysis {
     pub id: String,
     pub text: String,
     pub themes: Vec<String>,
     pub sentiment: String,
     pub complexity: f32,
+    /// 確信度（0〜10）
+    pub conviction_level: u8,
+    /// 哲学の学派の傾き（割合の高い順）
+    pub schools: Vec<SchoolScore>,
+    /// 主張・前提・反論・結論の構造
+    pub argument: ArgumentStructure,
     pub recommendations: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    #[serde(default)]
+    pub moderation: ModerationVerdict,
+}
+
+/// `/v2/analyze` が返す詳細な分析結果
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PhilosophyAnalysisV2 {
+    pub id: String,
+    pub text: String,
+    pub themes: Vec<ThemeScore>,
+    pub sentiment: SentimentBreakdown,
+    pub complexity: f32,
+    pub readability: f32,
+    pub keywords: Vec<String>,
+    /// 保存済みの古い分析には無いので、読み込み時は中立の値になる
+    #[serde(default)]
+    pub conviction: ConvictionScore,
+    #[serde(default)]
+    pub schools: Vec<SchoolScore>,
+    #[serde(default)]
+    pub argument: ArgumentStructure,
+    /// 誤謬の疑い（説明は `recommendations` にも入る）
+    #[serde(default)]
+    pub fallacies: Vec<Fallacy>,
+    pub recommendations: Vec<String>,
+    /// 要約を求められたときだけ付く
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// テキストの言語（`ja` / `en` / `und`、古い分析では空）
+    #[serde(default)]
+    pub language: String,
+    /// 分析を求めたキャラクター
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub character: Option<String>,
+    /// 分析が属する会話のセッションID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// 自傷の兆候があれば `recommendations` は支援的な返答だけになる
+    #[serde(default)]
+    pub moderation: ModerationVerdict,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 分析の付加的な指定
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeOptions {
+    /// 誤謬の説明に使うキャラクター
+    pub character: Option<String>,
+    /// 要約で残す文の割合（`None` なら要約しない）
+    pub summary_ratio: Option<f32>,
+    /// 分析を会話に結びつけるセッションID
+    pub session_id: Option<String>,
+    /// 入力の検証でかけたモデレーションの結果（無ければ分析の中でかける）
+    pub moderation: Option<ModerationVerdict>,
+}
+
+impl From<PhilosophyAnalysisV2> for PhilosophyAnalysis {
+    fn from(analysis: PhilosophyAnalysisV2) -> Self {
+        let mut themes: Vec<String> = analysis.themes.into_iter().map(|t| t.theme).collect();
+        if themes.is_empty() {
+            themes.push("general".to_string());
+        }
+
+        PhilosophyAnalysis {
+            id: analysis.id,
+            text: analysis.text,
+            themes,
+            sentiment: analysis.sentiment.label,
+            complexity: analysis.complexity,
+            conviction_level: analysis.conviction.level,
+            schools: analysis.schools,
+            argument: analysis.argument,
+            recommendations: analysis.recommendations,
+            summary: analysis.summary,
+            moderation: analysis.moderation,
+        }
+    }
 }
 
 impl AIEngine {
@@ -106,26 +237,239 @@ impl AIEngine {
             "多様性を受け入れることで、世界はより美しくなる".to_string(),
         ];
 
-        AIEngine {
+        AIEngine::from_snapshot(EngineSnapshot {
             characters,
             nlp: NLPProcessor::new(),
             wisdom_database,
+        })
+    }
+
+    pub fn from_snapshot(snapshot: EngineSnapshot) -> Self {
+        let similarity = SimilarityIndex::in_memory();
+        similarity.set_wisdom(&snapshot.wisdom_database);
+        AIEngine {
+            snapshot: Arc::new(RwLock::new(Arc::new(snapshot))),
+            sessions: Arc::new(SessionStore::default()),
+            stats: Arc::new(EngineStats::default()),
+            store: Arc::new(MemoryStore::default()),
+            similarity: Arc::new(similarity),
+            cluster_jobs: Arc::new(ClusterJobs::default()),
+            moderator: Moderator::shared(),
+            redactor: Redactor::shared(),
+        }
+    }
+
+    /// 分析結果の保存先を差し替える
+    pub fn with_store(mut self, store: Arc<dyn AnalysisStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// モデレーションの設定を差し替える
+    pub fn with_moderator(mut self, moderator: Moderator) -> Self {
+        self.moderator = Arc::new(moderator);
+        self
+    }
+
+    /// 個人情報の伏せ字の設定を差し替える（ログと共有する）
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// 類似検索の索引を差し替える（知恵は今のデータから入れ直す）
+    pub fn with_similarity_index(mut self, index: SimilarityIndex) -> Self {
+        index.set_wisdom(&self.snapshot().wisdom_database);
+        self.similarity = Arc::new(index);
+        self
+    }
+
+    /// 現在のデータスナップショット（処理中に差し替えられても影響を受けない）
+    pub fn snapshot(&self) -> Arc<EngineSnapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// データを丸ごと差し替える。処理中のリクエストは古いスナップショットを使い切る。
+    pub fn replace_snapshot(&self, snapshot: EngineSnapshot) {
+        self.similarity.set_wisdom(&snapshot.wisdom_database);
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+    }
+
+    /// キャラクターを追加するか置き換える。新しく追加したら `true`。
+    pub fn upsert_character(&self, id: &str, character: Character) -> bool {
+        self.update_snapshot(|snapshot| snapshot.characters.insert(id.to_string(), character).is_none())
+    }
+
+    /// キャラクターを取り除く。いなければ `false`。
+    pub fn remove_character(&self, id: &str) -> bool {
+        self.update_snapshot(|snapshot| snapshot.characters.remove(id).is_some())
+    }
+
+    // 書き込みロックを持ったまま今のスナップショットを写して変え、差し替える
+    fn update_snapshot<R>(&self, update: impl FnOnce(&mut EngineSnapshot) -> R) -> R {
+        let mut current = self.snapshot.write().unwrap();
+        let mut snapshot = EngineSnapshot::clone(&current);
+        let result = update(&mut snapshot);
+        *current = Arc::new(snapshot);
+        result
+    }
+
+    pub fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// チャットや分析の前に、テキストをモデレーションにかける
+    pub fn moderate(&self, text: &str) -> ModerationVerdict {
+        self.moderator.moderate(text)
+    }
+
+    /// モデレーションで止めたメッセージへの定型の返答。
+    /// 自傷の兆候なら支援的な返答にし、進行中のソクラテス式対話は進めない。
+    pub fn moderated_reply(&self, session_id: Option<&str>, character_name: &str, message: &str, verdict: ModerationVerdict) -> ChatResponse {
+        let snapshot = self.snapshot();
+        let character = snapshot.characters.get(character_name);
+        let (response, emotion) = match verdict.action {
+            ModerationAction::Crisis => {
+                let english = snapshot.nlp.detect_language(message) == "en";
+                (moderation::crisis_response(character, english), "caring")
+            }
+            _ => (moderation::blocked_response(character), "concerned"),
+        };
+        if let Some(session_id) = session_id {
+            self.record_exchange(session_id, character_name, message, &response);
+        }
+
+        ChatResponse {
+            character: character_name.to_string(),
+            response,
+            emotion: emotion.to_string(),
+            confidence: 1.0,
+            question_type: None,
+            socratic: None,
+            moderation: Some(verdict),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// セッションIDがあれば、やり取りを履歴に残してからチャットする
+    pub async fn chat_in_session(&self, session_id: Option<&str>, character_name: &str, message: &str, context: Option<&str>) -> ChatResponse {
+        let response = self.chat_with_character(character_name, message, context).await;
+        if let Some(session_id) = session_id {
+            self.record_exchange(session_id, &response.character, message, &response.response);
+        }
+        response
+    }
+
+    // 設定があればユーザーの発言の個人情報を伏せてから履歴に残す。
+    // キャラクターの返答は伏せない（相談窓口の電話番号などを [PHONE] にしない）
+    fn record_exchange(&self, session_id: &str, character: &str, message: &str, reply: &str) {
+        if self.redactor.applies_to_sessions() {
+            self.sessions.record_exchange(session_id, character, &self.redactor.redact(message), reply);
+        } else {
+            self.sessions.record_exchange(session_id, character, message, reply);
+        }
+    }
+
+    /// ソクラテス式対話モード。キャラクターは答えずに段階ごとの問いを返し、
+    /// 最後の段階の答えを受け取ったらユーザーの考えのまとめを返す。
+    /// 対話が終わった後のメッセージは新しい対話の主張として扱う。
+    pub async fn socratic_in_session(&self, session_id: &str, character_name: &str, message: &str) -> ChatResponse {
+        // 対話の状態もセッションに残るので、伏せるなら最初に伏せる
+        let redacted;
+        let message = if self.redactor.applies_to_sessions() {
+            redacted = self.redactor.redact(message);
+            redacted.as_str()
+        } else {
+            message
+        };
+        let snapshot = self.snapshot();
+        let Some(character) = snapshot.characters.get(character_name) else {
+            return self.chat_with_character(character_name, message, None).await;
+        };
+        let sentiment = snapshot.nlp.analyze_sentiment(message);
+        self.stats.record_chat(character_name, &sentiment);
+
+        let ongoing = self.sessions.get(session_id)
+            .filter(|session| session.character == character_name)
+            .and_then(|session| session.dialogue)
+            .filter(|dialogue| !dialogue.is_finished());
+
+        let (dialogue, response, summary) = match ongoing {
+            None => {
+                let themes = self.dialogue_themes(&snapshot, message);
+                let topic = question::content_words(message).into_iter().next()
+                    .or_else(|| themes.first().cloned())
+                    .unwrap_or_else(|| "その考え".to_string());
+                let dialogue = SocraticDialogue::start(message, topic, snapshot.nlp.conviction(message).level);
+                let response = dialogue.next_question(character, &themes, None, false);
+                (dialogue, response, None)
+            }
+            Some(mut dialogue) => {
+                let advanced = dialogue.record_answer(message);
+                let themes = self.dialogue_themes(&snapshot, &dialogue.user_text());
+                if dialogue.is_finished() {
+                    let summary = dialogue.summary(themes, snapshot.nlp.conviction(message).level);
+                    let response = socratic::render_summary(character, &summary);
+                    (dialogue, response, Some(summary))
+                } else {
+                    let focus = question::content_words(message).into_iter().next();
+                    let response = dialogue.next_question(character, &themes, focus.as_deref(), !advanced);
+                    (dialogue, response, None)
+                }
+            }
+        };
+
+        let turn = dialogue.turn(summary);
+        let emotion = if dialogue.is_finished() { "contemplative" } else { "curious" };
+        self.sessions.record_exchange(session_id, character_name, message, &response);
+        self.sessions.set_dialogue(session_id, Some(dialogue));
+
+        ChatResponse {
+            character: character_name.to_string(),
+            response,
+            emotion: emotion.to_string(),
+            confidence: 0.9,
+            question_type: None,
+            socratic: Some(turn),
+            moderation: None,
+            timestamp: Utc::now(),
         }
     }
 
-    pub async fn chat_with_character(&self, character_name: &str, message: &str, context: Option<&str>) -> ChatResponse {
-        let character = self.characters.get(character_name);
+    // 対話全体のテーマ（多い順、無ければ空）
+    fn dialogue_themes(&self, snapshot: &EngineSnapshot, text: &str) -> Vec<String> {
+        snapshot.nlp.theme_scores(text).into_iter().map(|t| t.theme).collect()
+    }
+
+    pub async fn chat_with_character(&self, character_name: &str, message: &str, _context: Option<&str>) -> ChatResponse {
+        let snapshot = self.snapshot();
+        let character = snapshot.characters.get(character_name);
         
         if let Some(char) = character {
-            let sentiment = self.nlp.analyze_sentiment(message);
-            let response = self.generate_character_response(char, message, &sentiment);
-            let emotion = self.determine_emotion(&sentiment, char);
+            let sentiment = snapshot.nlp.analyze_sentiment(message);
+            let question = question::detect(message);
+            let response = match &question {
+                Some(question) => self.answer_question(&snapshot, char, message, question, &sentiment),
+                None => self.generate_character_response(char, message, &sentiment),
+            };
+            let emotion = match &question {
+                Some(_) if sentiment == "neutral" => "curious".to_string(),
+                _ => self.determine_emotion(&sentiment, char),
+            };
+            self.stats.record_chat(character_name, &sentiment);
             
             ChatResponse {
                 character: character_name.to_string(),
                 response,
                 emotion,
                 confidence: 0.85 + (rand::random::<f32>() * 0.1),
+                question_type: question.map(|q| q.kind),
+                socratic: None,
+                moderation: None,
                 timestamp: Utc::now(),
             }
         } else {
@@ -134,37 +478,353 @@ impl AIEngine {
                 response: "そのキャラクターは見つからないっぺ...🤖".to_string(),
                 emotion: "confused".to_string(),
                 confidence: 0.0,
+                question_type: None,
+                socratic: None,
+                moderation: None,
                 timestamp: Utc::now(),
             }
         }
     }
 
     pub async fn analyze_philosophy(&self, text: &str) -> PhilosophyAnalysis {
-        let themes = self.nlp.extract_themes(text);
-        let sentiment = self.nlp.analyze_sentiment(text);
+        self.analyze_philosophy_v2(text).await.into()
+    }
+
+    pub async fn analyze_philosophy_v2(&self, text: &str) -> PhilosophyAnalysisV2 {
+        self.analyze_text(text, &AnalyzeOptions::default())
+    }
+
+    /// 分析して保存する。保存できなかったとき（と分析のタスクが落ちたとき）はエラーにし、
+    /// 取り出せない `id` は返さない。
+    pub async fn analyze_and_store(&self, text: &str, user_id: Option<&str>, options: AnalyzeOptions) -> Result<PhilosophyAnalysisV2, StoreError> {
+        let engine = self.clone();
+        let text = text.to_string();
+        let user_id = user_id.map(str::to_string);
+        let span = tracing::Span::current();
+        let task = tokio::task::spawn_blocking(move || span.in_scope(|| {
+            let analysis = engine.analyze_text(&text, &options);
+            engine.store_analysis(&analysis, user_id.as_deref()).map(|()| analysis)
+        }));
+        task.await.map_err(|e| StoreError::Backend(e.to_string()))?
+    }
+
+    /// 分析結果を保存する同期処理（`spawn_blocking` かスレッドプールから呼ぶ）。
+    /// 類似検索の索引に入れられなくても保存できていれば成功にする
+    pub fn store_analysis(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> Result<(), StoreError> {
+        self.store.save(analysis, user_id)?;
+        if let Err(e) = self.similarity.insert(&analysis.id, &analysis.text, user_id) {
+            tracing::warn!(analysis_id = %analysis.id, error = %e, "failed to index analysis");
+        }
+        Ok(())
+    }
+
+    /// 分析を取り出す。`owner` を指定すると、その持ち主の分析でなければ `None`。
+    pub async fn find_analysis(&self, id: &str, owner: Option<&str>) -> Result<Option<PhilosophyAnalysisV2>, StoreError> {
+        let id = id.to_string();
+        let owner = owner.map(str::to_string);
+        self.with_blocking_store(move |store| Ok(store.get(&id)?
+            .filter(|(_, user_id)| owner.is_none() || *user_id == owner)
+            .map(|(analysis, _)| analysis))).await
+    }
+
+    pub async fn list_analyses(&self, query: AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+        self.with_blocking_store(move |store| store.list(&query)).await
+    }
+
+    /// 分析を削除する。`owner` を指定すると、その持ち主の分析でなければ削除せず `false`。
+    pub async fn delete_analysis(&self, id: &str, owner: Option<&str>) -> Result<bool, StoreError> {
+        let id = id.to_string();
+        let owner = owner.map(str::to_string);
+        let similarity = self.similarity.clone();
+        self.with_blocking_store(move |store| {
+            if owner.is_some() && store.get(&id)?.is_some_and(|(_, user_id)| user_id != owner) {
+                return Ok(false);
+            }
+            let deleted = store.delete(&id)?;
+            if let Err(e) = similarity.remove(&id) {
+                tracing::warn!(analysis_id = %id, error = %e, "failed to unindex analysis");
+            }
+            Ok(deleted)
+        }).await
+    }
+
+    /// `text` に近い分析と知恵をコサイン類似度の高い順に返す。
+    ///
+    /// `owner` を指定すると、分析はその持ち主のものだけを返す。
+    /// 索引には削除済みの分析が残っていることがある（ユーザー単位の削除など）ので、
+    /// ストアに無い分析は結果から外し、索引からも取り除く。
+    pub async fn find_similar(&self, text: &str, kind: Option<DocumentKind>, limit: usize, exclude: Option<&str>, owner: Option<&str>) -> Result<Vec<SimilarItem>, StoreError> {
+        let similarity = self.similarity.clone();
+        let text = text.to_string();
+        let exclude = exclude.map(str::to_string);
+        let owner = owner.map(str::to_string);
+        self.with_blocking_store(move |store| {
+            let mut results = Vec::new();
+            for item in similarity.search(&text, kind, limit * 2, exclude.as_deref(), owner.as_deref()) {
+                if item.kind == DocumentKind::Analysis && store.get(&item.id)?.is_none() {
+                    if let Err(e) = similarity.remove(&item.id) {
+                        tracing::warn!(analysis_id = %item.id, error = %e, "failed to unindex analysis");
+                    }
+                    continue;
+                }
+                results.push(item);
+                if results.len() == limit {
+                    break;
+                }
+            }
+            Ok(results)
+        }).await
+    }
+
+    /// ストアの分析をすべて索引に入れ直し、件数を返す（索引ファイルが無いときの初回用）
+    pub async fn rebuild_similarity_index(&self) -> Result<usize, StoreError> {
+        let similarity = self.similarity.clone();
+        self.with_blocking_store(move |store| {
+            let mut indexed = 0;
+            loop {
+                let query = AnalysisQuery { limit: Some(MAX_PAGE_SIZE), offset: Some(indexed), ..AnalysisQuery::default() };
+                let (page, _) = store.list(&query)?;
+                if page.is_empty() {
+                    return Ok(indexed);
+                }
+                for analysis in &page {
+                    // 一覧には持ち主が載らないので1件ずつ引く
+                    let owner = store.get(&analysis.id)?.and_then(|(_, user_id)| user_id);
+                    similarity.insert(&analysis.id, &analysis.text, owner.as_deref())?;
+                }
+                indexed += page.len();
+            }
+        }).await
+    }
+
+    /// クラスタリングをバックグラウンドで始め、実行中のジョブを返す。
+    /// 上限（`cluster::MAX_RUNNING_JOBS`）まで走っていれば始めずに `None`
+    pub fn start_clustering(&self, source: ClusterSource, k: Option<usize>) -> Option<ClusterJob> {
+        let job = self.cluster_jobs.start()?;
+        let engine = self.clone();
+        let id = job.id.clone();
+        // ジョブのログも始めたリクエストのスパンに紐付ける
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || span.in_scope(|| {
+            let outcome = engine.run_clustering(source, k).map_err(|e| e.to_string());
+            if let Err(e) = &outcome {
+                tracing::error!(job_id = %id, error = %e, "clustering job failed");
+            }
+            engine.cluster_jobs.finish(&id, outcome);
+        }));
+        Some(job)
+    }
+
+    pub fn cluster_job(&self, id: &str) -> Option<ClusterJob> {
+        self.cluster_jobs.get(id)
+    }
+
+    // 文書を集めて語を取り出し、クラスタリングする（同期処理）
+    fn run_clustering(&self, source: ClusterSource, k: Option<usize>) -> Result<ClusterResult, StoreError> {
+        let texts: Vec<(Option<String>, String)> = match source {
+            ClusterSource::Texts(texts) => texts.into_iter().map(|text| (None, text)).collect(),
+            ClusterSource::Window { since, until, user_id } => {
+                let query = AnalysisQuery { user_id, since: Some(since), until, ..AnalysisQuery::default() };
+                let (analyses, _) = self.collect_analyses(query, cluster::MAX_DOCUMENTS)?;
+                analyses.into_iter().map(|analysis| (Some(analysis.id), analysis.text)).collect()
+            }
+        };
+
+        let snapshot = self.snapshot();
+        let documents: Vec<Document> = texts.into_iter()
+            .map(|(id, text)| {
+                let terms = snapshot.nlp.keyword_terms_with(&text, &snapshot.nlp.scan(&text));
+                Document { id, text, terms }
+            })
+            .collect();
+        Ok(cluster::cluster(&documents, k))
+    }
+
+    /// 保存済みの分析を、感情とテーマの区間ごとの推移にまとめる
+    pub async fn trends(&self, spec: TrendSpec) -> Result<TrendReport, StoreError> {
+        let engine = self.clone();
+        tokio::task::spawn_blocking(move || engine.run_trends(spec))
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+    }
+
+    fn run_trends(&self, spec: TrendSpec) -> Result<TrendReport, StoreError> {
+        let query = AnalysisQuery {
+            user_id: spec.user_id.clone(),
+            since: Some(spec.since),
+            until: Some(spec.until),
+            ..AnalysisQuery::default()
+        };
+        let (analyses, total) = self.collect_analyses(query, trends::MAX_ANALYSES)?;
+
+        let snapshot = self.snapshot();
+        // 系列の名前が無い分析（キャラクターやセッションの指定が無いもの）は数えない
+        let samples: Vec<Sample> = analyses.into_iter()
+            .filter_map(|analysis| {
+                let group = match spec.group_by {
+                    GroupBy::All => Some("all".to_string()),
+                    GroupBy::Character => analysis.character,
+                    GroupBy::Session => analysis.session_id,
+                    GroupBy::Language if analysis.language.is_empty() => {
+                        Some(snapshot.nlp.detect_language(&analysis.text).to_string())
+                    }
+                    GroupBy::Language => Some(analysis.language),
+                }?;
+                Some(Sample {
+                    timestamp: analysis.timestamp,
+                    group,
+                    sentiment: analysis.sentiment.label,
+                    score: analysis.sentiment.score,
+                    themes: analysis.themes.into_iter().map(|t| t.theme).collect(),
+                })
+            })
+            .collect();
+
+        let truncated = total > trends::MAX_ANALYSES;
+        let mut series = trends::aggregate(&samples, &spec);
+        if truncated {
+            // 新しい順に上限までしか読んでいないので、古い区間ほど件数が欠けて
+            // 新しい区間で増えたように見える。偽の変化点を返さないように外す
+            for point in series.iter_mut().flat_map(|s| s.points.iter_mut()) {
+                point.change_points.clear();
+            }
+        }
+
+        Ok(TrendReport {
+            series,
+            interval: spec.interval,
+            group_by: spec.group_by,
+            since: spec.interval.floor(spec.since),
+            until: spec.until,
+            window: spec.window,
+            analyses: samples.len(),
+            truncated,
+        })
+    }
+
+    // 条件に合う分析を新しい順に `max` 件まで集め、条件に合う総件数と返す
+    fn collect_analyses(&self, query: AnalysisQuery, max: usize) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+        let mut analyses = Vec::new();
+        let mut total = 0;
+        while analyses.len() < max {
+            let page = AnalysisQuery { limit: Some(MAX_PAGE_SIZE), offset: Some(analyses.len()), ..query.clone() };
+            let (items, count) = self.store.list(&page)?;
+            total = count;
+            if items.is_empty() {
+                break;
+            }
+            analyses.extend(items);
+        }
+        analyses.truncate(max);
+        Ok((analyses, total))
+    }
+
+    pub fn similarity_index_len(&self) -> usize {
+        self.similarity.len()
+    }
+
+    /// ユーザーの分析をすべて削除し、削除件数を返す
+    pub async fn delete_user_analyses(&self, user_id: &str) -> Result<usize, StoreError> {
+        let user_id = user_id.to_string();
+        self.with_blocking_store(move |store| store.delete_by_user(&user_id)).await
+    }
+
+    async fn with_blocking_store<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn AnalysisStore) -> Result<T, StoreError> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || f(store.as_ref()))
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+    }
+
+    /// 分析の本体。CPUだけを使う同期処理なので、バッチではスレッドプールから直接呼ぶ。
+    pub fn analyze_text(&self, text: &str, options: &AnalyzeOptions) -> PhilosophyAnalysisV2 {
+        let verdict = options.moderation.clone().unwrap_or_else(|| self.moderator.moderate(text));
+        // 保存する分析を伏せるなら、伏せたテキストを分析して位置や抜粋もそろえる
+        let redacted;
+        let text = if self.redactor.applies_to_analyses() {
+            redacted = self.redactor.redact(text);
+            redacted.as_str()
+        } else {
+            text
+        };
+        let snapshot = self.snapshot();
+        let matches = snapshot.nlp.scan(text);
+        let themes = snapshot.nlp.theme_scores_with(&matches);
+        let sentiment = snapshot.nlp.sentiment_breakdown_with(text, &matches);
         let complexity = self.calculate_complexity(text);
-        let recommendations = self.generate_recommendations(&themes, &sentiment);
+        let theme_names = if themes.is_empty() {
+            vec!["general".to_string()]
+        } else {
+            themes.iter().map(|t| t.theme.clone()).collect()
+        };
+        let character = options.character.as_deref().and_then(|id| snapshot.characters.get(id));
+        let fallacies = snapshot.nlp.fallacies_with(text, &matches, character);
+        let recommendations = if verdict.action == ModerationAction::Crisis {
+            vec![moderation::crisis_response(character, snapshot.nlp.detect_language(text) == "en")]
+        } else {
+            let mut recommendations = self.generate_recommendations(&theme_names, &sentiment.label);
+            recommendations.extend(fallacies.iter().map(|f| f.explanation.clone()));
+            recommendations
+        };
+        self.stats.record_analysis(&sentiment.label);
 
-        PhilosophyAnalysis {
+        PhilosophyAnalysisV2 {
             id: Uuid::new_v4().to_string(),
             text: text.to_string(),
             themes,
             sentiment,
             complexity,
+            readability: snapshot.nlp.calculate_readability(text),
+            keywords: snapshot.nlp.extract_keywords_with(text, &matches),
+            conviction: snapshot.nlp.conviction_with(text, &matches),
+            schools: snapshot.nlp.schools_with(text, &matches),
+            argument: snapshot.nlp.argument_structure_with(text, &matches),
+            fallacies,
             recommendations,
+            summary: options.summary_ratio.map(|ratio| snapshot.nlp.summarize_with(text, &matches, ratio)),
+            language: snapshot.nlp.detect_language(text).to_string(),
+            character: character.and(options.character.clone()),
+            session_id: options.session_id.clone(),
+            moderation: verdict,
+            timestamp: Utc::now(),
         }
     }
 
+    /// テキストの要約だけを求める
+    pub fn summarize(&self, text: &str, compression_ratio: f32) -> Summary {
+        self.snapshot().nlp.summarize(text, compression_ratio)
+    }
+
+    /// テキストの確信度だけを求める
+    pub fn estimate_conviction(&self, text: &str) -> ConvictionScore {
+        self.snapshot().nlp.conviction(text)
+    }
+
+    pub fn has_character(&self, character_id: &str) -> bool {
+        self.snapshot().characters.contains_key(character_id)
+    }
+
+    pub fn character_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.snapshot().characters.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
     pub async fn get_character_personalities(&self) -> HashMap<String, CharacterPersonality> {
-        self.characters.iter()
+        self.snapshot().characters.iter()
             .map(|(name, char)| (name.clone(), char.personality.clone()))
             .collect()
     }
 
     pub async fn generate_wisdom(&self, theme: &str) -> String {
         // テーマに基づいて知恵を生成
+        let snapshot = self.snapshot();
         let theme_lower = theme.to_lowercase();
-        let relevant_wisdom: Vec<&String> = self.wisdom_database.iter()
+        let relevant_wisdom: Vec<&String> = snapshot.wisdom_database.iter()
             .filter(|wisdom| {
                 wisdom.to_lowercase().contains(&theme_lower) || 
                 theme_lower.contains("life") ||
@@ -173,17 +833,19 @@ impl AIEngine {
             })
             .collect();
 
+        self.stats.record_wisdom(!relevant_wisdom.is_empty());
+
         if !relevant_wisdom.is_empty() {
             let index = (rand::random::<f32>() * relevant_wisdom.len() as f32) as usize;
             relevant_wisdom[index].clone()
         } else {
             // デフォルトの知恵
-            let index = (rand::random::<f32>() * self.wisdom_database.len() as f32) as usize;
-            self.wisdom_database[index].clone()
+            let index = (rand::random::<f32>() * snapshot.wisdom_database.len() as f32) as usize;
+            snapshot.wisdom_database[index].clone()
         }
     }
 
-    fn generate_character_response(&self, character: &Character, message: &str, sentiment: &str) -> String {
+    fn generate_character_response(&self, character: &Character, _message: &str, sentiment: &str) -> String {
         let base_responses = &character.personality.response_patterns;
         let sentiment_modifier = match sentiment {
             "positive" => "素晴らしい考えだね！",
@@ -197,6 +859,35 @@ impl AIEngine {
         format!("{} {} {}", character.emoji, sentiment_modifier, base_response)
     }
 
+    /// 質問に関係する知恵とおすすめで答える。関係する知恵が無ければ問い返す。
+    fn answer_question(&self, snapshot: &EngineSnapshot, character: &Character, message: &str, question: &Question, sentiment: &str) -> String {
+        let matches = snapshot.nlp.scan(message);
+        let themes: Vec<String> = snapshot.nlp.theme_scores_with(&matches).into_iter().map(|t| t.theme).collect();
+        let words = question::content_words(&question.sentence);
+
+        // 共通のテーマと、知恵の中に出てくる内容語の数で関連度を測る
+        let best = snapshot.wisdom_database.iter()
+            .map(|wisdom| {
+                let wisdom_themes = snapshot.nlp.theme_scores(wisdom);
+                let shared_themes = wisdom_themes.iter().filter(|t| themes.contains(&t.theme)).count();
+                let shared_words = words.iter().filter(|w| wisdom.to_lowercase().contains(w.as_str())).count();
+                (wisdom, 2 * shared_themes + shared_words)
+            })
+            .filter(|(_, score)| *score > 0)
+            .max_by_key(|(_, score)| *score)
+            .map(|(wisdom, _)| wisdom);
+
+        self.stats.record_wisdom(best.is_some());
+
+        match best {
+            Some(wisdom) => {
+                let recommendations = self.generate_recommendations(&themes, sentiment);
+                question::answer(character, question.kind, wisdom, recommendations.first().map(String::as_str))
+            }
+            None => question::counter_question(character, question.kind, words.first().map(String::as_str)),
+        }
+    }
+
     fn determine_emotion(&self, sentiment: &str, character: &Character) -> String {
         match sentiment {
             "positive" => "happy".to_string(),
@@ -239,3 +930,9 @@ impl AIEngine {
         recommendations
     }
 }
+
+impl Default for AIEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,412 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::ai_engine::AIEngine;
+use crate::character_ai::{Character, CharacterPersonality};
+use crate::cluster::{self, ClusterSource};
+use crate::error::ApiError;
+use crate::moderation::{ModerationAction, ModerationVerdict};
+use crate::similarity::{self, DocumentKind, SimilarItem};
+use crate::summary::DEFAULT_COMPRESSION_RATIO;
+use crate::trends::{self, GroupBy, Interval, TrendSpec};
+
+const MAX_SESSION_ID_LENGTH: usize = 128;
+const MAX_USER_ID_LENGTH: usize = 100;
+const MAX_CHARACTER_ID_LENGTH: usize = 64;
+const MAX_CHARACTER_NAME_LENGTH: usize = 100;
+
+/// チャットの進め方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatMode {
+    /// キャラクターが自由に返答する
+    #[default]
+    Free,
+    /// キャラクターが問いを重ねてユーザーの考えを導く（`session_id` が必要）
+    Socratic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChatRequest {
+    /// キャラクターID（`snowman` / `frog` / `fugu`）。省略時は設定のデフォルト
+    #[serde(default)]
+    pub character: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub context: Option<String>,
+    /// 会話履歴を残すためのセッションID
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub mode: ChatMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AnalyzeRequest {
+    pub text: String,
+    /// 保存する分析の持ち主（`DELETE /analyses?user_id=` でまとめて削除できる）。
+    /// 認証が有効なら、サービスと管理者以外は JWT の `sub` で置き換える
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// 誤謬の説明に使うキャラクターの口調。省略時は設定のデフォルト
+    #[serde(default)]
+    pub character: Option<String>,
+    /// `true` なら `summary` に抽出型の要約を付ける
+    #[serde(default)]
+    pub summarize: bool,
+    /// 要約で残す文の割合（0 より大きく 1 以下、省略時は 0.3）
+    #[serde(default)]
+    pub compression_ratio: Option<f32>,
+    /// 分析を会話に結びつけるセッションID（会話ごとの推移に使う）
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SummarizeRequest {
+    pub text: String,
+    /// 残す文の割合（0 より大きく 1 以下、省略時は 0.3）
+    #[serde(default)]
+    pub compression_ratio: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ConvictionRequest {
+    pub text: String,
+}
+
+/// `text` か `analysis_id` のどちらか一方を指定する
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarRequest {
+    #[serde(default)]
+    pub text: Option<String>,
+    /// 保存済みの分析に近いものを探す（その分析自体は結果に含めない）
+    #[serde(default)]
+    pub analysis_id: Option<String>,
+    /// 対象を分析か知恵に絞る（省略時は両方）
+    #[serde(default)]
+    pub kind: Option<DocumentKind>,
+    /// 返す件数（既定 5、最大 20）
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarResponse {
+    pub results: Vec<SimilarItem>,
+}
+
+/// `texts` を渡すか、保存済みの分析の時間窓（`since` / `until`）を指定する
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ClusterRequest {
+    #[serde(default)]
+    pub texts: Option<Vec<String>>,
+    /// 省略時は7日前から
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// クラスタ数（1〜20、省略時は件数から決める）
+    #[serde(default)]
+    pub k: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserAnalysesQuery {
+    /// 削除するユーザーのID
+    pub user_id: Option<String>,
+}
+
+/// 推移の集計条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TrendQuery {
+    /// 集計の区間（`day` / `week`、既定 `day`）
+    pub interval: Option<Interval>,
+    /// 系列の分け方（`all` / `character` / `session` / `language`、既定 `all`）
+    pub group_by: Option<GroupBy>,
+    /// 省略時は日なら30日前、週なら12週前から
+    pub since: Option<DateTime<Utc>>,
+    /// 省略時は現在まで
+    pub until: Option<DateTime<Utc>>,
+    /// 集計する分析の持ち主（管理者とサービス以外は自分に固定される）
+    pub user_id: Option<String>,
+    /// 移動平均の区間数（1〜30、既定は日なら7、週なら4）
+    pub window: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeletedResponse {
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WisdomQuery {
+    /// 知恵のテーマ（省略時は `life`）
+    pub theme: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WisdomResponse {
+    pub theme: String,
+    pub wisdom: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    pub service: String,
+    pub version: String,
+}
+
+impl ChatRequest {
+    pub fn validate(&self, ai_engine: &AIEngine, max_length: usize) -> Result<(), ApiError> {
+        require_text("message", &self.message, max_length)?;
+        if let Some(context) = &self.context {
+            check_length("context", context, max_length)?;
+        }
+        match &self.session_id {
+            Some(session_id) => require_text("session_id", session_id, MAX_SESSION_ID_LENGTH)?,
+            None if self.mode == ChatMode::Socratic => return Err(ApiError::MissingField("session_id")),
+            None => {}
+        }
+
+        validate_character(ai_engine, self.character.as_deref())
+    }
+}
+
+impl AnalyzeRequest {
+    /// 入力を確かめ、テキストのモデレーション結果を返す（分析でもこの結果を使う）
+    pub fn validate(&self, ai_engine: &AIEngine, max_length: usize) -> Result<ModerationVerdict, ApiError> {
+        require_text("text", &self.text, max_length)?;
+        validate_user_id(self.user_id.as_deref())?;
+        validate_compression_ratio(self.compression_ratio)?;
+        if let Some(session_id) = &self.session_id {
+            require_text("session_id", session_id, MAX_SESSION_ID_LENGTH)?;
+        }
+        validate_character(ai_engine, self.character.as_deref())?;
+
+        // 自傷の兆候は拒否せず、分析の中で支援的な返答に切り替える
+        let verdict = ai_engine.moderate(&self.text);
+        match verdict.action {
+            ModerationAction::Block => Err(ApiError::Blocked { field: "text", categories: verdict.categories }),
+            _ => Ok(verdict),
+        }
+    }
+
+    /// 要約を求められていれば、その圧縮率
+    pub fn summary_ratio(&self) -> Option<f32> {
+        self.summarize.then(|| self.compression_ratio.unwrap_or(DEFAULT_COMPRESSION_RATIO))
+    }
+}
+
+impl SummarizeRequest {
+    pub fn validate(&self, max_length: usize) -> Result<(), ApiError> {
+        require_text("text", &self.text, max_length)?;
+        validate_compression_ratio(self.compression_ratio)
+    }
+
+    pub fn compression_ratio(&self) -> f32 {
+        self.compression_ratio.unwrap_or(DEFAULT_COMPRESSION_RATIO)
+    }
+}
+
+impl ConvictionRequest {
+    pub fn validate(&self, max_length: usize) -> Result<(), ApiError> {
+        require_text("text", &self.text, max_length)
+    }
+}
+
+impl SimilarRequest {
+    pub fn validate(&self, max_length: usize) -> Result<(), ApiError> {
+        match (&self.text, &self.analysis_id) {
+            (Some(_), Some(_)) => Err(ApiError::InvalidBody("specify either `text` or `analysis_id`, not both".to_string())),
+            (Some(text), None) => require_text("text", text, max_length),
+            (None, Some(id)) => require_text("analysis_id", id, MAX_SESSION_ID_LENGTH),
+            (None, None) => Err(ApiError::MissingField("text")),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(similarity::DEFAULT_LIMIT).clamp(1, similarity::MAX_LIMIT)
+    }
+}
+
+impl ClusterRequest {
+    pub fn validate(&self, max_length: usize, max_texts: usize) -> Result<(), ApiError> {
+        if let Some(texts) = &self.texts {
+            if self.since.is_some() || self.until.is_some() {
+                return Err(ApiError::InvalidBody("specify either `texts` or `since`/`until`, not both".to_string()));
+            }
+            if texts.is_empty() {
+                return Err(ApiError::MissingField("texts"));
+            }
+            if texts.len() > max_texts {
+                return Err(ApiError::BatchTooLarge { max: max_texts });
+            }
+            for text in texts {
+                require_text("texts", text, max_length)?;
+            }
+        }
+        match self.k {
+            Some(k) if k == 0 || k > cluster::MAX_CLUSTERS => {
+                Err(ApiError::OutOfRange { field: "k", min: 0.0, max: cluster::MAX_CLUSTERS as f32 })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 時間窓は `owner` があればその人の分析だけを集める
+    pub fn source(&self, owner: Option<&str>) -> ClusterSource {
+        match &self.texts {
+            Some(texts) => ClusterSource::Texts(texts.clone()),
+            None => ClusterSource::Window {
+                since: self.since.unwrap_or_else(|| Utc::now() - Duration::days(cluster::DEFAULT_WINDOW_DAYS)),
+                until: self.until,
+                user_id: owner.map(str::to_string),
+            },
+        }
+    }
+}
+
+impl TrendQuery {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        validate_user_id(self.user_id.as_deref())?;
+        if let Some(window) = self.window {
+            if window == 0 || window > trends::MAX_WINDOW {
+                return Err(ApiError::OutOfRange { field: "window", min: 0.0, max: trends::MAX_WINDOW as f32 });
+            }
+        }
+        let (since, until) = self.range();
+        if since >= until {
+            return Err(ApiError::InvalidBody("`since` must be earlier than `until`".to_string()));
+        }
+        if self.interval.unwrap_or_default().buckets(since, until) > trends::MAX_BUCKETS {
+            return Err(ApiError::InvalidBody(format!(
+                "the range spans more than {} intervals; shorten it or use `interval=week`",
+                trends::MAX_BUCKETS
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn spec(&self) -> TrendSpec {
+        let interval = self.interval.unwrap_or_default();
+        let (since, until) = self.range();
+        TrendSpec {
+            interval,
+            group_by: self.group_by.unwrap_or_default(),
+            since,
+            until,
+            window: self.window.unwrap_or_else(|| interval.default_window()),
+            user_id: self.user_id.clone(),
+        }
+    }
+
+    fn range(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        let until = self.until.unwrap_or_else(Utc::now);
+        let interval = self.interval.unwrap_or_default();
+        (self.since.unwrap_or_else(|| until - interval.default_range()), until)
+    }
+}
+
+/// キャラクターの追加・置き換え（`PUT /characters/{id}`）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CharacterRequest {
+    pub name: String,
+    pub emoji: String,
+    /// `response_patterns` は1つ以上必要
+    pub personality: CharacterPersonality,
+}
+
+impl CharacterRequest {
+    pub fn validate(&self, id: &str, max_length: usize) -> Result<(), ApiError> {
+        validate_character_id(id)?;
+        require_text("name", &self.name, MAX_CHARACTER_NAME_LENGTH)?;
+        require_text("emoji", &self.emoji, MAX_CHARACTER_NAME_LENGTH)?;
+        require_text("personality.language_style", &self.personality.language_style, MAX_CHARACTER_NAME_LENGTH)?;
+        for trait_name in &self.personality.traits {
+            require_text("personality.traits", trait_name, MAX_CHARACTER_NAME_LENGTH)?;
+        }
+        if self.personality.response_patterns.is_empty() {
+            return Err(ApiError::MissingField("personality.response_patterns"));
+        }
+        for pattern in &self.personality.response_patterns {
+            require_text("personality.response_patterns", pattern, max_length)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_character(self) -> Character {
+        Character { name: self.name, emoji: self.emoji, personality: self.personality }
+    }
+}
+
+/// キャラクターIDは英数字・`-`・`_` の64文字まで
+fn validate_character_id(id: &str) -> Result<(), ApiError> {
+    if id.is_empty() || id.len() > MAX_CHARACTER_ID_LENGTH || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ApiError::InvalidBody(format!(
+            "a character id must be 1-{} ASCII letters, digits, `-` or `_`",
+            MAX_CHARACTER_ID_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+impl UserAnalysesQuery {
+    pub fn validate(&self) -> Result<&str, ApiError> {
+        let user_id = self.user_id.as_deref().unwrap_or("");
+        require_text("user_id", user_id, MAX_USER_ID_LENGTH)?;
+        Ok(user_id)
+    }
+}
+
+fn validate_compression_ratio(ratio: Option<f32>) -> Result<(), ApiError> {
+    match ratio {
+        Some(ratio) if !(ratio > 0.0 && ratio <= 1.0) => Err(ApiError::OutOfRange { field: "compression_ratio", min: 0.0, max: 1.0 }),
+        _ => Ok(()),
+    }
+}
+
+fn validate_character(ai_engine: &AIEngine, character: Option<&str>) -> Result<(), ApiError> {
+    match character {
+        Some(character) if !ai_engine.has_character(character) => Err(ApiError::UnknownCharacter {
+            id: character.to_string(),
+            allowed: ai_engine.character_ids(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn validate_user_id(user_id: Option<&str>) -> Result<(), ApiError> {
+    match user_id {
+        Some(user_id) => require_text("user_id", user_id, MAX_USER_ID_LENGTH),
+        None => Ok(()),
+    }
+}
+
+impl WisdomQuery {
+    pub fn validate(&self, max_length: usize) -> Result<(), ApiError> {
+        match &self.theme {
+            Some(theme) => require_text("theme", theme, max_length),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn require_text(field: &'static str, value: &str, max_length: usize) -> Result<(), ApiError> {
+    if value.trim().is_empty() {
+        return Err(ApiError::MissingField(field));
+    }
+    check_length(field, value, max_length)
+}
+
+fn check_length(field: &'static str, value: &str, max_length: usize) -> Result<(), ApiError> {
+    if value.chars().count() > max_length {
+        return Err(ApiError::TooLong { field, max: max_length });
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::matcher::{char_offset, outermost, KeywordMatch, Vocabulary};
+use crate::nlp::NLPProcessor;
+
+/// 談話標識の働き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscourseMarker {
+    /// 後に続く部分が前提（なぜなら / because）
+    Premise,
+    /// 前の部分が前提になる後置の標識（〜ので、 / 〜から、）
+    PremiseSuffix,
+    /// 後に続く部分が結論（したがって / therefore）
+    Conclusion,
+    /// 後に続く部分が反論（しかし / however）
+    Counter,
+}
+
+const PREMISE: [&str; 10] = [
+    "なぜなら", "というのも", "その理由は", "例えば",
+    "because", "since", "for example", "for instance", "given that", "as shown by",
+];
+
+const PREMISE_SUFFIX: [&str; 3] = ["ので、", "から、", "ため、"];
+
+const CONCLUSION: [&str; 15] = [
+    "したがって", "従って", "よって", "ゆえに", "故に", "だから", "それゆえ", "つまり", "結論として",
+    "therefore", "thus", "hence", "consequently", "in conclusion", "as a result",
+];
+
+const COUNTER: [&str; 15] = [
+    "しかし", "しかしながら", "だが", "けれども", "ところが", "とはいえ", "一方で", "それでも",
+    "but", "however", "although", "on the other hand", "nevertheless", "even so", "whereas",
+];
+
+/// 論証の単位の役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentRole {
+    Claim,
+    Premise,
+    CounterClaim,
+    Conclusion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationKind {
+    Support,
+    Attack,
+}
+
+/// 論証の単位（文、または談話標識で区切った節）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ArgumentUnit {
+    pub id: usize,
+    pub role: ArgumentRole,
+    pub text: String,
+    /// 役割の決め手になった談話標識
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    /// 元テキストでの位置（文字単位、`end` は含まない）
+    pub start: usize,
+    pub end: usize,
+}
+
+/// `from` が `to` を支持または攻撃する
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ArgumentRelation {
+    pub from: usize,
+    pub to: usize,
+    pub kind: RelationKind,
+}
+
+/// 論証構造（単位をノード、支持・攻撃をエッジとするグラフ）
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ArgumentStructure {
+    pub units: Vec<ArgumentUnit>,
+    pub relations: Vec<ArgumentRelation>,
+}
+
+/// `KeywordMatcher` に登録する談話標識
+pub fn vocabulary() -> impl Iterator<Item = (&'static str, Vocabulary)> {
+    let lexicon = [
+        (&PREMISE[..], DiscourseMarker::Premise),
+        (&PREMISE_SUFFIX[..], DiscourseMarker::PremiseSuffix),
+        (&CONCLUSION[..], DiscourseMarker::Conclusion),
+        (&COUNTER[..], DiscourseMarker::Counter),
+    ];
+    lexicon.into_iter().flat_map(|(words, marker)| {
+        words.iter().map(move |word| (*word, Vocabulary::Discourse(marker)))
+    })
+}
+
+// 区切ったばかりの単位（バイト位置と、役割を決めた標識）
+struct Segment<'m> {
+    start: usize,
+    end: usize,
+    marker: Option<(&'m str, DiscourseMarker)>,
+}
+
+/// `scan` 済みの一致から論証構造を求める
+pub fn mine(text: &str, matches: &[KeywordMatch<'_>]) -> ArgumentStructure {
+    let segments = segment(text, matches);
+
+    let units: Vec<ArgumentUnit> = segments.iter()
+        .enumerate()
+        .map(|(id, segment)| ArgumentUnit {
+            id,
+            role: match segment.marker {
+                Some((_, DiscourseMarker::Premise | DiscourseMarker::PremiseSuffix)) => ArgumentRole::Premise,
+                Some((_, DiscourseMarker::Conclusion)) => ArgumentRole::Conclusion,
+                Some((_, DiscourseMarker::Counter)) => ArgumentRole::CounterClaim,
+                None => ArgumentRole::Claim,
+            },
+            text: text[segment.start..segment.end].trim().to_string(),
+            marker: segment.marker.map(|(keyword, _)| keyword.to_string()),
+            start: char_offset(text, segment.start),
+            end: char_offset(text, segment.end),
+        })
+        .collect();
+
+    let relations = relate(&units, &segments);
+    ArgumentStructure { units, relations }
+}
+
+/// 文に分け、さらに談話標識の位置で節に分ける
+fn segment<'m>(text: &str, matches: &[KeywordMatch<'m>]) -> Vec<Segment<'m>> {
+    // 日本語の接続詞は文頭か読点の後だけを標識として扱う（「〜だから、」の「だから」を除く）
+    let candidates = matches.iter()
+        .filter(|m| matches!(m.vocabulary, Vocabulary::Discourse(_)) && m.is_whole_word(text))
+        .filter(|m| {
+            m.keyword.is_ascii()
+                || matches!(m.vocabulary, Vocabulary::Discourse(DiscourseMarker::PremiseSuffix))
+                || is_clause_start(text[..m.start].trim_end().chars().next_back())
+        });
+    let markers: Vec<(&KeywordMatch<'m>, DiscourseMarker)> = outermost(candidates).into_iter()
+        .filter_map(|m| match m.vocabulary {
+            Vocabulary::Discourse(marker) => Some((m, *marker)),
+            _ => None,
+        })
+        .collect();
+
+    let mut segments = Vec::new();
+    for (start, end) in NLPProcessor::sentence_spans(text) {
+        let mut current = Segment { start, end, marker: None };
+
+        for (m, marker) in markers.iter().filter(|(m, _)| m.start >= start && m.end <= end) {
+            match marker {
+                // 「〜ので、」は標識までを前提として切り出す
+                DiscourseMarker::PremiseSuffix => {
+                    if current.marker.is_none() {
+                        current.marker = Some((m.keyword, *marker));
+                    }
+                    push_segment(&mut segments, text, Segment { end: m.end, ..current });
+                    current = Segment { start: m.end, end, marker: None };
+                }
+                _ => {
+                    if !text[current.start..m.start].trim().is_empty() {
+                        push_segment(&mut segments, text, Segment { end: m.start, ..current });
+                        current = Segment { start: m.start, end, marker: None };
+                    }
+                    if current.marker.is_none() {
+                        current.marker = Some((m.keyword, *marker));
+                    }
+                }
+            }
+        }
+        push_segment(&mut segments, text, current);
+    }
+    segments
+}
+
+fn push_segment<'m>(segments: &mut Vec<Segment<'m>>, text: &str, segment: Segment<'m>) {
+    if !text[segment.start..segment.end].trim().is_empty() {
+        segments.push(segment);
+    }
+}
+
+// 直前の文字（空白を除く）から見て、節の先頭か
+fn is_clause_start(previous: Option<char>) -> bool {
+    previous.is_none_or(|c| matches!(c, '。' | '.' | '!' | '?' | '！' | '？' | '、' | ',' | ';' | '；'))
+}
+
+/// 役割と並び順から支持・攻撃の関係を張る
+///
+/// - 前提は直前の主張・結論を支持する（「〜ので、」の前提と、先頭の前提は直後の単位を支持する）
+/// - 反論は直前の主張・結論を攻撃する
+/// - 結論は直前の主張（無ければ前提）に支持される
+fn relate(units: &[ArgumentUnit], segments: &[Segment<'_>]) -> Vec<ArgumentRelation> {
+    let mut relations = Vec::new();
+    let support = |from, to| ArgumentRelation { from, to, kind: RelationKind::Support };
+    let previous = |i: usize, roles: &[ArgumentRole]| units[..i].iter().rev().find(|u| roles.contains(&u.role)).map(|u| u.id);
+
+    for (i, unit) in units.iter().enumerate() {
+        match unit.role {
+            ArgumentRole::Premise => {
+                let suffix = matches!(segments[i].marker, Some((_, DiscourseMarker::PremiseSuffix)));
+                let target = if suffix {
+                    units.get(i + 1).map(|u| u.id)
+                } else {
+                    previous(i, &[ArgumentRole::Claim, ArgumentRole::Conclusion, ArgumentRole::CounterClaim])
+                        .or_else(|| units[i + 1..].iter().find(|u| u.role != ArgumentRole::Premise).map(|u| u.id))
+                };
+                if let Some(target) = target {
+                    relations.push(support(unit.id, target));
+                }
+            }
+            ArgumentRole::CounterClaim => {
+                if let Some(target) = previous(i, &[ArgumentRole::Claim, ArgumentRole::Conclusion]) {
+                    relations.push(ArgumentRelation { from: unit.id, to: target, kind: RelationKind::Attack });
+                }
+            }
+            ArgumentRole::Conclusion => {
+                let source = previous(i, &[ArgumentRole::Claim]).or_else(|| previous(i, &[ArgumentRole::Premise]));
+                if let Some(source) = source {
+                    relations.push(support(source, unit.id));
+                }
+            }
+            ArgumentRole::Claim => {}
+        }
+    }
+    relations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(structure: &ArgumentStructure) -> Vec<ArgumentRole> {
+        structure.units.iter().map(|unit| unit.role).collect()
+    }
+
+    fn edges(structure: &ArgumentStructure) -> Vec<(usize, usize, RelationKind)> {
+        structure.relations.iter().map(|r| (r.from, r.to, r.kind)).collect()
+    }
+
+    #[test]
+    fn premises_support_and_counters_attack_the_claim() {
+        let structure = NLPProcessor::new().argument_structure("自由は大切だ。なぜなら人は選ぶことで成長するからだ。しかし自由には責任が伴う。");
+        assert_eq!(roles(&structure), vec![ArgumentRole::Claim, ArgumentRole::Premise, ArgumentRole::CounterClaim]);
+        assert_eq!(edges(&structure), vec![(1, 0, RelationKind::Support), (2, 0, RelationKind::Attack)]);
+        assert_eq!(structure.units[1].marker.as_deref(), Some("なぜなら"));
+        assert_eq!(structure.units[0].start, 0);
+        assert_eq!(structure.units[0].end, 7);
+    }
+
+    #[test]
+    fn a_suffix_premise_supports_the_following_unit() {
+        let structure = NLPProcessor::new().argument_structure("雨が降ったので、試合は中止だ。");
+        assert_eq!(roles(&structure), vec![ArgumentRole::Premise, ArgumentRole::Claim]);
+        assert_eq!(edges(&structure), vec![(0, 1, RelationKind::Support)]);
+    }
+
+    #[test]
+    fn conclusions_are_supported_by_the_claim_and_mid_sentence_connectives_are_ignored() {
+        let structure = NLPProcessor::new().argument_structure("All men are mortal. Therefore Socrates is mortal.");
+        assert_eq!(roles(&structure), vec![ArgumentRole::Claim, ArgumentRole::Conclusion]);
+        assert_eq!(edges(&structure), vec![(0, 1, RelationKind::Support)]);
+
+        let structure = NLPProcessor::new().argument_structure("疲れただから寝る。");
+        assert_eq!(roles(&structure), vec![ArgumentRole::Claim]);
+    }
+}
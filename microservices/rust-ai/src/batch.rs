@@ -0,0 +1,313 @@
+use std::pin::Pin;
+use bytes::{Buf, Bytes};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use rayon::prelude::*;
+use utoipa::ToSchema;
+use warp::http::header::CONTENT_TYPE;
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+use crate::ai_engine::{AIEngine, AnalyzeOptions, PhilosophyAnalysisV2};
+use crate::api::AnalyzeRequest;
+use crate::error::{ApiError, ErrorResponse};
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// 届いた分から読むリクエストボディ
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, warp::Error>> + Send>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchAnalyzeRequest {
+    pub texts: Vec<String>,
+    /// 全テキストの分析に付ける持ち主
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// 誤謬の説明に使うキャラクターの口調
+    #[serde(default)]
+    pub character: Option<String>,
+    /// 全テキストに要約を付ける
+    #[serde(default)]
+    pub summarize: bool,
+    #[serde(default)]
+    pub compression_ratio: Option<f32>,
+}
+
+/// NDJSONで返す1行分の結果。`result` と `error` のどちらか一方が入る。
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchItem<T> {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+/// リクエストボディをストリームのまま取り出すフィルター。
+///
+/// `Content-Length` が `max_bytes` を超えていれば読む前に断る。ヘッダーの無い
+/// （chunked の）ボディは `BatchInput` が読みながら上限を確かめる。
+pub fn body(max_bytes: u64) -> impl Filter<Extract = (BodyStream,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and_then(move |length: Option<u64>| async move {
+            match length {
+                Some(length) if length > max_bytes => Err(warp::reject::custom(ApiError::PayloadTooLarge { max: max_bytes })),
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+        .and(warp::body::stream())
+        .map(|stream| Box::pin(StreamExt::map(stream, into_bytes)) as BodyStream)
+}
+
+fn into_bytes<B: Buf>(chunk: Result<B, warp::Error>) -> Result<Bytes, warp::Error> {
+    chunk.map(|mut buf| buf.copy_to_bytes(buf.remaining()))
+}
+
+/// バッチの入力を1件ずつ取り出す。
+///
+/// JSON（`{"texts": [...]}`）はまとめて読んで解釈し、NDJSON（1行に `{"text": "..."}`）は
+/// 届いた行から順に解釈する。NDJSONで解釈できない行はバッチ全体を失敗させず、
+/// その行だけのエラーにする。
+pub struct BatchInput {
+    source: Source,
+    next_index: usize,
+    max_batch_size: usize,
+}
+
+enum Source {
+    Parsed(std::vec::IntoIter<AnalyzeRequest>),
+    Lines { reader: LineReader, pending: Option<Vec<u8>> },
+    Done,
+}
+
+impl BatchInput {
+    /// 入力を読み始める。
+    ///
+    /// 空のバッチや、JSONで件数が上限を超えるものはここでエラーにする。NDJSONは
+    /// 最初の1行までを読み、上限を超えた分は `next` がその行のエラーとして返す。
+    pub async fn new(body: BodyStream, content_type: Option<&str>, max_body: u64, max_batch_size: usize) -> Result<Self, ApiError> {
+        let is_ndjson = content_type
+            .map(|ct| ct.starts_with(NDJSON) || ct.starts_with("application/ndjson"))
+            .unwrap_or(false);
+        let mut reader = LineReader::new(body, max_body);
+
+        let source = if is_ndjson {
+            let first = reader.next_line().await?.ok_or(ApiError::MissingField("texts"))?;
+            Source::Lines { reader, pending: Some(first) }
+        } else {
+            let body = reader.read_to_end().await?;
+            let request: BatchAnalyzeRequest = serde_json::from_slice(&body)
+                .map_err(|e| ApiError::InvalidBody(e.to_string()))?;
+            if request.texts.is_empty() {
+                return Err(ApiError::MissingField("texts"));
+            }
+            if request.texts.len() > max_batch_size {
+                return Err(ApiError::BatchTooLarge { max: max_batch_size });
+            }
+            let items: Vec<AnalyzeRequest> = request.texts.iter()
+                .map(|text| AnalyzeRequest {
+                    text: text.clone(),
+                    user_id: request.user_id.clone(),
+                    character: request.character.clone(),
+                    summarize: request.summarize,
+                    compression_ratio: request.compression_ratio,
+                    session_id: None,
+                })
+                .collect();
+            Source::Parsed(items.into_iter())
+        };
+
+        Ok(BatchInput { source, next_index: 0, max_batch_size })
+    }
+
+    /// 次の1件と入力順の番号。ボディを読めなくなったらそのエラーを最後の1件として返す
+    pub async fn next(&mut self) -> Option<(usize, Result<AnalyzeRequest, ApiError>)> {
+        let index = self.next_index;
+        let item = match &mut self.source {
+            Source::Parsed(items) => Ok(items.next()?),
+            Source::Lines { reader, pending } => {
+                let line = match pending.take() {
+                    Some(line) => Ok(Some(line)),
+                    None => reader.next_line().await,
+                };
+                match line {
+                    Ok(None) => {
+                        self.source = Source::Done;
+                        return None;
+                    }
+                    Ok(Some(_)) if index >= self.max_batch_size => {
+                        self.source = Source::Done;
+                        Err(ApiError::BatchTooLarge { max: self.max_batch_size })
+                    }
+                    Ok(Some(line)) => serde_json::from_slice::<AnalyzeRequest>(&line)
+                        .map_err(|e| ApiError::InvalidBody(e.to_string())),
+                    Err(e) => {
+                        self.source = Source::Done;
+                        Err(e)
+                    }
+                }
+            }
+            Source::Done => return None,
+        };
+        self.next_index += 1;
+        Some((index, item))
+    }
+}
+
+// ボディを行ごとに切り出す（空行は飛ばす）
+struct LineReader {
+    body: BodyStream,
+    buffer: Vec<u8>,
+    // `buffer` のうち改行が無いと確かめた長さ
+    scanned: usize,
+    read: u64,
+    max_body: u64,
+    finished: bool,
+}
+
+impl LineReader {
+    fn new(body: BodyStream, max_body: u64) -> Self {
+        LineReader { body, buffer: Vec::new(), scanned: 0, read: 0, max_body, finished: false }
+    }
+
+    async fn next_line(&mut self) -> Result<Option<Vec<u8>>, ApiError> {
+        loop {
+            if let Some(offset) = self.buffer[self.scanned..].iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=self.scanned + offset).collect();
+                self.scanned = 0;
+                if is_blank(&line) {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            self.scanned = self.buffer.len();
+            if self.finished {
+                let rest = std::mem::take(&mut self.buffer);
+                self.scanned = 0;
+                return Ok((!is_blank(&rest)).then_some(rest));
+            }
+            self.fill().await?;
+        }
+    }
+
+    async fn read_to_end(mut self) -> Result<Vec<u8>, ApiError> {
+        while !self.finished {
+            self.fill().await?;
+        }
+        Ok(self.buffer)
+    }
+
+    async fn fill(&mut self) -> Result<(), ApiError> {
+        match self.body.next().await {
+            Some(Ok(chunk)) => {
+                self.read += chunk.len() as u64;
+                if self.read > self.max_body {
+                    return Err(ApiError::PayloadTooLarge { max: self.max_body });
+                }
+                self.buffer.extend_from_slice(&chunk);
+            }
+            Some(Err(e)) => return Err(ApiError::InvalidBody(e.to_string())),
+            None => self.finished = true,
+        }
+        Ok(())
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+/// テキストを全コアで並列に分析・保存し、入力順のNDJSONとしてストリームで返す。
+///
+/// 入力を読みながら、届いている分（最大で一定件数）をまとめて並列処理し、
+/// 結果を順番どおりに送り出す。`prepare` は分析の前に各リクエストへ
+/// 適用する（持ち主の上書きなど）。クライアントが切断したら残りは処理しない。
+pub fn stream_analyses<T, P>(
+    ai_engine: AIEngine,
+    mut input: BatchInput,
+    prepare: P,
+    max_text_length: usize,
+    default_character: String,
+) -> warp::reply::Response
+where
+    T: Serialize + From<PhilosophyAnalysisV2> + Send + 'static,
+    P: Fn(&mut AnalyzeRequest) + Send + 'static,
+{
+    let chunk_size = rayon::current_num_threads() * 4;
+    let (items_tx, mut items_rx) = mpsc::channel::<(usize, Result<AnalyzeRequest, ApiError>)>(chunk_size);
+    let (tx, rx) = mpsc::channel::<Result<String, std::io::Error>>(64);
+
+    tokio::spawn(async move {
+        while let Some((index, mut item)) = input.next().await {
+            if let Ok(request) = &mut item {
+                prepare(request);
+            }
+            if items_tx.send((index, item)).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        while let Some(first) = items_rx.blocking_recv() {
+            let mut chunk = vec![first];
+            while chunk.len() < chunk_size {
+                match items_rx.try_recv() {
+                    Ok(item) => chunk.push(item),
+                    Err(_) => break,
+                }
+            }
+
+            let lines: Vec<String> = chunk.par_iter()
+                .map(|(index, item)| {
+                    let _entered = span.enter();
+                    let outcome = match item {
+                        Ok(request) => request.validate(&ai_engine, max_text_length)
+                            .and_then(|verdict| {
+                                let options = AnalyzeOptions {
+                                    character: Some(request.character.clone().unwrap_or_else(|| default_character.clone())),
+                                    summary_ratio: request.summary_ratio(),
+                                    session_id: request.session_id.clone(),
+                                    moderation: Some(verdict),
+                                };
+                                let analysis = ai_engine.analyze_text(&request.text, &options);
+                                ai_engine.store_analysis(&analysis, request.user_id.as_deref())?;
+                                Ok(T::from(analysis))
+                            })
+                            .map_err(|e| ErrorResponse::from(&e)),
+                        Err(e) => Err(ErrorResponse::from(e)),
+                    };
+                    to_line(*index, outcome)
+                })
+                .collect();
+
+            for line in lines {
+                if tx.blocking_send(Ok(line)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut response = warp::reply::Response::new(Body::wrap_stream(ReceiverStream::new(rx)));
+    response.headers_mut().insert(CONTENT_TYPE, warp::http::HeaderValue::from_static(NDJSON));
+    response
+}
+
+// 1件分の結果をNDJSONの1行にする。結果をJSONにできなければその行のエラーにする
+fn to_line<T: Serialize>(index: usize, outcome: Result<T, ErrorResponse>) -> String {
+    let item = match outcome {
+        Ok(result) => BatchItem { index, result: Some(result), error: None },
+        Err(error) => BatchItem { index, result: None, error: Some(error) },
+    };
+    let mut line = serde_json::to_string(&item).unwrap_or_else(|e| {
+        tracing::error!(index, error = %e, "failed to serialize a batch result");
+        let error = ErrorResponse::new("internal", "the result could not be serialized");
+        serde_json::json!({ "index": index, "error": error }).to_string()
+    });
+    line.push('\n');
+    line
+}
@@ -1,13 +1,17 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 起動時から使える組み込みのキャラクター（`characters.default` に指定できる）
+pub const BUILTIN_CHARACTERS: [&str; 3] = ["frog", "fugu", "snowman"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Character {
     pub name: String,
     pub emoji: String,
     pub personality: CharacterPersonality,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CharacterPersonality {
     pub traits: Vec<String>,
     pub language_style: String,
@@ -101,7 +105,7 @@ impl Character {
         }
     }
 
-    pub fn respond_to(&self, message: &str, context: Option<&str>) -> String {
+    pub fn respond_to(&self, message: &str, _context: Option<&str>) -> String {
         let sentiment = self.analyze_message_sentiment(message);
         let response_base = self.select_response_pattern(&sentiment);
         
@@ -134,7 +138,7 @@ impl Character {
     fn select_response_pattern(&self, sentiment: &str) -> String {
         let patterns = &self.personality.response_patterns;
         let index = match sentiment {
-            "positive" => 0.min(patterns.len() - 1),
+            "positive" => 0,
             "negative" => (patterns.len() / 2).min(patterns.len() - 1),
             _ => (patterns.len() - 1).min(patterns.len() - 1),
         };
@@ -0,0 +1,396 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::nlp::excerpt;
+
+pub const MAX_CLUSTERS: usize = 20;
+/// 保存済みの分析から集める最大件数
+pub const MAX_DOCUMENTS: usize = 5_000;
+/// 時間窓を省略したときの日数
+pub const DEFAULT_WINDOW_DAYS: i64 = 7;
+
+const MAX_ITERATIONS: usize = 50;
+const TOP_KEYWORDS: usize = 5;
+const REPRESENTATIVES: usize = 3;
+// 結果を再現できるよう k-means++ の乱数は固定の種から作る
+const SEED: u64 = 42;
+// 保持するジョブ数（超えたら終わったものを古い順に捨てる）
+const MAX_JOBS: usize = 100;
+/// 同時に走らせるジョブの上限
+pub const MAX_RUNNING_JOBS: usize = 4;
+const EXCERPT_CHARS: usize = 120;
+
+/// クラスタリングする文書の集め方
+#[derive(Debug, Clone)]
+pub enum ClusterSource {
+    /// リクエストで渡されたテキスト
+    Texts(Vec<String>),
+    /// 保存済みの分析のうち `since..until` のもの（`user_id` があればその人の分だけ）
+    Window { since: DateTime<Utc>, until: Option<DateTime<Utc>>, user_id: Option<String> },
+}
+
+/// クラスタリングの対象になる1件
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// 保存済みの分析なら、その ID
+    pub id: Option<String>,
+    pub text: String,
+    /// `NLPProcessor::keyword_terms_with` で取り出した語
+    pub terms: Vec<String>,
+}
+
+/// クラスタの代表テキスト（重心に近い順）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Representative {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub text: String,
+    /// 重心とのコサイン類似度
+    pub similarity: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicCluster {
+    pub id: usize,
+    pub size: usize,
+    pub keywords: Vec<String>,
+    pub representatives: Vec<Representative>,
+}
+
+/// クラスタリングの結果（大きいクラスタから順）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClusterResult {
+    pub k: usize,
+    pub documents: usize,
+    pub clusters: Vec<TopicCluster>,
+    /// 他の文書と共通の語が無く、どのクラスタにも入れなかった文書の数
+    pub unclustered: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+/// バックグラウンドで走るクラスタリングのジョブ
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClusterJob {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ClusterResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// ジョブの状態を持つストア（`AIEngine` のクローン間で共有する）
+#[derive(Default)]
+pub struct ClusterJobs {
+    jobs: RwLock<Vec<ClusterJob>>,
+}
+
+impl ClusterJobs {
+    /// 実行中のジョブを登録する。`MAX_RUNNING_JOBS` 件が走っていれば `None`
+    pub fn start(&self) -> Option<ClusterJob> {
+        let job = ClusterJob {
+            id: Uuid::new_v4().to_string(),
+            status: JobStatus::Running,
+            created_at: Utc::now(),
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        let mut jobs = self.jobs.write().unwrap();
+        if jobs.iter().filter(|job| job.status == JobStatus::Running).count() >= MAX_RUNNING_JOBS {
+            return None;
+        }
+        jobs.push(job.clone());
+        // 実行中のジョブは結果を書き込めるように残す
+        let mut excess = jobs.len().saturating_sub(MAX_JOBS);
+        jobs.retain(|job| {
+            let evict = excess > 0 && job.status != JobStatus::Running;
+            excess -= usize::from(evict);
+            !evict
+        });
+        Some(job)
+    }
+
+    pub fn finish(&self, id: &str, outcome: Result<ClusterResult, String>) {
+        if let Some(job) = self.jobs.write().unwrap().iter_mut().find(|job| job.id == id) {
+            job.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(result) => {
+                    job.status = JobStatus::Done;
+                    job.result = Some(result);
+                }
+                Err(error) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(error);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<ClusterJob> {
+        self.jobs.read().unwrap().iter().find(|job| job.id == id).cloned()
+    }
+}
+
+// 疎な TF-IDF ベクトル（語の番号 → 重み、L2ノルム 1）
+type Vector = BTreeMap<usize, f32>;
+
+/// TF-IDF ベクトルを球面 k-means（コサイン類似度）で `k` 個に分ける。
+///
+/// `k` を省略すると `sqrt(件数 / 2)` を 2〜`MAX_CLUSTERS` に収めた数にする。
+/// 1件にしか出てこない語は似た文書を結びつけないので使わない。使える語が
+/// 1つも無い文書はどの重心とも似ていないので、`unclustered` に数えて外す。
+pub fn cluster(documents: &[Document], k: Option<usize>) -> ClusterResult {
+    let total = documents.len();
+    let (vectors, vocabulary) = vectorize(documents);
+    let (documents, vectors): (Vec<&Document>, Vec<Vector>) = documents.iter()
+        .zip(vectors)
+        .filter(|(_, vector)| !vector.is_empty())
+        .unzip();
+    let n = documents.len();
+    let unclustered = total - n;
+    let k = k.unwrap_or_else(|| ((n as f32 / 2.0).sqrt().round() as usize).clamp(2, MAX_CLUSTERS))
+        .min(n)
+        .max(1);
+    if n == 0 {
+        return ClusterResult { k: 0, documents: total, clusters: Vec::new(), unclustered };
+    }
+
+    let mut centroids = initial_centroids(&vectors, k);
+    let mut assignments = vec![0; n];
+    for iteration in 0..MAX_ITERATIONS {
+        let next: Vec<usize> = vectors.iter().map(|v| nearest(v, &centroids).0).collect();
+        if iteration > 0 && next == assignments {
+            break;
+        }
+        assignments = next;
+        centroids = (0..k)
+            .map(|c| {
+                let members = vectors.iter().zip(&assignments).filter(|(_, a)| **a == c).map(|(v, _)| v);
+                normalized(sum(members))
+            })
+            .collect();
+    }
+
+    let mut clusters: Vec<TopicCluster> = (0..k)
+        .filter_map(|c| {
+            let members: Vec<usize> = (0..n).filter(|&i| assignments[i] == c).collect();
+            if members.is_empty() {
+                return None;
+            }
+
+            let mut weights: Vec<(&usize, &f32)> = centroids[c].iter().collect();
+            weights.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            let keywords = weights.into_iter()
+                .take(TOP_KEYWORDS)
+                .map(|(term, _)| vocabulary[*term].clone())
+                .collect();
+
+            let mut representatives: Vec<Representative> = members.iter()
+                .map(|&i| Representative {
+                    id: documents[i].id.clone(),
+                    text: excerpt(&documents[i].text, EXCERPT_CHARS),
+                    similarity: (dot(&vectors[i], &centroids[c]) * 1000.0).round() / 1000.0,
+                })
+                .collect();
+            representatives.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+            representatives.truncate(REPRESENTATIVES);
+
+            Some(TopicCluster { id: 0, size: members.len(), keywords, representatives })
+        })
+        .collect();
+
+    clusters.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.keywords.cmp(&b.keywords)));
+    for (id, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = id;
+    }
+    ClusterResult { k, documents: total, clusters, unclustered }
+}
+
+fn vectorize(documents: &[Document]) -> (Vec<Vector>, Vec<String>) {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for document in documents {
+        let mut seen: Vec<&str> = document.terms.iter().map(String::as_str).collect();
+        seen.sort();
+        seen.dedup();
+        for term in seen {
+            *document_frequency.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    let min_frequency = if documents.len() >= 3 { 2 } else { 1 };
+    let mut vocabulary: Vec<String> = document_frequency.iter()
+        .filter(|(_, df)| **df >= min_frequency)
+        .map(|(term, _)| term.to_string())
+        .collect();
+    vocabulary.sort();
+    let index: HashMap<&str, usize> = vocabulary.iter().enumerate().map(|(i, term)| (term.as_str(), i)).collect();
+
+    let n = documents.len() as f32;
+    let vectors = documents.iter()
+        .map(|document| {
+            let mut counts: BTreeMap<usize, f32> = BTreeMap::new();
+            for term in &document.terms {
+                if let Some(&i) = index.get(term.as_str()) {
+                    *counts.entry(i).or_insert(0.0) += 1.0;
+                }
+            }
+            let weighted = counts.into_iter()
+                .map(|(i, count)| {
+                    let idf = (n / document_frequency[vocabulary[i].as_str()] as f32).ln() + 1.0;
+                    (i, (1.0 + count.ln()) * idf)
+                })
+                .collect();
+            normalized(weighted)
+        })
+        .collect();
+    (vectors, vocabulary)
+}
+
+// k-means++：すでに選んだ重心から遠い点ほど選ばれやすくする
+fn initial_centroids(vectors: &[Vector], k: usize) -> Vec<Vector> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut centroids = vec![vectors[rng.gen_range(0..vectors.len())].clone()];
+    while centroids.len() < k {
+        let distances: Vec<f32> = vectors.iter().map(|v| 1.0 - nearest(v, &centroids).1).collect();
+        let total: f32 = distances.iter().sum();
+        if total <= f32::EPSILON {
+            // 残りがすべて同じ点なら、まだ選んでいない点を順に使う
+            centroids.push(vectors[centroids.len() % vectors.len()].clone());
+            continue;
+        }
+        let mut target = rng.gen_range(0.0..total);
+        let chosen = distances.iter()
+            .position(|d| {
+                target -= d;
+                target <= 0.0
+            })
+            .unwrap_or(vectors.len() - 1);
+        centroids.push(vectors[chosen].clone());
+    }
+    centroids
+}
+
+// 最も近い重心の番号とコサイン類似度
+fn nearest(vector: &Vector, centroids: &[Vector]) -> (usize, f32) {
+    centroids.iter()
+        .enumerate()
+        .map(|(i, centroid)| (i, dot(vector, centroid)))
+        .fold((0, f32::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+}
+
+fn dot(a: &Vector, b: &Vector) -> f32 {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    small.iter().filter_map(|(i, w)| large.get(i).map(|v| w * v)).sum()
+}
+
+fn sum<'a>(vectors: impl Iterator<Item = &'a Vector>) -> Vector {
+    let mut total = Vector::new();
+    for vector in vectors {
+        for (i, w) in vector {
+            *total.entry(*i).or_insert(0.0) += w;
+        }
+    }
+    total
+}
+
+fn normalized(mut vector: Vector) -> Vector {
+    let norm = vector.values().map(|w| w * w).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.values_mut().for_each(|w| *w /= norm);
+    }
+    vector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(terms: &[&str]) -> Document {
+        Document { id: None, text: terms.join(" "), terms: terms.iter().map(|term| term.to_string()).collect() }
+    }
+
+    fn documents() -> Vec<Document> {
+        vec![
+            document(&["自由", "責任", "選択"]),
+            document(&["宇宙", "星", "光"]),
+            document(&["自由", "責任"]),
+            document(&["宇宙", "星"]),
+            document(&["責任", "選択"]),
+            document(&["星", "光"]),
+        ]
+    }
+
+    #[test]
+    fn separates_documents_by_shared_terms() {
+        let result = cluster(&documents(), Some(2));
+        assert_eq!(result.k, 2);
+        assert_eq!(result.unclustered, 0);
+        assert_eq!(result.clusters.iter().map(|c| c.size).collect::<Vec<_>>(), vec![3, 3]);
+        let keywords: Vec<&Vec<String>> = result.clusters.iter().map(|c| &c.keywords).collect();
+        assert!(keywords.iter().any(|k| k.contains(&"責任".to_string()) && !k.contains(&"星".to_string())));
+        assert!(keywords.iter().any(|k| k.contains(&"星".to_string()) && !k.contains(&"責任".to_string())));
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let first = serde_json::to_string(&cluster(&documents(), None)).unwrap();
+        let second = serde_json::to_string(&cluster(&documents(), None)).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn documents_without_shared_terms_are_unclustered() {
+        let mut documents = documents();
+        documents.push(document(&["孤独"]));
+        documents.push(document(&[]));
+        let result = cluster(&documents, Some(2));
+        assert_eq!(result.documents, 8);
+        assert_eq!(result.unclustered, 2);
+        assert_eq!(result.clusters.iter().map(|c| c.size).sum::<usize>(), 6);
+
+        let result = cluster(&[document(&["孤独"]), document(&["宇宙"]), document(&["光"])], None);
+        assert_eq!((result.k, result.unclustered), (0, 3));
+        assert!(result.clusters.is_empty());
+    }
+
+    #[test]
+    fn caps_running_jobs() {
+        let jobs = ClusterJobs::default();
+        let running: Vec<ClusterJob> = (0..MAX_RUNNING_JOBS).map(|_| jobs.start().unwrap()).collect();
+        assert!(jobs.start().is_none());
+
+        jobs.finish(&running[0].id, Err("failed".to_string()));
+        assert_eq!(jobs.get(&running[0].id).unwrap().status, JobStatus::Failed);
+        assert!(jobs.start().is_some());
+    }
+
+    #[test]
+    fn evicts_only_finished_jobs() {
+        let jobs = ClusterJobs::default();
+        let long_running = jobs.start().unwrap();
+        let first_finished = jobs.start().unwrap();
+        jobs.finish(&first_finished.id, Ok(cluster(&[], None)));
+        for _ in 0..MAX_JOBS {
+            let job = jobs.start().unwrap();
+            jobs.finish(&job.id, Ok(cluster(&[], None)));
+        }
+        assert_eq!(jobs.get(&long_running.id).unwrap().status, JobStatus::Running);
+        assert!(jobs.get(&first_finished.id).is_none());
+    }
+}
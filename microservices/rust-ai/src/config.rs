@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use crate::character_ai::BUILTIN_CHARACTERS;
+use crate::moderation::{ModerationRule, Moderator};
+use crate::rate_limit;
+use crate::redaction::Redactor;
+pub use service_common::settings::{AuthSettings, BucketSettings, LoggingSettings, RateLimitSettings};
+use crate::server::{ServerConfig, AUTH_ROUTES, BODY_LIMIT_ROUTES, DEFAULT_ALLOWED_ORIGIN};
+
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+const LOG_FORMATS: [&str; 2] = ["text", "json"];
+const STORAGE_BACKENDS: [&str; 3] = ["sqlite", "postgres", "memory"];
+// HS256 の鍵として短すぎない長さ
+const MIN_JWT_SECRET_BYTES: usize = 32;
+const MIN_API_KEY_LENGTH: usize = 16;
+
+/// philosophy-ai の設定。
+///
+/// 優先順位は デフォルト < TOMLファイル < 環境変数 < CLIフラグ。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerSettings,
+    pub storage: StorageSettings,
+    pub logging: LoggingSettings,
+    pub limits: LimitSettings,
+    pub characters: CharacterSettings,
+    pub moderation: ModerationSettings,
+    pub redaction: RedactionSettings,
+    pub rate_limit: RateLimitSettings,
+    pub auth: AuthSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: String,
+    pub port: u16,
+    /// CORSで許可するオリジン（"*" で全て許可）
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    pub data_dir: PathBuf,
+    /// 分析結果の保存先（`sqlite` / `postgres` / `memory`）
+    pub backend: String,
+    /// `postgres` のときの接続文字列
+    pub database_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// テキスト系フィールドの最大文字数
+    pub max_text_length: usize,
+    /// `/analyze/batch` で受け付ける最大件数
+    pub max_batch_size: usize,
+    /// ルートごとのリクエストボディの上限（バイト）。無いルートは `max_text_length` から決める
+    pub max_body_bytes: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CharacterSettings {
+    /// `character` を省略したチャットで使うキャラクターID
+    pub default: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModerationSettings {
+    /// チャットと分析の前にモデレーションをかけるか
+    pub enabled: bool,
+    /// 組み込みの語彙に足す、ブロックする語
+    pub blocklist: Vec<String>,
+    /// 組み込みに足す正規表現のルール
+    pub rules: Vec<ModerationRule>,
+}
+
+/// 個人情報の伏せ字（ログには常にかける）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionSettings {
+    /// 伏せる人名
+    pub names: Vec<String>,
+    /// 会話履歴に残すテキストも伏せる
+    pub sessions: bool,
+    /// 保存する分析も伏せたテキストから作る
+    pub analyses: bool,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind: "0.0.0.0".to_string(),
+            port: 3001,
+            allowed_origins: vec![DEFAULT_ALLOWED_ORIGIN.to_string()],
+        }
+    }
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        StorageSettings {
+            data_dir: PathBuf::from("data"),
+            backend: "sqlite".to_string(),
+            database_url: None,
+        }
+    }
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        LimitSettings {
+            max_text_length: ServerConfig::default().max_text_length,
+            max_batch_size: ServerConfig::default().max_batch_size,
+            max_body_bytes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for CharacterSettings {
+    fn default() -> Self {
+        CharacterSettings {
+            default: ServerConfig::default().default_character,
+        }
+    }
+}
+
+impl Default for ModerationSettings {
+    fn default() -> Self {
+        ModerationSettings {
+            enabled: true,
+            blocklist: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// コマンドライン引数（各フラグは対応する環境変数でも指定できる）
+#[derive(Debug, Default, Parser)]
+#[command(name = "philosophy-ai", version, about = "Philosophy AI Server")]
+pub struct Cli {
+    /// TOML設定ファイル
+    #[arg(long, env = "PHILOSOPHY_AI_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "PHILOSOPHY_AI_BIND")]
+    pub bind: Option<String>,
+    #[arg(long, env = "PORT")]
+    pub port: Option<u16>,
+    /// 許可するCORSオリジン（複数指定可、環境変数はカンマ区切り）
+    #[arg(long = "allowed-origin", env = "PHILOSOPHY_AI_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub allowed_origins: Vec<String>,
+    #[arg(long, env = "PHILOSOPHY_AI_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// 分析結果の保存先（sqlite / postgres / memory）
+    #[arg(long, env = "PHILOSOPHY_AI_STORAGE_BACKEND")]
+    pub storage_backend: Option<String>,
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+    #[arg(long, env = "PHILOSOPHY_AI_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// ログの形式（text / json）
+    #[arg(long, env = "PHILOSOPHY_AI_LOG_FORMAT")]
+    pub log_format: Option<String>,
+    #[arg(long, env = "PHILOSOPHY_AI_MAX_TEXT_LENGTH")]
+    pub max_text_length: Option<usize>,
+    #[arg(long, env = "PHILOSOPHY_AI_MAX_BATCH_SIZE")]
+    pub max_batch_size: Option<usize>,
+    #[arg(long, env = "PHILOSOPHY_AI_DEFAULT_CHARACTER")]
+    pub default_character: Option<String>,
+    /// ブロックする語（複数指定可、環境変数はカンマ区切り）
+    #[arg(long = "moderation-blocklist", env = "PHILOSOPHY_AI_MODERATION_BLOCKLIST", value_delimiter = ',')]
+    pub moderation_blocklist: Vec<String>,
+    /// クライアントIPごとに1秒あたり受け付けるリクエスト数
+    #[arg(long, env = "PHILOSOPHY_AI_RATE_LIMIT_PER_SECOND")]
+    pub rate_limit_per_second: Option<f64>,
+    /// クライアントIPごとに続けて受け付けられるリクエスト数
+    #[arg(long, env = "PHILOSOPHY_AI_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+    /// 信頼するプロキシのIPかCIDR（複数指定可、環境変数はカンマ区切り）
+    #[arg(long = "trusted-proxy", env = "PHILOSOPHY_AI_TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Vec<String>,
+    /// 認証を有効にするか（true / false）
+    #[arg(long, env = "PHILOSOPHY_AI_AUTH_ENABLED")]
+    pub auth_enabled: Option<bool>,
+    /// サービス用のAPIキー（複数指定可、環境変数はカンマ区切り）
+    #[arg(long = "api-key", env = "PHILOSOPHY_AI_API_KEYS", value_delimiter = ',', hide_env_values = true)]
+    pub api_keys: Vec<String>,
+    /// JWT の署名を確かめる共有の秘密鍵
+    #[arg(long, env = "PHILOSOPHY_AI_JWT_SECRET", hide_env_values = true)]
+    pub jwt_secret: Option<String>,
+    /// 伏せる人名（複数指定可、環境変数はカンマ区切り）
+    #[arg(long = "redact-name", env = "PHILOSOPHY_AI_REDACT_NAMES", value_delimiter = ',')]
+    pub redact_names: Vec<String>,
+    /// 最終的な設定をTOMLで出力して終了する
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: toml::de::Error },
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "failed to read config file {}: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, source } => {
+                write!(f, "failed to parse config file {}: {}", path.display(), source)
+            }
+            ConfigError::Invalid { key, message } => write!(f, "invalid `{}`: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// デフォルト → ファイル → 環境変数/CLI の順に重ねて検証する
+    pub fn load(cli: &Cli) -> Result<Config, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&content).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(bind) = &cli.bind {
+            self.server.bind = bind.clone();
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if !cli.allowed_origins.is_empty() {
+            self.server.allowed_origins = cli.allowed_origins.clone();
+        }
+        if let Some(data_dir) = &cli.data_dir {
+            self.storage.data_dir = data_dir.clone();
+        }
+        if let Some(backend) = &cli.storage_backend {
+            self.storage.backend = backend.to_lowercase();
+        }
+        if let Some(url) = &cli.database_url {
+            self.storage.database_url = Some(url.clone());
+        }
+        if let Some(level) = &cli.log_level {
+            self.logging.level = level.to_lowercase();
+        }
+        if let Some(format) = &cli.log_format {
+            self.logging.format = format.to_lowercase();
+        }
+        if let Some(max_text_length) = cli.max_text_length {
+            self.limits.max_text_length = max_text_length;
+        }
+        if let Some(max_batch_size) = cli.max_batch_size {
+            self.limits.max_batch_size = max_batch_size;
+        }
+        if let Some(character) = &cli.default_character {
+            self.characters.default = character.clone();
+        }
+        if !cli.moderation_blocklist.is_empty() {
+            self.moderation.blocklist = cli.moderation_blocklist.clone();
+        }
+        if !cli.redact_names.is_empty() {
+            self.redaction.names = cli.redact_names.clone();
+        }
+        if let Some(per_second) = cli.rate_limit_per_second {
+            self.rate_limit.per_ip.per_second = per_second;
+        }
+        if let Some(burst) = cli.rate_limit_burst {
+            self.rate_limit.per_ip.burst = burst;
+        }
+        if !cli.trusted_proxies.is_empty() {
+            self.rate_limit.trusted_proxies = cli.trusted_proxies.clone();
+        }
+        if let Some(enabled) = cli.auth_enabled {
+            self.auth.enabled = enabled;
+        }
+        if !cli.api_keys.is_empty() {
+            self.auth.api_keys = cli.api_keys.clone();
+        }
+        if let Some(secret) = &cli.jwt_secret {
+            self.auth.jwt_secret = Some(secret.clone());
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn invalid(key: &'static str, message: impl Into<String>) -> Result<(), ConfigError> {
+            Err(ConfigError::Invalid { key, message: message.into() })
+        }
+
+        if self.server.bind.parse::<IpAddr>().is_err() {
+            return invalid("server.bind", format!("`{}` is not an IP address (e.g. 0.0.0.0)", self.server.bind));
+        }
+        if self.server.port == 0 {
+            return invalid("server.port", "must be between 1 and 65535");
+        }
+        if self.server.allowed_origins.is_empty() {
+            return invalid("server.allowed_origins", "must not be empty (use [\"*\"] to allow any origin)");
+        }
+        for origin in &self.server.allowed_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                return invalid("server.allowed_origins", format!("`{}` must be \"*\" or start with http:// or https://", origin));
+            }
+        }
+        if self.storage.data_dir.as_os_str().is_empty() {
+            return invalid("storage.data_dir", "must not be empty");
+        }
+        if !STORAGE_BACKENDS.contains(&self.storage.backend.as_str()) {
+            return invalid("storage.backend", format!("`{}` is not one of {}", self.storage.backend, STORAGE_BACKENDS.join(", ")));
+        }
+        if self.storage.backend == "postgres" {
+            if !cfg!(feature = "postgres") {
+                return invalid("storage.backend", "this build does not include the `postgres` feature");
+            }
+            if self.storage.database_url.as_deref().is_none_or(|url| url.trim().is_empty()) {
+                return invalid("storage.database_url", "is required when `storage.backend` is \"postgres\"");
+            }
+        }
+        if !LOG_LEVELS.contains(&self.logging.level.as_str()) {
+            return invalid("logging.level", format!("`{}` is not one of {}", self.logging.level, LOG_LEVELS.join(", ")));
+        }
+        if !LOG_FORMATS.contains(&self.logging.format.as_str()) {
+            return invalid("logging.format", format!("`{}` is not one of {}", self.logging.format, LOG_FORMATS.join(", ")));
+        }
+        if self.limits.max_text_length == 0 {
+            return invalid("limits.max_text_length", "must be greater than 0");
+        }
+        if self.limits.max_batch_size == 0 {
+            return invalid("limits.max_batch_size", "must be greater than 0");
+        }
+        for (route, bytes) in &self.limits.max_body_bytes {
+            if !BODY_LIMIT_ROUTES.contains(&route.as_str()) {
+                return invalid("limits.max_body_bytes", format!("unknown route `{}` (allowed: {})", route, BODY_LIMIT_ROUTES.join(", ")));
+            }
+            if *bytes == 0 {
+                return invalid("limits.max_body_bytes", format!("`{}` must be greater than 0", route));
+            }
+        }
+        if self.characters.default.trim().is_empty() {
+            return invalid("characters.default", "must not be empty");
+        }
+        if !BUILTIN_CHARACTERS.contains(&self.characters.default.as_str()) {
+            return invalid(
+                "characters.default",
+                format!("unknown character `{}` (allowed: {})", self.characters.default, BUILTIN_CHARACTERS.join(", ")),
+            );
+        }
+        if self.moderation.blocklist.iter().any(|word| word.trim().is_empty()) {
+            return invalid("moderation.blocklist", "must not contain empty words");
+        }
+        if let Err(e) = Moderator::new(&self.moderation) {
+            return invalid("moderation.rules", e.to_string());
+        }
+        for (key, bucket) in [("rate_limit.per_ip", self.rate_limit.per_ip), ("rate_limit.per_principal", self.rate_limit.per_principal)] {
+            if !(bucket.per_second.is_finite() && bucket.per_second > 0.0) {
+                return invalid(key, "`per_second` must be greater than 0");
+            }
+            if bucket.burst == 0 {
+                return invalid(key, "`burst` must be greater than 0");
+            }
+        }
+        if let Some(proxy) = self.rate_limit.trusted_proxies.iter().find(|proxy| rate_limit::parse_network(proxy).is_none()) {
+            return invalid("rate_limit.trusted_proxies", format!("`{}` is not an IP address or CIDR (e.g. 10.0.0.0/8)", proxy));
+        }
+        if self.auth.enabled && self.auth.api_keys.is_empty() && self.auth.jwt_secret.is_none() {
+            return invalid("auth", "set `api_keys` or `jwt_secret` when `enabled` is true");
+        }
+        if self.auth.api_keys.iter().any(|key| key.len() < MIN_API_KEY_LENGTH) {
+            return invalid("auth.api_keys", format!("keys must be at least {} characters", MIN_API_KEY_LENGTH));
+        }
+        if self.auth.jwt_secret.as_ref().is_some_and(|secret| secret.len() < MIN_JWT_SECRET_BYTES) {
+            return invalid("auth.jwt_secret", format!("must be at least {} bytes", MIN_JWT_SECRET_BYTES));
+        }
+        if self.auth.admin_role.trim().is_empty() {
+            return invalid("auth.admin_role", "must not be empty");
+        }
+        if let Some(route) = self.auth.routes.keys().find(|route| !AUTH_ROUTES.contains(&route.as_str())) {
+            return invalid("auth.routes", format!("unknown route `{}` (allowed: {})", route, AUTH_ROUTES.join(", ")));
+        }
+        Ok(())
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip = self.server.bind.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
+        SocketAddr::new(ip, self.server.port)
+    }
+
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            allowed_origins: self.server.allowed_origins.clone(),
+            max_text_length: self.limits.max_text_length,
+            default_character: self.characters.default.clone(),
+            max_batch_size: self.limits.max_batch_size,
+            max_body_bytes: self.limits.max_body_bytes.clone(),
+            rate_limit: self.rate_limit.clone(),
+            auth: self.auth.clone(),
+        }
+    }
+
+    /// `--print-config` 用。APIキーと秘密鍵、接続文字列の認証情報は伏せる
+    pub fn to_toml(&self) -> String {
+        let mut config = self.clone();
+        config.auth.api_keys.iter_mut().for_each(|key| *key = "<redacted>".to_string());
+        if let Some(secret) = &mut config.auth.jwt_secret {
+            *secret = "<redacted>".to_string();
+        }
+        if let Some(url) = &mut config.storage.database_url {
+            *url = Redactor::shared().redact_url(url);
+        }
+        toml::to_string_pretty(&config).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_config_hides_secrets_and_database_credentials() {
+        let mut config = Config::default();
+        config.auth.api_keys = vec!["key-0123456789abcdef".to_string()];
+        config.auth.jwt_secret = Some("jwt-secret-0123456789abcdef0123456789".to_string());
+        config.storage.database_url = Some("postgres://app:s3cret@db:5432/philosophy?password=other".to_string());
+
+        let printed = config.to_toml();
+        for secret in ["key-0123456789abcdef", "jwt-secret-0123456789abcdef0123456789", "s3cret", "other"] {
+            assert!(!printed.contains(secret), "{} should be redacted", secret);
+        }
+        assert!(printed.contains("postgres://[REDACTED]@db:5432/philosophy"));
+    }
+
+    #[test]
+    fn validate_rejects_an_unknown_default_character() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+        config.characters.default = "nobody".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid { key: "characters.default", .. })));
+    }
+
+    #[test]
+    fn only_the_local_frontend_is_allowed_by_default() {
+        assert_eq!(Config::default().server.allowed_origins, vec![DEFAULT_ALLOWED_ORIGIN.to_string()]);
+    }
+}
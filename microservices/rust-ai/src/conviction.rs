@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::matcher::{KeywordMatch, Vocabulary};
+
+/// 確信度の手がかりになる表現の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConvictionMarker {
+    /// ぼかし表現（かもしれない / maybe）
+    Hedge,
+    /// 断定表現（である / must）
+    Assertion,
+    /// 根拠を示す表現（なぜなら / according to）
+    Evidence,
+    /// 反語・修辞疑問（ではないか / isn't it）
+    Rhetorical,
+}
+
+const HEDGES: [&str; 30] = [
+    "かもしれない", "かもしれません", "かも", "たぶん", "多分", "おそらく", "恐らく",
+    "と思う", "と思います", "気がする", "らしい", "みたい", "だろう", "でしょう",
+    "可能性がある", "一概には", "わからない", "分からない",
+    "maybe", "perhaps", "might", "possibly", "probably", "i think", "i guess",
+    "seems", "sort of", "kind of", "not sure", "could be",
+];
+
+// 語の一部にもなる短いぼかし表現（「素晴らしい」「読みたい」「しかも」）。
+// 直前が `BOUND_HEDGE_BEFORE`（終止形・「た」「ない」などの終わり）で、
+// 直後が文末・句読点・助詞のときだけ数える。`true` は名詞の直後（「雨かも」）も認める
+const BOUND_HEDGES: [(&str, bool); 4] = [("らしい", false), ("みたい", false), ("かも", true), ("だろう", true)];
+const BOUND_HEDGE_BEFORE: &str = "うくすつぬふむゆるぐずづぶぷたいだん";
+const BOUND_HEDGE_AFTER: &str = "。．.！!？?、，,…」』）)ねよなかとけがしだで";
+// 終止形の直後でもぼかしではない語
+const NOT_HEDGES: [&str; 1] = ["かわいらしい"];
+
+const ASSERTIONS: [&str; 28] = [
+    "である", "だ。", "だ！", "に違いない", "間違いない", "絶対", "必ず", "明らか",
+    "断言", "べきだ", "べきである", "なければならない", "ねばならない", "確実", "当然",
+    "must", "always", "definitely", "certainly", "clearly", "undoubtedly",
+    "without doubt", "of course", "absolutely", "have to", "should", "never", "no doubt",
+];
+
+const EVIDENCE: [&str; 24] = [
+    "なぜなら", "によると", "によれば", "研究", "調査", "データ", "実際", "証拠",
+    "統計", "実験", "例えば", "根拠", "事実",
+    "because", "according to", "research", "study", "studies", "evidence",
+    "data shows", "for example", "statistics", "proven", "in fact",
+];
+
+const RHETORICAL: [&str; 13] = [
+    "ではないか", "じゃないか", "ではないだろうか", "ではなかろうか", "ではありませんか",
+    "isn't it", "aren't we", "don't we", "don't you", "who doesn't", "who could deny",
+    "how could anyone", "isn't that",
+];
+
+const NEUTRAL: f32 = 5.0;
+
+/// テキストの確信度（`conviction_level` と同じ 0〜10）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ConvictionScore {
+    /// 0（迷い）〜10（強い確信）の整数。DBの `conviction_level` にそのまま入れられる
+    pub level: u8,
+    /// 丸める前のスコア
+    pub score: f32,
+    pub hedges: Vec<String>,
+    pub assertions: Vec<String>,
+    pub evidence: Vec<String>,
+    pub rhetorical_questions: Vec<String>,
+}
+
+impl Default for ConvictionScore {
+    fn default() -> Self {
+        ConvictionScore {
+            level: 5,
+            score: NEUTRAL,
+            hedges: Vec::new(),
+            assertions: Vec::new(),
+            evidence: Vec::new(),
+            rhetorical_questions: Vec::new(),
+        }
+    }
+}
+
+/// `KeywordMatcher` に登録する確信度の語彙
+pub fn vocabulary() -> impl Iterator<Item = (&'static str, Vocabulary)> {
+    let lexicon = [
+        (&HEDGES[..], ConvictionMarker::Hedge),
+        (&ASSERTIONS[..], ConvictionMarker::Assertion),
+        (&EVIDENCE[..], ConvictionMarker::Evidence),
+        (&RHETORICAL[..], ConvictionMarker::Rhetorical),
+    ];
+    lexicon.into_iter().flat_map(|(words, marker)| {
+        words.iter().map(move |word| (*word, Vocabulary::Conviction(marker)))
+    })
+}
+
+/// `scan` 済みの一致から確信度を見積もる。
+///
+/// 中立の 5 から、断定・根拠・反語で上げ、ぼかしで下げる。
+/// 長い文章ほど表現が増えるので文の数あたりの密度で評価し、
+/// 端に張り付かないよう tanh でなだらかに 0〜10 に収める。
+pub fn estimate(text: &str, matches: &[KeywordMatch<'_>]) -> ConvictionScore {
+    let mut result = ConvictionScore::default();
+
+    for (keyword, marker) in markers(text, matches) {
+        let found = match marker {
+            ConvictionMarker::Hedge => &mut result.hedges,
+            ConvictionMarker::Assertion => &mut result.assertions,
+            ConvictionMarker::Evidence => &mut result.evidence,
+            ConvictionMarker::Rhetorical => &mut result.rhetorical_questions,
+        };
+        found.push(keyword.to_string());
+    }
+
+    let exclamations = text.chars().filter(|&c| c == '！' || c == '!').count();
+    let weight = result.assertions.len() as f32
+        + 0.8 * result.evidence.len() as f32
+        + 0.7 * result.rhetorical_questions.len() as f32
+        + 0.3 * exclamations.min(3) as f32
+        - 1.2 * result.hedges.len() as f32;
+
+    let density = weight / sentence_count(text) as f32;
+    let score = (NEUTRAL + NEUTRAL * (0.6 * density).tanh()).clamp(0.0, 10.0);
+    result.score = score;
+    result.level = score.round() as u8;
+
+    for found in [&mut result.hedges, &mut result.assertions, &mut result.evidence, &mut result.rhetorical_questions] {
+        found.sort();
+        found.dedup();
+    }
+    result
+}
+
+/// 確信度の表現を、長い表現に含まれる短い一致（「かもしれない」の中の「かも」など）と
+/// 英単語の途中の一致（"mighty" の中の "might"）を除いて返す
+fn markers<'m>(text: &str, matches: &[KeywordMatch<'m>]) -> Vec<(&'m str, ConvictionMarker)> {
+    let mut candidates: Vec<(&KeywordMatch<'m>, ConvictionMarker)> = matches.iter()
+        .filter_map(|m| match m.vocabulary {
+            Vocabulary::Conviction(marker) => Some((m, *marker)),
+            _ => None,
+        })
+        .filter(|(m, _)| is_whole_word(text, m) && is_hedge_position(text, m))
+        .collect();
+    candidates.sort_by(|(a, _), (b, _)| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
+
+    let mut kept: Vec<(&str, ConvictionMarker)> = Vec::new();
+    let mut covered_until = 0;
+    for (m, marker) in candidates {
+        if m.end <= covered_until {
+            continue;
+        }
+        covered_until = m.end;
+        kept.push((m.keyword, marker));
+    }
+    kept
+}
+
+pub(crate) fn is_whole_word(text: &str, m: &KeywordMatch<'_>) -> bool {
+    if !m.keyword.is_ascii() {
+        return true;
+    }
+    let before = text[..m.start].chars().next_back();
+    let after = text[m.end..].chars().next();
+    !before.is_some_and(|c| c.is_ascii_alphanumeric()) && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+}
+
+/// 短いぼかし表現（`BOUND_HEDGES`）が助動詞として使われている位置か。
+/// それ以外の表現は常に `true`
+fn is_hedge_position(text: &str, m: &KeywordMatch<'_>) -> bool {
+    let Some(&(hedge, after_noun)) = BOUND_HEDGES.iter().find(|(hedge, _)| *hedge == m.keyword) else {
+        return true;
+    };
+    if NOT_HEDGES.iter().any(|word| {
+        word.find(hedge).is_some_and(|offset| m.start >= offset && text[m.start - offset..].starts_with(word))
+    }) {
+        return false;
+    }
+    let before = text[..m.start].chars().next_back();
+    let after = text[m.end..].chars().next();
+    let before_ok = before.is_some_and(|c| {
+        BOUND_HEDGE_BEFORE.contains(c) || (after_noun && c.is_alphanumeric() && !is_hiragana(c))
+    });
+    let after_ok = after.is_none_or(|c| c.is_whitespace() || BOUND_HEDGE_AFTER.contains(c));
+    before_ok && after_ok
+}
+
+fn is_hiragana(c: char) -> bool {
+    ('\u{3041}'..='\u{3096}').contains(&c)
+}
+
+fn sentence_count(text: &str) -> usize {
+    text.split(['。', '.', '!', '?', '！', '？', '\n'])
+        .filter(|s| !s.trim().is_empty())
+        .count()
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nlp::NLPProcessor;
+
+    #[test]
+    fn short_hedges_inside_other_words_are_not_counted() {
+        let nlp = NLPProcessor::new();
+        for text in ["素晴らしい考えだ。", "しかも、彼は来た。", "本を読みたい。", "かわいらしい猫がいる。", "白いかもめが飛ぶ。"] {
+            assert!(nlp.conviction(text).hedges.is_empty(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn short_hedges_count_after_plain_forms() {
+        let nlp = NLPProcessor::new();
+        for (text, hedge) in [
+            ("彼は明日来るらしい。", "らしい"),
+            ("雨が降ったみたいだ。", "みたい"),
+            ("雨かもね。", "かも"),
+            ("それは間違いだろう。", "だろう"),
+        ] {
+            assert_eq!(nlp.conviction(text).hedges, vec![hedge.to_string()], "{}", text);
+        }
+    }
+
+    #[test]
+    fn contained_markers_are_dropped_and_partly_overlapping_ones_kept() {
+        let nlp = NLPProcessor::new();
+        let score = nlp.conviction("そうかもしれない。");
+        assert_eq!(score.hedges, vec!["かもしれない".to_string()]);
+
+        let score = nlp.conviction("正しいのではないかもしれない。");
+        assert_eq!(score.hedges, vec!["かもしれない".to_string()]);
+        assert_eq!(score.rhetorical_questions, vec!["ではないか".to_string()]);
+    }
+
+    #[test]
+    fn english_markers_match_whole_words_only() {
+        let nlp = NLPProcessor::new();
+        assert!(nlp.conviction("A mighty river.").hedges.is_empty());
+        assert_eq!(nlp.conviction("It might rain.").hedges, vec!["might".to_string()]);
+    }
+
+    #[test]
+    fn assertions_raise_and_hedges_lower_the_level() {
+        let nlp = NLPProcessor::new();
+        let neutral = nlp.conviction("今日は晴れ。").level;
+        assert_eq!(neutral, 5);
+        assert!(nlp.conviction("これは絶対に正しい。必ずそうなる。").level > neutral);
+        assert!(nlp.conviction("たぶん正しいと思う。").level < neutral);
+    }
+}
@@ -0,0 +1,189 @@
+use std::convert::Infallible;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::http::header::{HeaderValue, RETRY_AFTER, WWW_AUTHENTICATE};
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+use crate::auth::AuthError;
+use crate::moderation::ModerationCategory;
+use crate::rate_limit::RateLimited;
+use crate::redaction;
+use crate::storage::StoreError;
+
+/// APIが返すエラー
+#[derive(Debug)]
+pub enum ApiError {
+    /// 必須フィールドが空
+    MissingField(&'static str),
+    /// フィールドが長すぎる
+    TooLong { field: &'static str, max: usize },
+    /// 存在しないキャラクターID
+    UnknownCharacter { id: String, allowed: Vec<String> },
+    /// リクエストボディを解釈できない
+    InvalidBody(String),
+    /// 数値が許される範囲の外
+    OutOfRange { field: &'static str, min: f32, max: f32 },
+    /// バッチの件数が上限を超えている
+    BatchTooLarge { max: usize },
+    /// リクエストボディが上限（バイト）を超えている
+    PayloadTooLarge { max: u64 },
+    /// モデレーションで止められた
+    Blocked { field: &'static str, categories: Vec<ModerationCategory> },
+    /// 指定IDのリソースが存在しない
+    NotFound { resource: &'static str, id: String },
+    /// 今の状態ではできない操作（理由）
+    Conflict(String),
+    /// 同時に走らせられる上限まで埋まっている
+    Busy { resource: &'static str, max: usize },
+    /// 保存先の読み書きに失敗した
+    Storage(String),
+}
+
+impl warp::reject::Reject for ApiError {}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::MissingField(_) | ApiError::TooLong { .. } | ApiError::OutOfRange { .. } | ApiError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnknownCharacter { .. } | ApiError::Blocked { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::BatchTooLarge { .. } | ApiError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Storage(_) | ApiError::Busy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::MissingField(_) => "missing_field",
+            ApiError::TooLong { .. } => "too_long",
+            ApiError::OutOfRange { .. } => "out_of_range",
+            ApiError::UnknownCharacter { .. } => "unknown_character",
+            ApiError::InvalidBody(_) => "invalid_body",
+            ApiError::BatchTooLarge { .. } => "batch_too_large",
+            ApiError::PayloadTooLarge { .. } => "payload_too_large",
+            ApiError::Blocked { .. } => "content_blocked",
+            ApiError::NotFound { .. } => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Busy { .. } => "busy",
+            ApiError::Storage(_) => "storage_unavailable",
+        }
+    }
+
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            ApiError::MissingField(field) | ApiError::TooLong { field, .. } | ApiError::OutOfRange { field, .. } | ApiError::Blocked { field, .. } => Some(field),
+            ApiError::UnknownCharacter { .. } => Some("character"),
+            ApiError::InvalidBody(_) | ApiError::PayloadTooLarge { .. } | ApiError::NotFound { .. } | ApiError::Conflict(_) | ApiError::Busy { .. } | ApiError::Storage(_) => None,
+            ApiError::BatchTooLarge { .. } => Some("texts"),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::MissingField(field) => format!("`{}` is required", field),
+            ApiError::TooLong { field, max } => format!("`{}` must be at most {} characters", field, max),
+            ApiError::OutOfRange { field, min, max } => format!("`{}` must be greater than {} and at most {}", field, min, max),
+            ApiError::UnknownCharacter { id, allowed } => {
+                format!("unknown character `{}` (allowed: {})", id, allowed.join(", "))
+            }
+            ApiError::InvalidBody(message) | ApiError::Conflict(message) => message.clone(),
+            ApiError::BatchTooLarge { max } => format!("a batch may contain at most {} texts", max),
+            ApiError::PayloadTooLarge { max } => format!("request body must be at most {} bytes", max),
+            ApiError::Blocked { field, categories } => {
+                let categories: Vec<&str> = categories.iter().map(|c| c.as_str()).collect();
+                format!("`{}` was blocked by moderation ({})", field, categories.join(", "))
+            }
+            ApiError::NotFound { resource, id } => format!("{} `{}` not found", resource, id),
+            ApiError::Busy { resource, max } => format!("at most {} {}s may run at once; try again later", max, resource),
+            ApiError::Storage(_) => "analysis storage is unavailable".to_string(),
+        }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        // 整形する前に、中身のメッセージだけを伏せる
+        let redacted = match &e {
+            StoreError::Backend(message) => StoreError::Backend(redaction::for_log(message)),
+            StoreError::Serialization(message) => StoreError::Serialization(redaction::for_log(message)),
+        };
+        tracing::error!(error = %redacted, "storage error");
+        ApiError::Storage(e.to_string())
+    }
+}
+
+/// 全エラーレスポンス共通のJSON形式
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl From<&ApiError> for ErrorResponse {
+    fn from(error: &ApiError) -> Self {
+        ErrorResponse {
+            code: error.code().to_string(),
+            message: error.message(),
+            field: error.field().map(str::to_string),
+        }
+    }
+}
+
+impl ErrorResponse {
+    pub(crate) fn new(code: &str, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            code: code.to_string(),
+            message: message.into(),
+            field: None,
+        }
+    }
+}
+
+/// warpのリジェクションを一貫したJSONエラーに変換する
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let (status, body) = if let Some(api_error) = err.find::<ApiError>() {
+        (api_error.status(), ErrorResponse::from(api_error))
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, ErrorResponse::new("not_found", "route not found"))
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, ErrorResponse::new("invalid_body", e.to_string()))
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, ErrorResponse::new("invalid_query", e.to_string()))
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, ErrorResponse::new("unsupported_media_type", "expected application/json"))
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, ErrorResponse::new("payload_too_large", "request body is too large"))
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        (StatusCode::LENGTH_REQUIRED, ErrorResponse::new("length_required", "a Content-Length header is required"))
+    } else if let Some(e @ AuthError::Forbidden) = err.find::<AuthError>() {
+        (StatusCode::FORBIDDEN, ErrorResponse::new("forbidden", e.message()))
+    } else if let Some(e) = err.find::<AuthError>() {
+        (StatusCode::UNAUTHORIZED, ErrorResponse::new("unauthorized", e.message()))
+    } else if let Some(e) = err.find::<RateLimited>() {
+        (StatusCode::TOO_MANY_REQUESTS, ErrorResponse::new("rate_limited", format!("too many requests, retry after {} seconds", e.retry_after)))
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, ErrorResponse::new("method_not_allowed", "method not allowed"))
+    } else {
+        // ここに来るのは warp 組み込みのリジェクションだけで、ボディやクエリの値は含まない
+        tracing::error!(rejection = ?err, "unhandled rejection");
+        (StatusCode::INTERNAL_SERVER_ERROR, ErrorResponse::new("internal", "internal server error"))
+    };
+
+    let mut response = warp::reply::with_status(warp::reply::json(&body), status).into_response();
+    if let Some(e) = err.find::<RateLimited>() {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(e.retry_after));
+    }
+    match err.find::<AuthError>() {
+        Some(AuthError::Missing) => {
+            response.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        Some(AuthError::Invalid(_)) => {
+            response.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer error=\"invalid_token\""));
+        }
+        _ => {}
+    }
+    Ok(response)
+}
@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::character_ai::Character;
+use crate::matcher::{char_offset, outermost, KeywordMatch, Vocabulary};
+use crate::nlp::{excerpt, NLPProcessor};
+use crate::question::{voice, Voice};
+
+/// 誤謬の手がかりになる表現の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FallacyCue {
+    /// 相手をおとしめる言葉（バカ / idiot）
+    Insult,
+    /// 相手の発言を指す言葉（人身攻撃の確からしさを上げる）
+    Reference,
+    /// 相手の主張の言い換え（つまりあなたは / so you're saying）
+    Restatement,
+    /// 連鎖の一歩（そうなれば / eventually）
+    SlopeStep,
+    /// 「〜か〜」の後に来る限定（しかない）
+    DichotomyEnd,
+    /// それだけで二択を表す言い回し（白か黒か / only two options）
+    DichotomyFixed,
+    /// 後に or が続くと二択になる（either）
+    Either,
+    /// 全称の言葉（みんな / always）
+    Universal,
+    /// 少ない例（一度 / my friend）
+    SmallSample,
+    /// 権威への言及（専門家が / experts say）
+    Authority,
+}
+
+const INSULTS: [&str; 19] = [
+    "バカ", "馬鹿", "アホ", "無能", "素人", "頭が悪い", "愚か", "嘘つき", "偽善者", "あんな奴",
+    "idiot", "stupid", "moron", "fool", "liar", "hypocrite", "incompetent", "clueless", "ignorant",
+];
+
+const REFERENCES: [&str; 10] = [
+    "意見", "主張", "言うこと", "言っている", "言い分",
+    "argument", "opinion", "says", "claims", "idea",
+];
+
+const RESTATEMENTS: [&str; 14] = [
+    "つまりあなたは", "要するにあなたは", "つまり君は", "要するに君は", "結局あなたは",
+    "と言いたいんでしょ", "と言いたいわけ", "なんて言う人は",
+    "so you're saying", "so what you're saying", "you're basically saying", "you're just saying",
+    "so you think", "in other words, you",
+];
+
+const SLOPE_STEPS: [&str; 17] = [
+    "そうなれば", "そうなると", "そうすると", "やがて", "ついには", "最終的には", "いずれは", "その次は", "そのうち",
+    "lead to", "leads to", "will lead", "eventually", "next thing", "before you know it", "and then", "ultimately",
+];
+
+const DICHOTOMY_ENDS: [&str; 4] = ["しかない", "以外にない", "以外ありえない", "ほかない"];
+
+const DICHOTOMY_FIXED: [&str; 8] = [
+    "二択", "白か黒か", "0か100か", "敵か味方か",
+    "only two options", "only two choices", "with us or against us", "black and white",
+];
+
+// 説明に引用する文の長さ
+const EXCERPT_CHARS: usize = 40;
+
+const EITHER: [&str; 1] = ["either"];
+
+const UNIVERSALS: [&str; 18] = [
+    "みんな", "皆が", "誰もが", "誰も", "全員", "いつも", "必ず", "絶対に", "決して",
+    "always", "never", "everyone", "everybody", "nobody", "no one", "all people", "every time", "everything",
+];
+
+const SMALL_SAMPLES: [&str; 13] = [
+    "一度", "一回", "一人", "一例", "友達", "知り合い", "聞いた話",
+    "once", "one time", "my friend", "a friend", "someone i know", "i heard",
+];
+
+const AUTHORITIES: [&str; 14] = [
+    "専門家が", "専門家によると", "偉い人", "有名人", "教授が", "博士が", "科学者が", "権威",
+    "experts say", "expert says", "scientists say", "according to experts", "a famous", "authorities say",
+];
+
+/// 誤謬の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FallacyKind {
+    /// 人身攻撃
+    AdHominem,
+    /// 藁人形論法
+    StrawMan,
+    /// 滑り坂論法
+    SlipperySlope,
+    /// 誤った二分法
+    FalseDichotomy,
+    /// 早まった一般化
+    HastyGeneralization,
+    /// 権威に訴える論証
+    AppealToAuthority,
+}
+
+impl FallacyKind {
+    fn label(self) -> &'static str {
+        match self {
+            FallacyKind::AdHominem => "人身攻撃",
+            FallacyKind::StrawMan => "藁人形論法",
+            FallacyKind::SlipperySlope => "滑り坂論法",
+            FallacyKind::FalseDichotomy => "誤った二分法",
+            FallacyKind::HastyGeneralization => "早まった一般化",
+            FallacyKind::AppealToAuthority => "権威に訴える論証",
+        }
+    }
+
+    fn advice(self) -> &'static str {
+        match self {
+            FallacyKind::AdHominem => "人ではなく、主張そのものを検討しよう。",
+            FallacyKind::StrawMan => "相手の主張を、相手が認める形で言い直してみよう。",
+            FallacyKind::SlipperySlope => "一つひとつの「そうなれば」が本当に起こるか確かめよう。",
+            FallacyKind::FalseDichotomy => "第三の選択肢が無いか探してみよう。",
+            FallacyKind::HastyGeneralization => "例外は無いか、いくつの例から言えることかを考えよう。",
+            FallacyKind::AppealToAuthority => "誰が言ったかより、何を根拠に言ったかを見よう。",
+        }
+    }
+}
+
+/// 見つかった誤謬の疑い
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Fallacy {
+    pub kind: FallacyKind,
+    pub label: String,
+    /// 該当する文
+    pub text: String,
+    /// 決め手になった表現
+    pub cue: String,
+    /// 元テキストでの位置（文字単位、`end` は含まない）
+    pub start: usize,
+    pub end: usize,
+    /// 0.0〜1.0
+    pub confidence: f32,
+    /// キャラクターの口調での説明
+    pub explanation: String,
+}
+
+/// `KeywordMatcher` に登録する誤謬の手がかり
+pub fn vocabulary() -> impl Iterator<Item = (&'static str, Vocabulary)> {
+    let lexicon = [
+        (&INSULTS[..], FallacyCue::Insult),
+        (&REFERENCES[..], FallacyCue::Reference),
+        (&RESTATEMENTS[..], FallacyCue::Restatement),
+        (&SLOPE_STEPS[..], FallacyCue::SlopeStep),
+        (&DICHOTOMY_ENDS[..], FallacyCue::DichotomyEnd),
+        (&DICHOTOMY_FIXED[..], FallacyCue::DichotomyFixed),
+        (&EITHER[..], FallacyCue::Either),
+        (&UNIVERSALS[..], FallacyCue::Universal),
+        (&SMALL_SAMPLES[..], FallacyCue::SmallSample),
+        (&AUTHORITIES[..], FallacyCue::Authority),
+    ];
+    lexicon.into_iter().flat_map(|(words, cue)| {
+        words.iter().map(move |word| (*word, Vocabulary::Fallacy(cue)))
+    })
+}
+
+/// `scan` 済みの一致から誤謬の疑いを文ごとに探す。
+///
+/// 1つの文では種類ごとに1件だけ返す。滑り坂は連鎖の表現が
+/// 隣り合う2文の中に2つ以上あるときだけ数える。
+pub fn detect(text: &str, matches: &[KeywordMatch<'_>], character: Option<&Character>) -> Vec<Fallacy> {
+    let candidates = matches.iter()
+        .filter(|m| matches!(m.vocabulary, Vocabulary::Fallacy(_)) && m.is_whole_word(text));
+    let cues: Vec<(&KeywordMatch<'_>, FallacyCue)> = outermost(candidates).into_iter()
+        .filter_map(|m| match m.vocabulary {
+            Vocabulary::Fallacy(cue) => Some((m, *cue)),
+            _ => None,
+        })
+        .collect();
+    let sentences = NLPProcessor::sentence_spans(text);
+    let in_sentence = |(start, end): (usize, usize)| {
+        cues.iter().filter(move |(m, _)| m.start >= start && m.end <= end).copied()
+    };
+
+    let (emoji, voice) = match character {
+        Some(character) => (character.emoji.as_str(), voice(character)),
+        None => ("", Voice::default()),
+    };
+    let finding = |kind: FallacyKind, (start, end): (usize, usize), cue: &str, confidence: f32| {
+        let sentence = text[start..end].trim();
+        Fallacy {
+            kind,
+            label: kind.label().to_string(),
+            text: sentence.to_string(),
+            cue: cue.to_string(),
+            start: char_offset(text, start + (text[start..end].len() - text[start..end].trim_start().len())),
+            end: char_offset(text, start + text[start..end].trim_end().len()),
+            confidence: (confidence * 100.0).round() / 100.0,
+            explanation: format!(
+                "{} {}「{}」は{}かもしれない。{}",
+                emoji, voice.caution, excerpt(sentence, EXCERPT_CHARS), kind.label(), kind.advice()
+            ).trim_start().to_string(),
+        }
+    };
+
+    let mut findings = Vec::new();
+    let mut slope_until = 0;
+    for (i, &span) in sentences.iter().enumerate() {
+        let (start, end) = span;
+        let sentence = &text[start..end];
+        let found = |cue: FallacyCue| in_sentence(span).find(|(_, c)| *c == cue).map(|(m, _)| m);
+
+        if let Some(insult) = found(FallacyCue::Insult) {
+            let confidence = if found(FallacyCue::Reference).is_some() { 0.8 } else { 0.55 };
+            findings.push(finding(FallacyKind::AdHominem, span, insult.keyword, confidence));
+        }
+        if let Some(restatement) = found(FallacyCue::Restatement) {
+            findings.push(finding(FallacyKind::StrawMan, span, restatement.keyword, 0.6));
+        }
+
+        // 「AかBしかない」「either A or B」
+        let dichotomy = found(FallacyCue::DichotomyFixed).map(|m| (m, 0.75))
+            .or_else(|| found(FallacyCue::DichotomyEnd)
+                .filter(|m| offers_alternatives(&text[start..m.start]))
+                .map(|m| (m, 0.7)))
+            .or_else(|| found(FallacyCue::Either)
+                .filter(|m| text[m.end..end].to_lowercase().contains(" or "))
+                .map(|m| (m, 0.6)));
+        if let Some((m, confidence)) = dichotomy {
+            findings.push(finding(FallacyKind::FalseDichotomy, span, m.keyword, confidence));
+        }
+
+        // 疑問文の「いつも？」は一般化ではない
+        let is_question = sentence.trim_end().ends_with(['?', '？']);
+        if let Some(universal) = found(FallacyCue::Universal).filter(|_| !is_question) {
+            let confidence = if found(FallacyCue::SmallSample).is_some() { 0.7 } else { 0.35 };
+            findings.push(finding(FallacyKind::HastyGeneralization, span, universal.keyword, confidence));
+        }
+        if let Some(authority) = found(FallacyCue::Authority) {
+            findings.push(finding(FallacyKind::AppealToAuthority, span, authority.keyword, 0.6));
+        }
+
+        if start >= slope_until {
+            let window = (start, sentences.get(i + 1).map_or(end, |next| next.1));
+            let steps: Vec<&KeywordMatch<'_>> = in_sentence(window)
+                .filter(|(_, c)| *c == FallacyCue::SlopeStep)
+                .map(|(m, _)| m)
+                .collect();
+            if steps.len() >= 2 {
+                // 連鎖の最後の表現を含む文までを1件にする
+                let last = steps[steps.len() - 1];
+                let window = (start, sentences.iter().map(|s| s.1).find(|&e| e >= last.end).unwrap_or(window.1));
+                let confidence = (0.4 + 0.15 * (steps.len() - 1) as f32).min(0.9);
+                findings.push(finding(FallacyKind::SlipperySlope, window, steps[0].keyword, confidence));
+                slope_until = window.1;
+            }
+        }
+    }
+    findings
+}
+
+// 「しかない」の直前が「AかB」になっているか。読点より前や「確かに」「分から」
+// 「何か」のような二択でない「か」は数えない
+fn offers_alternatives(before: &str) -> bool {
+    let clause = before.rsplit(['、', '，', ',', '。', ' ', '　']).next().unwrap_or(before);
+    // 「AかBかしかない」
+    let clause = clause.strip_suffix('か').unwrap_or(clause);
+    clause.char_indices().filter(|&(_, c)| c == 'か').any(|(i, _)| {
+        let (first, second) = (&clause[..i], &clause[i + 'か'.len_utf8()..]);
+        !first.is_empty()
+            && !NOT_ALTERNATIVE_BEFORE.iter().any(|word| first.ends_with(word))
+            && second.chars().next().is_some_and(|c| !NOT_ALTERNATIVE_AFTER.contains(c))
+            && !second.contains('か')
+    })
+}
+
+// 「何か」「確か」「静か」などで「か」の前に来る字
+const NOT_ALTERNATIVE_BEFORE: [&str; 13] = ["何", "誰", "どこ", "いつ", "どれ", "確", "静", "豊", "愚", "僅", "細", "暖", "分"];
+
+// 「分からない」「確かに」「静かな」などで「か」の後に来る字
+const NOT_ALTERNATIVE_AFTER: &str = "いにならりるれろけっしも";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nlp::NLPProcessor;
+
+    fn kinds(text: &str) -> Vec<FallacyKind> {
+        NLPProcessor::new().fallacies(text, None).into_iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn false_dichotomy_needs_an_a_or_b_clause() {
+        assert_eq!(kinds("戦うか逃げるしかない。"), vec![FallacyKind::FalseDichotomy]);
+        assert_eq!(kinds("賛成か反対かしかない。"), vec![FallacyKind::FalseDichotomy]);
+        assert!(kinds("確かに、待つしかない。").is_empty());
+        assert!(kinds("分からないけど、やるしかない。").is_empty());
+        assert!(kinds("何か手を打つしかない。").is_empty());
+        assert_eq!(kinds("You are either with us or against us."), vec![FallacyKind::FalseDichotomy]);
+    }
+
+    #[test]
+    fn ordinary_words_are_not_cues() {
+        assert!(kinds("皆さん、こんにちは。").is_empty());
+        assert!(kinds("有名な本を読んだ。").is_empty());
+        assert_eq!(kinds("皆がそう言っている。"), vec![FallacyKind::HastyGeneralization]);
+    }
+
+    #[test]
+    fn slippery_slope_needs_a_chain() {
+        assert_eq!(
+            kinds("これを許せば、やがて規則は無視され、ついには社会が崩壊する。"),
+            vec![FallacyKind::SlipperySlope]
+        );
+        assert!(kinds("やがて春が来る。").is_empty());
+    }
+
+    #[test]
+    fn positions_are_in_characters() {
+        let text = "はじめに。戦うか逃げるしかない。";
+        let fallacy = &NLPProcessor::new().fallacies(text, None)[0];
+        let sentence: String = text.chars().skip(fallacy.start).take(fallacy.end - fallacy.start).collect();
+        assert_eq!(sentence, "戦うか逃げるしかない。");
+    }
+}
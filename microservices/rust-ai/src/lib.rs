@@ -1,7 +1,32 @@
 pub mod ai_engine;
+pub mod argument;
+pub mod api;
+pub mod batch;
 pub mod character_ai;
+pub mod cluster;
+pub mod config;
+pub mod conviction;
+pub mod error;
+pub mod fallacy;
+pub mod matcher;
+pub mod metrics;
+pub mod moderation;
 pub mod nlp;
+pub mod openapi;
+pub mod question;
+pub mod redaction;
+pub mod schools;
+pub mod server;
+pub mod session;
+pub mod similarity;
+pub mod socratic;
+pub mod stats;
+pub mod storage;
+pub mod summary;
+pub mod trends;
 
-pub use ai_engine::AIEngine;
+pub use service_common::{auth, rate_limit, telemetry};
+
+pub use ai_engine::{AIEngine, ChatResponse, EngineSnapshot, PhilosophyAnalysis, PhilosophyAnalysisV2};
 pub use character_ai::{Character, CharacterPersonality};
 pub use nlp::NLPProcessor;
@@ -1,140 +1,88 @@
-use warp::Filter;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use uuid::Uuid;
-use chrono::{DateTime, Utc};
-
-mod ai_engine;
-mod character_ai;
-mod nlp;
-
-use ai_engine::AIEngine;
-use character_ai::{Character, CharacterPersonality};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ChatRequest {
-    character: String,
-    message: String,
-    context: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ChatResponse {
-    character: String,
-    response: String,
-    emotion: String,
-    confidence: f32,
-    timestamp: DateTime<Utc>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct PhilosophyAnalysis {
-    id: String,
-    text: String,
-    themes: Vec<String>,
-    sentiment: String,
-    complexity: f32,
-    recommendations: Vec<String>,
-}
+use std::sync::Arc;
+use clap::Parser;
+use philosophy_ai::config::{Cli, Config};
+use philosophy_ai::{redaction, server, similarity, storage, telemetry};
+use philosophy_ai::moderation::Moderator;
+use philosophy_ai::redaction::Redactor;
+use philosophy_ai::similarity::SimilarityIndex;
+use philosophy_ai::AIEngine;
 
 #[tokio::main]
 async fn main() {
-    println!("🦀 Philosophy AI Server starting...");
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ Invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    telemetry::init(&config.logging);
+    tracing::info!("🦀 Philosophy AI Server starting...");
+
+    let store = match storage::open_store(&config.storage).await {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!(backend = %config.storage.backend, error = %e, "❌ Failed to open analysis storage");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(backend = %config.storage.backend, "💾 Analysis storage opened");
+
+    let index = match similarity::index_path(&config.storage.backend, &config.storage.data_dir) {
+        Some(path) => match SimilarityIndex::open(&path) {
+            Ok(index) => index,
+            Err(e) => {
+                tracing::error!(path = %path.display(), error = %e, "❌ Failed to open similarity index");
+                std::process::exit(1);
+            }
+        },
+        None => SimilarityIndex::in_memory(),
+    };
+
+    let moderator = match Moderator::new(&config.moderation) {
+        Ok(moderator) => moderator,
+        Err(e) => {
+            tracing::error!(error = %e, "❌ Invalid configuration: invalid `moderation.rules`");
+            std::process::exit(2);
+        }
+    };
+
+    let redactor = Arc::new(Redactor::new(&config.redaction));
+    redaction::install(redactor.clone());
 
     // Initialize AI engine
-    let ai_engine = AIEngine::new();
-    let ai_filter = warp::any().map(move || ai_engine.clone());
-
-    // CORS
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
-
-    // Health check
-    let health = warp::path("health")
-        .and(warp::get())
-        .map(|| {
-            warp::reply::json(&serde_json::json!({
-                "status": "healthy",
-                "service": "philosophy-ai",
-                "version": "0.1.0"
-            }))
-        });
-
-    // Character chat endpoint
-    let chat = warp::path("chat")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(ai_filter.clone())
-        .and_then(handle_chat);
-
-    // Philosophy analysis
-    let analyze = warp::path("analyze")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(ai_filter.clone())
-        .and_then(handle_analysis);
-
-    // Character personalities
-    let personalities = warp::path("personalities")
-        .and(warp::get())
-        .and(ai_filter.clone())
-        .and_then(get_personalities);
-
-    // Wisdom generation
-    let wisdom = warp::path("wisdom")
-        .and(warp::get())
-        .and(warp::query())
-        .and(ai_filter.clone())
-        .and_then(generate_wisdom);
-
-    let routes = health
-        .or(chat)
-        .or(analyze)
-        .or(personalities)
-        .or(wisdom)
-        .with(cors);
-
-    // Get port from environment variable
-    let port: u16 = std::env::var("PORT")
-        .unwrap_or_else(|_| "3001".to_string())
-        .parse()
-        .unwrap_or(3001);
-
-    println!("🚀 AI Server running on http://0.0.0.0:{}", port);
-    warp::serve(routes)
-        .run(([0, 0, 0, 0], port))
-        .await;
-}
-
-async fn handle_chat(request: ChatRequest, ai_engine: AIEngine) -> Result<impl warp::Reply, warp::Rejection> {
-    println!("Chat request: {:?}", request);
-    
-    let response = ai_engine.chat_with_character(&request.character, &request.message, request.context.as_deref()).await;
-    
-    Ok(warp::reply::json(&response))
-}
-
-async fn handle_analysis(request: serde_json::Value, ai_engine: AIEngine) -> Result<impl warp::Reply, warp::Rejection> {
-    let text = request["text"].as_str().unwrap_or("");
-    let analysis = ai_engine.analyze_philosophy(text).await;
-    
-    Ok(warp::reply::json(&analysis))
-}
-
-async fn get_personalities(ai_engine: AIEngine) -> Result<impl warp::Reply, warp::Rejection> {
-    let personalities = ai_engine.get_character_personalities().await;
-    Ok(warp::reply::json(&personalities))
-}
-
-async fn generate_wisdom(params: HashMap<String, String>, ai_engine: AIEngine) -> Result<impl warp::Reply, warp::Rejection> {
-    let theme = params.get("theme").unwrap_or(&"life".to_string()).clone();
-    let wisdom = ai_engine.generate_wisdom(&theme).await;
-    
-    Ok(warp::reply::json(&serde_json::json!({
-        "theme": theme,
-        "wisdom": wisdom,
-        "timestamp": Utc::now()
-    })))
+    let ai_engine = AIEngine::new()
+        .with_store(store)
+        .with_similarity_index(index)
+        .with_moderator(moderator)
+        .with_redactor(redactor);
+
+    // 索引ファイルが無い（初回起動など）ときは保存済みの分析から作る
+    if ai_engine.similarity_index_len() == 0 {
+        match ai_engine.rebuild_similarity_index().await {
+            Ok(0) => {}
+            Ok(indexed) => tracing::info!(indexed, "🔎 Indexed stored analyses for similarity search"),
+            Err(e) => tracing::warn!(error = %e, "⚠️ Failed to build similarity index"),
+        }
+    }
+
+    if !config.auth.enabled {
+        tracing::warn!("⚠️ Authentication is disabled; every route is public (set `auth.enabled`)");
+    }
+
+    let addr = config.socket_addr();
+    let routes = server::app(ai_engine, config.server_config());
+
+    tracing::info!("🚀 AI Server running on http://{}", addr);
+    if let Err(e) = telemetry::serve(routes, addr, server::route_label).await {
+        tracing::error!(%addr, error = %e, "❌ Server error");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use crate::argument::DiscourseMarker;
+use crate::conviction::ConvictionMarker;
+use crate::fallacy::FallacyCue;
+use crate::moderation::ModerationCategory;
+
+/// キーワードが属する語彙
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Vocabulary {
+    /// 感情語（"positive" / "negative"）
+    Sentiment(String),
+    /// テーマ語（"technology" / "philosophy" など）
+    Theme(String),
+    StopWord,
+    /// プログラミング言語などの専門用語
+    Tech,
+    /// 確信度の手がかり（ぼかし・断定・根拠・反語）
+    Conviction(ConvictionMarker),
+    /// 哲学の学派の特徴語（"stoic" / "buddhist" など）
+    School(String),
+    /// 論証の談話標識（なぜなら / therefore / しかし）
+    Discourse(DiscourseMarker),
+    /// 誤謬の手がかり（バカ / しかない / experts say）
+    Fallacy(FallacyCue),
+    /// モデレーションの語彙（`Moderator` だけが使う）
+    Moderation(ModerationCategory),
+    /// 伏せる人名（`Redactor` だけが使う）
+    PersonName,
+}
+
+/// テキスト中で見つかったキーワード（`start..end` は元テキストのバイト位置）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeywordMatch<'m> {
+    pub keyword: &'m str,
+    pub vocabulary: &'m Vocabulary,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl KeywordMatch<'_> {
+    /// 英字のキーワードが単語の途中（"mighty" の中の "might"）で一致していないか
+    pub fn is_whole_word(&self, text: &str) -> bool {
+        if !self.keyword.is_ascii() {
+            return true;
+        }
+        let before = text[..self.start].chars().next_back();
+        let after = text[self.end..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric()) && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+/// バイト位置を文字単位の位置に変換する（APIで返す位置は文字単位）
+pub fn char_offset(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
+
+/// 重なり合う一致は、先に始まる長いほうだけを残す（「かもしれない」の中の「かも」などを除く）
+pub fn outermost<'a, 'm>(matches: impl IntoIterator<Item = &'a KeywordMatch<'m>>) -> Vec<&'a KeywordMatch<'m>> {
+    let mut candidates: Vec<&KeywordMatch<'m>> = matches.into_iter().collect();
+    candidates.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
+
+    let mut kept = Vec::new();
+    let mut covered_until = 0;
+    for m in candidates {
+        if m.start < covered_until {
+            continue;
+        }
+        covered_until = m.end;
+        kept.push(m);
+    }
+    kept
+}
+
+/// 全語彙をまとめた Aho-Corasick オートマトン。
+///
+/// 構築はエンジン起動時に一度だけ行い、`scan` はテキストを1回なめるだけで
+/// 全語彙の一致を（重なりも含めて）返す。英字は大文字小文字を区別しない。
+#[derive(Clone)]
+pub struct KeywordMatcher {
+    automaton: AhoCorasick,
+    keywords: Vec<String>,
+    vocabularies: Vec<Vec<Vocabulary>>,
+}
+
+impl KeywordMatcher {
+    pub fn new<I, S>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (S, Vocabulary)>,
+        S: AsRef<str>,
+    {
+        let mut keywords: Vec<String> = Vec::new();
+        let mut vocabularies: Vec<Vec<Vocabulary>> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        // 同じキーワードが複数の語彙に属する場合は1パターンにまとめる
+        for (keyword, vocabulary) in entries {
+            let keyword = keyword.as_ref().to_lowercase();
+            if keyword.is_empty() {
+                continue;
+            }
+            let id = *index.entry(keyword.clone()).or_insert_with(|| {
+                keywords.push(keyword.clone());
+                vocabularies.push(Vec::new());
+                keywords.len() - 1
+            });
+            if !vocabularies[id].contains(&vocabulary) {
+                vocabularies[id].push(vocabulary);
+            }
+        }
+
+        let automaton = AhoCorasickBuilder::new()
+            .match_kind(MatchKind::Standard)
+            .ascii_case_insensitive(true)
+            .build(&keywords)
+            .expect("keyword automaton should build");
+
+        KeywordMatcher {
+            automaton,
+            keywords,
+            vocabularies,
+        }
+    }
+
+    /// 全語彙の一致を出現順に返す
+    pub fn scan(&self, text: &str) -> Vec<KeywordMatch<'_>> {
+        let mut matches = Vec::new();
+        for m in self.automaton.find_overlapping_iter(text) {
+            let id = m.pattern().as_usize();
+            for vocabulary in &self.vocabularies[id] {
+                matches.push(KeywordMatch {
+                    keyword: &self.keywords[id],
+                    vocabulary,
+                    start: m.start(),
+                    end: m.end(),
+                });
+            }
+        }
+        matches
+    }
+
+    /// `scan` と同じだが、英字以外（Ö・Ж など）も大文字小文字を区別しない。
+    /// 小文字にしたテキストをなめ、位置は元テキストのものに戻して返す。
+    pub fn scan_ignoring_case(&self, text: &str) -> Vec<KeywordMatch<'_>> {
+        if text.is_ascii() {
+            return self.scan(text);
+        }
+        let mut folded = String::with_capacity(text.len());
+        // `folded` のバイト位置ごとの、元テキストでの文字の開始位置
+        let mut origin: Vec<usize> = Vec::with_capacity(text.len() + 1);
+        for (offset, c) in text.char_indices() {
+            folded.extend(c.to_lowercase());
+            origin.resize(folded.len(), offset);
+        }
+        origin.push(text.len());
+
+        self.scan(&folded).into_iter()
+            .map(|m| KeywordMatch { start: origin[m.start], end: origin[m.end], ..m })
+            .collect()
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.keywords.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(entries: &[(&str, Vocabulary)]) -> KeywordMatcher {
+        KeywordMatcher::new(entries.iter().cloned())
+    }
+
+    #[test]
+    fn scan_returns_overlapping_matches_with_byte_offsets() {
+        let matcher = matcher(&[("かも", Vocabulary::StopWord), ("かもしれない", Vocabulary::Tech)]);
+        let text = "雨かもしれない";
+        let matches = matcher.scan(text);
+        assert_eq!(matches.len(), 2);
+        for m in &matches {
+            assert_eq!(&text[m.start..m.end], m.keyword);
+        }
+        assert_eq!(char_offset(text, matches[0].start), 1);
+    }
+
+    #[test]
+    fn a_keyword_in_several_vocabularies_matches_once_per_vocabulary() {
+        let matcher = matcher(&[("rust", Vocabulary::Tech), ("Rust", Vocabulary::Theme("technology".to_string()))]);
+        assert_eq!(matcher.pattern_count(), 1);
+        let matches = matcher.scan("I like RUST");
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.start == 7 && m.end == 11));
+    }
+
+    #[test]
+    fn scan_ignoring_case_maps_offsets_back_to_the_original_text() {
+        let matcher = matcher(&[("ärzte", Vocabulary::PersonName)]);
+        let text = "Die ÄRZTE sagen";
+        assert!(matcher.scan(text).is_empty());
+        let matches = matcher.scan_ignoring_case(text);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(&text[matches[0].start..matches[0].end], "ÄRZTE");
+    }
+
+    #[test]
+    fn whole_words_and_outermost_matches() {
+        let matcher = matcher(&[("might", Vocabulary::StopWord), ("かも", Vocabulary::StopWord), ("かもしれない", Vocabulary::StopWord), ("しれないと", Vocabulary::StopWord)]);
+        let text = "mighty";
+        assert!(!matcher.scan(text)[0].is_whole_word(text));
+        let text = "it might";
+        assert!(matcher.scan(text)[0].is_whole_word(text));
+
+        let text = "かもしれないと思う";
+        let matches = matcher.scan(text);
+        let kept: Vec<&str> = outermost(&matches).into_iter().map(|m| m.keyword).collect();
+        assert_eq!(kept, vec!["かもしれない"]);
+    }
+}
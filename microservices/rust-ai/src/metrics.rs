@@ -0,0 +1,39 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use service_common::metrics::{escape, header, http};
+use crate::stats::StatsSnapshot;
+
+pub use service_common::metrics::CONTENT_TYPE;
+
+/// `/metrics` の本文（Prometheus のテキスト形式）。
+///
+/// メトリクス名は `philosophy_ai_` で始まり、アラートから参照されるので変えないこと。
+pub fn render(stats: &StatsSnapshot) -> String {
+    let mut out = String::new();
+    header(&mut out, "philosophy_ai_build_info", "gauge", "Build information.");
+    let _ = writeln!(out, "philosophy_ai_build_info{{version=\"{}\"}} 1", env!("CARGO_PKG_VERSION"));
+
+    http().render(&mut out, "philosophy_ai");
+
+    header(&mut out, "philosophy_ai_chats_total", "counter", "Chat replies generated.");
+    let _ = writeln!(out, "philosophy_ai_chats_total {}", stats.chats);
+    header(&mut out, "philosophy_ai_analyses_total", "counter", "Philosophy analyses performed.");
+    let _ = writeln!(out, "philosophy_ai_analyses_total {}", stats.analyses);
+
+    header(&mut out, "philosophy_ai_character_chats_total", "counter", "Chat replies by character.");
+    for (character, count) in sorted(&stats.characters) {
+        let _ = writeln!(out, "philosophy_ai_character_chats_total{{character=\"{}\"}} {}", escape(character), count);
+    }
+    header(&mut out, "philosophy_ai_sentiments_total", "counter", "Sentiment labels of chat messages and analyzed texts.");
+    for (sentiment, count) in sorted(&stats.sentiments) {
+        let _ = writeln!(out, "philosophy_ai_sentiments_total{{sentiment=\"{}\"}} {}", escape(sentiment), count);
+    }
+    header(&mut out, "philosophy_ai_wisdom_lookups_total", "counter", "Wisdom lookups by result (hit or fallback).");
+    let _ = writeln!(out, "philosophy_ai_wisdom_lookups_total{{result=\"hit\"}} {}", stats.wisdom_hits);
+    let _ = writeln!(out, "philosophy_ai_wisdom_lookups_total{{result=\"fallback\"}} {}", stats.wisdom_fallbacks);
+    out
+}
+
+fn sorted(counts: &std::collections::HashMap<String, u64>) -> BTreeMap<&str, u64> {
+    counts.iter().map(|(key, count)| (key.as_str(), *count)).collect()
+}
@@ -0,0 +1,342 @@
+use std::sync::{Arc, OnceLock};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::character_ai::Character;
+use crate::config::ModerationSettings;
+use crate::matcher::{char_offset, outermost, KeywordMatcher, Vocabulary};
+use crate::question::{voice, Voice};
+
+/// モデレーションで引っかかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationCategory {
+    /// 差別語
+    Slur,
+    /// 危害を加えるという脅し
+    Threat,
+    /// 相手への罵倒・嫌がらせ
+    Harassment,
+    /// 自傷・自殺をほのめかす言葉
+    SelfHarm,
+    /// 設定のブロックリスト
+    Blocklist,
+}
+
+impl ModerationCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModerationCategory::Slur => "slur",
+            ModerationCategory::Threat => "threat",
+            ModerationCategory::Harassment => "harassment",
+            ModerationCategory::SelfHarm => "self_harm",
+            ModerationCategory::Blocklist => "blocklist",
+        }
+    }
+}
+
+/// モデレーションの判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// 通常どおり応答する
+    Allow,
+    /// 応答しない（チャットは定型の返答、分析は拒否）
+    Block,
+    /// 自傷の兆候。キャラクターは支援的な定型の返答に切り替える
+    Crisis,
+}
+
+/// 引っかかった表現
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ModerationFlag {
+    pub category: ModerationCategory,
+    pub text: String,
+    /// 元テキストでの位置（文字単位、`end` は含まない）
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ModerationVerdict {
+    pub action: ModerationAction,
+    pub categories: Vec<ModerationCategory>,
+    pub flags: Vec<ModerationFlag>,
+}
+
+impl Default for ModerationVerdict {
+    fn default() -> Self {
+        Self::allowed()
+    }
+}
+
+impl ModerationVerdict {
+    pub fn allowed() -> Self {
+        ModerationVerdict { action: ModerationAction::Allow, categories: Vec::new(), flags: Vec::new() }
+    }
+
+    pub fn is_allowed(&self) -> bool {
+        self.action == ModerationAction::Allow
+    }
+}
+
+const SLURS: [&str; 11] = [
+    "ガイジ", "池沼", "つんぼ", "支那人",
+    "retard", "retarded", "faggot", "tranny", "nigger", "chink", "spic",
+];
+
+const THREATS: [&str; 16] = [
+    "殺してやる", "ぶっ殺", "殺すぞ", "刺してやる", "刺すぞ", "燃やしてやる", "爆破してやる", "爆破予告",
+    "kill you", "shoot you", "stab you", "hurt you", "beat you up", "you're dead", "find where you live", "bomb your",
+];
+
+const HARASSMENT: [&str; 11] = [
+    "死ね", "消えろ", "クズが", "ゴミ人間", "きもい", "キモい",
+    "kys", "kill yourself", "go die", "nobody wants you", "worthless piece",
+];
+
+const SELF_HARM: [&str; 20] = [
+    "死にたい", "消えたい", "生きていたくない", "生きるのがつらい", "生きるのが辛い", "自殺したい",
+    "リスカ", "リストカット", "首を吊", "飛び降りたい", "もう終わりにしたい",
+    "kill myself", "want to die", "end my life", "suicidal", "self-harm", "cut myself", "hurt myself",
+    "no reason to live", "better off dead",
+];
+
+// 語彙だけでは拾えない言い回し
+const THREAT_PATTERNS: [&str; 2] = [
+    r"(殺|刺|殴|燃や)(して|す|る)(やる|ぞ)",
+    r"\bi(?:'ll| will|'m going to|'m gonna| am going to) (?:kill|hurt|stab|shoot|find) (?:you|u)\b",
+];
+
+const SELF_HARM_PATTERNS: [&str; 2] = [
+    r"(自殺|死ぬ)(しよう|する|つもり|しかない)",
+    r"\b(?:want|going|plan(?:ning)?) to (?:die|kill myself|end it all)\b",
+];
+
+/// 正規表現のルール（設定ファイルの `moderation.rules`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModerationRule {
+    pub category: ModerationCategory,
+    pub pattern: String,
+}
+
+/// チャットと分析の前に置くモデレーション。
+///
+/// 組み込みの語彙と正規表現に、設定のブロックリストとルールを足して判定する。
+/// 全角英数字は半角に、英字は小文字にそろえてから照合する。
+/// 自傷の兆候は他の理由より優先し、応答を止めずに支援的な返答に切り替える。
+pub struct Moderator {
+    enabled: bool,
+    matcher: KeywordMatcher,
+    patterns: Vec<(ModerationCategory, Regex)>,
+}
+
+impl Moderator {
+    pub fn new(settings: &ModerationSettings) -> Result<Self, regex::Error> {
+        let lexicon = [
+            (&SLURS[..], ModerationCategory::Slur),
+            (&THREATS[..], ModerationCategory::Threat),
+            (&HARASSMENT[..], ModerationCategory::Harassment),
+            (&SELF_HARM[..], ModerationCategory::SelfHarm),
+        ];
+        let builtin = lexicon.into_iter().flat_map(|(words, category)| {
+            words.iter().map(move |word| (word.to_string(), Vocabulary::Moderation(category)))
+        });
+        let blocklist = settings.blocklist.iter()
+            .map(|word| (normalize(word), Vocabulary::Moderation(ModerationCategory::Blocklist)));
+        let matcher = KeywordMatcher::new(builtin.chain(blocklist));
+
+        let builtin = [
+            (&THREAT_PATTERNS[..], ModerationCategory::Threat),
+            (&SELF_HARM_PATTERNS[..], ModerationCategory::SelfHarm),
+        ];
+        let builtin = builtin.into_iter()
+            .flat_map(|(patterns, category)| patterns.iter().map(move |pattern| (category, pattern.to_string())));
+        let configured = settings.rules.iter().map(|rule| (rule.category, rule.pattern.clone()));
+        let patterns = builtin.chain(configured)
+            .map(|(category, pattern)| Ok((category, Regex::new(&format!("(?i){}", pattern))?)))
+            .collect::<Result<_, regex::Error>>()?;
+
+        Ok(Moderator { enabled: settings.enabled, matcher, patterns })
+    }
+
+    pub fn moderate(&self, text: &str) -> ModerationVerdict {
+        if !self.enabled {
+            return ModerationVerdict::allowed();
+        }
+        let normalized = normalize(text);
+
+        let matches = self.matcher.scan(&normalized);
+        let candidates = matches.iter()
+            .filter(|m| m.is_whole_word(&normalized) && is_whole_japanese_word(&normalized, m.start, m.end));
+        let mut flags: Vec<(ModerationCategory, usize, usize)> = outermost(candidates).into_iter()
+            .filter_map(|m| match m.vocabulary {
+                Vocabulary::Moderation(category) => Some((*category, m.start, m.end)),
+                _ => None,
+            })
+            .collect();
+        for (category, pattern) in &self.patterns {
+            for m in pattern.find_iter(&normalized) {
+                if !flags.iter().any(|&(c, start, end)| c == *category && start < m.end() && m.start() < end) {
+                    flags.push((*category, m.start(), m.end()));
+                }
+            }
+        }
+        if flags.is_empty() {
+            return ModerationVerdict::allowed();
+        }
+        flags.sort_by_key(|&(_, start, end)| (start, end));
+
+        // 正規化しても文字数は変わらないので、位置は元テキストにそのまま使える
+        let flags: Vec<ModerationFlag> = flags.into_iter()
+            .map(|(category, start, end)| {
+                let (start, end) = (char_offset(&normalized, start), char_offset(&normalized, end));
+                ModerationFlag {
+                    category,
+                    text: text.chars().skip(start).take(end - start).collect(),
+                    start,
+                    end,
+                }
+            })
+            .collect();
+        let mut categories: Vec<ModerationCategory> = Vec::new();
+        for flag in &flags {
+            if !categories.contains(&flag.category) {
+                categories.push(flag.category);
+            }
+        }
+        let action = if categories.contains(&ModerationCategory::SelfHarm) {
+            ModerationAction::Crisis
+        } else {
+            ModerationAction::Block
+        };
+
+        ModerationVerdict { action, categories, flags }
+    }
+}
+
+impl Moderator {
+    /// 既定の設定の `Moderator`。正規表現は一度だけ組み立てて共有する
+    pub fn shared() -> Arc<Moderator> {
+        static DEFAULT: OnceLock<Arc<Moderator>> = OnceLock::new();
+        DEFAULT.get_or_init(|| Arc::new(Moderator::default())).clone()
+    }
+}
+
+impl Default for Moderator {
+    fn default() -> Self {
+        Self::new(&ModerationSettings::default()).expect("built-in moderation patterns should compile")
+    }
+}
+
+// 命令形の語尾。後ろに終助詞以外のひらがなが続くと活用の途中になる
+const IMPERATIVE_ENDINGS: &str = "えけせてねへめれげぜでべぺろ";
+const SENTENCE_FINAL_PARTICLES: &str = "よやぞわっ";
+
+// 日本語の語彙が語の途中で一致していないか。
+// カタカナ語は前後にカタカナが続かないこと（「ガイジン」の中の「ガイジ」を除く）。
+// 命令形で終わる語は、後ろに終助詞以外のひらがなが続けば命令ではない（「死ねない」「死ねる」「死ねば」）
+fn is_whole_japanese_word(text: &str, start: usize, end: usize) -> bool {
+    let word = &text[start..end];
+    let (Some(first), Some(last)) = (word.chars().next(), word.chars().next_back()) else {
+        return true;
+    };
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+
+    if is_katakana(first) && before.is_some_and(is_katakana) {
+        return false;
+    }
+    if is_katakana(last) && after.is_some_and(is_katakana) {
+        return false;
+    }
+    if IMPERATIVE_ENDINGS.contains(last) && after.is_some_and(|c| is_hiragana(c) && !SENTENCE_FINAL_PARTICLES.contains(c)) {
+        return false;
+    }
+    true
+}
+
+fn is_hiragana(c: char) -> bool {
+    ('\u{3041}'..='\u{3096}').contains(&c)
+}
+
+fn is_katakana(c: char) -> bool {
+    ('\u{30a1}'..='\u{30fa}').contains(&c) || c == 'ー'
+}
+
+// 全角英数字・記号を半角に、英字を小文字にそろえる（1文字は1文字のまま）
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c })
+        .collect()
+}
+
+/// 自傷の兆候があったときの、支援的な定型の返答（キャラクターの冗談めいた口調は使わない）
+pub fn crisis_response(character: Option<&Character>, english: bool) -> String {
+    let emoji = character.map_or("", |c| c.emoji.as_str());
+    let message = if english {
+        "Thank you for telling me. It sounds like you are going through something really painful, \
+         and you don't have to carry it alone. Please reach out to someone you trust or a crisis line. \
+         In Japan you can call TELL Lifeline (03-5774-0992) or いのちの電話 (0570-783-556). \
+         If you are in immediate danger, call your local emergency number (119 in Japan)."
+    } else {
+        "話してくれてありがとう。今とてもつらい気持ちなんだね。あなたの気持ちは大切で、ひとりで抱えなくていいんだよ。\
+         信頼できる人や、相談窓口に話してみてほしい。「いのちの電話」（0570-783-556）や\
+         「よりそいホットライン」（0120-279-338）につながるよ。今すぐ危険なときは 119 に電話してね。"
+    };
+    format!("{} {}", emoji, message).trim_start().to_string()
+}
+
+/// 応答しないと判断したときの定型の返答
+pub fn blocked_response(character: Option<&Character>) -> String {
+    let (emoji, caution) = match character {
+        Some(character) => (character.emoji.as_str(), voice(character).caution),
+        None => ("", Voice::default().caution),
+    };
+    format!("{} {}その言葉には答えられないよ。誰かを傷つける言葉ではなく、あなたの考えを聞かせてほしい。", emoji, caution)
+        .trim_start()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(text: &str) -> ModerationAction {
+        Moderator::shared().moderate(text).action
+    }
+
+    #[test]
+    fn blocks_imperatives_but_not_their_conjugations() {
+        assert_eq!(action("お前なんか死ね"), ModerationAction::Block);
+        assert_eq!(action("死ねよ"), ModerationAction::Block);
+        assert_eq!(action("まだ死ねない"), ModerationAction::Allow);
+        assert_eq!(action("人はいつか死ねる"), ModerationAction::Allow);
+    }
+
+    #[test]
+    fn ignores_katakana_words_that_contain_an_entry() {
+        assert_eq!(action("ガイジンという言葉"), ModerationAction::Allow);
+        assert_eq!(action("あいつはガイジだ"), ModerationAction::Block);
+    }
+
+    #[test]
+    fn self_harm_switches_to_crisis() {
+        let verdict = Moderator::shared().moderate("もう死にたい");
+        assert_eq!(verdict.action, ModerationAction::Crisis);
+        assert_eq!(verdict.categories, vec![ModerationCategory::SelfHarm]);
+    }
+
+    #[test]
+    fn normalizes_full_width_text() {
+        let verdict = Moderator::shared().moderate("Ｉ will ＫＩＬＬ you");
+        assert_eq!(verdict.action, ModerationAction::Block);
+        assert!(verdict.categories.contains(&ModerationCategory::Threat));
+    }
+}
@@ -1,10 +1,46 @@
-use std::collections::HashMap;
-use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::argument::{self, ArgumentStructure};
+use crate::character_ai::Character;
+use crate::conviction::{self, ConvictionScore};
+use crate::fallacy::{self, Fallacy};
+use crate::matcher::{KeywordMatch, KeywordMatcher, Vocabulary};
+use crate::question::content_words;
+use crate::schools::{self, SchoolScore};
+use crate::summary::{self, Summary};
+
+const STOP_WORDS: [&str; 43] = [
+    "の", "は", "が", "を", "に", "で", "と", "から", "まで",
+    "です", "である", "ます", "した", "する", "される",
+    "この", "その", "あの", "どの", "これ", "それ", "あれ", "どれ",
+    "the", "is", "at", "which", "on", "and", "a", "an", "as", "are",
+    "was", "were", "been", "be", "have", "has", "had", "do", "does", "did",
+];
+
+// 専門的なキーワード（テーマ "technology" に加算する）
+const TECH_TERMS: [&str; 7] = ["rust", "go", "javascript", "python", "haskell", "programming", "code"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SentimentBreakdown {
+    pub label: String,
+    pub positive: usize,
+    pub negative: usize,
+    pub exclamations: usize,
+    pub questions: usize,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ThemeScore {
+    pub theme: String,
+    pub score: usize,
+    pub matched: Vec<String>,
+}
 
 #[derive(Clone)]
 pub struct NLPProcessor {
-    sentiment_keywords: HashMap<String, Vec<String>>,
-    theme_keywords: HashMap<String, Vec<String>>,
+    matcher: KeywordMatcher,
 }
 
 impl NLPProcessor {
@@ -51,34 +87,61 @@ impl NLPProcessor {
             "つながり".to_string(), "絆".to_string(), "協力".to_string(),
         ]);
 
+        let sentiment_entries = sentiment_keywords.into_iter().flat_map(|(label, words)| {
+            words.into_iter().map(move |word| (word, Vocabulary::Sentiment(label.clone())))
+        });
+        let theme_entries = theme_keywords.into_iter().flat_map(|(theme, words)| {
+            words.into_iter().map(move |word| (word, Vocabulary::Theme(theme.clone())))
+        });
+        let stop_entries = STOP_WORDS.iter().map(|word| (word.to_string(), Vocabulary::StopWord));
+        let tech_entries = TECH_TERMS.iter().map(|word| (word.to_string(), Vocabulary::Tech));
+        let conviction_entries = conviction::vocabulary().map(|(word, vocabulary)| (word.to_string(), vocabulary));
+        let school_entries = schools::vocabulary().map(|(word, vocabulary)| (word.to_string(), vocabulary));
+        let discourse_entries = argument::vocabulary().map(|(word, vocabulary)| (word.to_string(), vocabulary));
+        let fallacy_entries = fallacy::vocabulary().map(|(word, vocabulary)| (word.to_string(), vocabulary));
+
         NLPProcessor {
-            sentiment_keywords,
-            theme_keywords,
+            matcher: KeywordMatcher::new(
+                sentiment_entries
+                    .chain(theme_entries)
+                    .chain(stop_entries)
+                    .chain(tech_entries)
+                    .chain(conviction_entries)
+                    .chain(school_entries)
+                    .chain(discourse_entries)
+                    .chain(fallacy_entries),
+            ),
         }
     }
 
+    /// 全語彙のキーワード一致を1回の走査で求める
+    pub fn scan(&self, text: &str) -> Vec<KeywordMatch<'_>> {
+        self.matcher.scan(text)
+    }
+
     pub fn analyze_sentiment(&self, text: &str) -> String {
-        let text_lower = text.to_lowercase();
-        let mut positive_score = 0;
-        let mut negative_score = 0;
-
-        // ポジティブキーワードのカウント
-        if let Some(positive_words) = self.sentiment_keywords.get("positive") {
-            for word in positive_words {
-                if text_lower.contains(&word.to_lowercase()) {
-                    positive_score += 1;
-                }
-            }
-        }
+        self.sentiment_breakdown(text).label
+    }
 
-        // ネガティブキーワードのカウント
-        if let Some(negative_words) = self.sentiment_keywords.get("negative") {
-            for word in negative_words {
-                if text_lower.contains(&word.to_lowercase()) {
-                    negative_score += 1;
-                }
+    /// 感情分析の内訳（キーワード数・記号数・-1.0〜1.0のスコア）
+    pub fn sentiment_breakdown(&self, text: &str) -> SentimentBreakdown {
+        self.sentiment_breakdown_with(text, &self.scan(text))
+    }
+
+    /// `scan` 済みの一致から感情分析の内訳を求める
+    pub fn sentiment_breakdown_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> SentimentBreakdown {
+        // キーワードは種類ごとに1回だけ数える
+        let mut positive_words = HashSet::new();
+        let mut negative_words = HashSet::new();
+        for m in matches {
+            match m.vocabulary {
+                Vocabulary::Sentiment(label) if label == "positive" => { positive_words.insert(m.keyword); }
+                Vocabulary::Sentiment(label) if label == "negative" => { negative_words.insert(m.keyword); }
+                _ => {}
             }
         }
+        let mut positive_score = positive_words.len();
+        let negative_score = negative_words.len();
 
         // 感嘆符や疑問符の影響
         let exclamation_count = text.chars().filter(|&c| c == '！' || c == '!').count();
@@ -86,37 +149,86 @@ impl NLPProcessor {
         
         positive_score += exclamation_count;
         
-        if positive_score > negative_score {
-            "positive".to_string()
+        let label = if positive_score > negative_score {
+            "positive"
         } else if negative_score > positive_score {
-            "negative".to_string()
+            "negative"
         } else {
-            "neutral".to_string()
+            "neutral"
+        };
+
+        let total = positive_score + negative_score;
+        let score = if total > 0 {
+            (positive_score as f32 - negative_score as f32) / total as f32
+        } else {
+            0.0
+        };
+
+        SentimentBreakdown {
+            label: label.to_string(),
+            positive: positive_score,
+            negative: negative_score,
+            exclamations: exclamation_count,
+            questions: question_count,
+            score,
         }
     }
 
-    pub fn extract_themes(&self, text: &str) -> Vec<String> {
-        let text_lower = text.to_lowercase();
-        let mut themes = Vec::new();
-
-        for (theme, keywords) in &self.theme_keywords {
-            let mut theme_score = 0;
-            for keyword in keywords {
-                if text_lower.contains(&keyword.to_lowercase()) {
-                    theme_score += 1;
-                }
-            }
-            
-            if theme_score > 0 {
-                themes.push(theme.clone());
-            }
-        }
+    /// 確信度（0〜10）
+    pub fn conviction(&self, text: &str) -> ConvictionScore {
+        self.conviction_with(text, &self.scan(text))
+    }
 
-        // 専門的なキーワードの検出
-        let tech_regex = Regex::new(r"(rust|go|javascript|python|haskell|programming|code)").unwrap();
-        if tech_regex.is_match(&text_lower) && !themes.contains(&"technology".to_string()) {
-            themes.push("technology".to_string());
-        }
+    /// `scan` 済みの一致から確信度を求める
+    pub fn conviction_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> ConvictionScore {
+        conviction::estimate(text, matches)
+    }
+
+    /// 哲学の学派の分布（割合の高い順）
+    pub fn schools(&self, text: &str) -> Vec<SchoolScore> {
+        self.schools_with(text, &self.scan(text))
+    }
+
+    /// `scan` 済みの一致から学派の分布を求める
+    pub fn schools_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> Vec<SchoolScore> {
+        schools::classify(text, matches)
+    }
+
+    /// 主張・前提・反論・結論と、その支持・攻撃の関係
+    pub fn argument_structure(&self, text: &str) -> ArgumentStructure {
+        self.argument_structure_with(text, &self.scan(text))
+    }
+
+    /// `scan` 済みの一致から論証構造を求める
+    pub fn argument_structure_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> ArgumentStructure {
+        argument::mine(text, matches)
+    }
+
+    /// 抽出型の要約（`compression_ratio` は残す文の割合）
+    pub fn summarize(&self, text: &str, compression_ratio: f32) -> Summary {
+        self.summarize_with(text, &self.scan(text), compression_ratio)
+    }
+
+    /// `scan` 済みの一致を使って要約する
+    pub fn summarize_with(&self, text: &str, matches: &[KeywordMatch<'_>], compression_ratio: f32) -> Summary {
+        summary::summarize(text, matches, compression_ratio)
+    }
+
+    /// 誤謬の疑い（説明はキャラクターの口調。`None` なら中立の口調）
+    pub fn fallacies(&self, text: &str, character: Option<&Character>) -> Vec<Fallacy> {
+        self.fallacies_with(text, &self.scan(text), character)
+    }
+
+    /// `scan` 済みの一致から誤謬の疑いを探す
+    pub fn fallacies_with(&self, text: &str, matches: &[KeywordMatch<'_>], character: Option<&Character>) -> Vec<Fallacy> {
+        fallacy::detect(text, matches, character)
+    }
+
+    pub fn extract_themes(&self, text: &str) -> Vec<String> {
+        let mut themes: Vec<String> = self.theme_scores(text)
+            .into_iter()
+            .map(|theme| theme.theme)
+            .collect();
 
         // デフォルトテーマ
         if themes.is_empty() {
@@ -126,19 +238,48 @@ impl NLPProcessor {
         themes
     }
 
-    pub fn extract_keywords(&self, text: &str) -> Vec<String> {
-        let text_lower = text.to_lowercase();
-        let words: Vec<&str> = text_lower.split_whitespace().collect();
-        let mut keywords = Vec::new();
-
-        // 重要そうな単語を抽出（長さ3文字以上）
-        for word in words {
-            let clean_word = word.trim_matches(|c: char| !c.is_alphanumeric());
-            if clean_word.len() >= 3 && !self.is_stop_word(clean_word) {
-                keywords.push(clean_word.to_string());
+    /// テーマごとのスコアと一致したキーワード（スコアの高い順）
+    pub fn theme_scores(&self, text: &str) -> Vec<ThemeScore> {
+        self.theme_scores_with(&self.scan(text))
+    }
+
+    /// `scan` 済みの一致からテーマごとのスコアを求める
+    pub fn theme_scores_with(&self, matches: &[KeywordMatch<'_>]) -> Vec<ThemeScore> {
+        let mut matched: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+        for m in matches {
+            let theme = match m.vocabulary {
+                Vocabulary::Theme(theme) => theme.as_str(),
+                // 専門的なキーワードは technology として数える
+                Vocabulary::Tech => "technology",
+                _ => continue,
+            };
+            let keywords = matched.entry(theme).or_default();
+            if !keywords.iter().any(|k| k == m.keyword) {
+                keywords.push(m.keyword.to_string());
             }
         }
 
+        let mut themes: Vec<ThemeScore> = matched.into_iter()
+            .map(|(theme, matched)| ThemeScore {
+                theme: theme.to_string(),
+                score: matched.len(),
+                matched,
+            })
+            .collect();
+
+        themes.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.theme.cmp(&b.theme)));
+        themes
+    }
+
+    pub fn extract_keywords(&self, text: &str) -> Vec<String> {
+        self.extract_keywords_with(text, &self.scan(text))
+    }
+
+    /// `scan` 済みの一致を使ってキーワードを抽出する（ストップワードの判定に使う）
+    pub fn extract_keywords_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> Vec<String> {
+        let mut keywords = keyword_candidates(text, matches);
+
         // 重複を除去
         keywords.sort();
         keywords.dedup();
@@ -148,6 +289,61 @@ impl NLPProcessor {
         keywords
     }
 
+    /// クラスタリング用の語（重複あり）。キーワード抽出の候補に、
+    /// 空白で区切らない日本語の内容語と、テーマ・学派の語彙に当たった語を加える。
+    pub fn keyword_terms_with(&self, text: &str, matches: &[KeywordMatch<'_>]) -> Vec<String> {
+        let mut terms = keyword_candidates(text, matches);
+        terms.extend(content_words(text).into_iter().map(|word| word.to_lowercase()));
+        terms.extend(matches.iter()
+            .filter(|m| matches!(m.vocabulary, Vocabulary::Theme(_) | Vocabulary::Tech | Vocabulary::School(_)))
+            .map(|m| m.keyword.to_string()));
+        terms
+    }
+
+    /// 文字の種類から言語を推定する（`ja` / `en` / `und`）。
+    /// 日本語は1文字が英語の数文字分の情報を持つので、仮名・漢字を3倍に数える。
+    pub fn detect_language(&self, text: &str) -> &'static str {
+        let (mut japanese, mut latin) = (0, 0);
+        for c in text.chars() {
+            match c {
+                '\u{3040}'..='\u{30ff}' | '\u{4e00}'..='\u{9fff}' | '\u{ff66}'..='\u{ff9f}' => japanese += 1,
+                c if c.is_ascii_alphabetic() => latin += 1,
+                _ => {}
+            }
+        }
+        match (japanese, latin) {
+            (0, 0) => "und",
+            (japanese, latin) if japanese * 3 >= latin => "ja",
+            _ => "en",
+        }
+    }
+
+    /// 文のバイト範囲（句点などの終端記号を含む）。
+    ///
+    /// 小数（3.14）や略語（e.g. / Dr.）のピリオドでは区切らない。
+    pub fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            let ends = match c {
+                '。' | '!' | '?' | '！' | '？' | '\n' => true,
+                '.' => period_ends_sentence(text, i),
+                _ => false,
+            };
+            if ends {
+                let end = i + c.len_utf8();
+                if !text[start..end].trim().is_empty() {
+                    spans.push((start, end));
+                }
+                start = end;
+            }
+        }
+        if !text[start..].trim().is_empty() {
+            spans.push((start, text.len()));
+        }
+        spans
+    }
+
     pub fn calculate_readability(&self, text: &str) -> f32 {
         let sentences = text.split('.').count() as f32;
         let words = text.split_whitespace().count() as f32;
@@ -162,16 +358,86 @@ impl NLPProcessor {
             0.0
         }
     }
+}
 
-    fn is_stop_word(&self, word: &str) -> bool {
-        let stop_words = [
-            "の", "は", "が", "を", "に", "で", "と", "から", "まで",
-            "です", "である", "ます", "した", "する", "される",
-            "この", "その", "あの", "どの", "これ", "それ", "あれ", "どれ",
-            "the", "is", "at", "which", "on", "and", "a", "an", "as", "are",
-            "was", "were", "been", "be", "have", "has", "had", "do", "does", "did",
-        ];
-        
-        stop_words.contains(&word)
+impl Default for NLPProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 文末と見なさないピリオドの前の語（小文字）
+const ABBREVIATIONS: [&str; 14] = [
+    "e.g", "i.e", "cf", "vs", "dr", "mr", "mrs", "ms", "prof", "st", "jr", "sr", "no", "approx",
+];
+
+// `i` のピリオドが文を終えるか。直後に空白を挟まず文字が続くもの（小数、e.g. の
+// 最初のピリオド、URL）と、略語や頭文字（J. Smith）の後のものは文末ではない
+fn period_ends_sentence(text: &str, i: usize) -> bool {
+    if text[i + 1..].chars().next().is_some_and(char::is_alphanumeric) {
+        return false;
+    }
+    let word = text[..i].rsplit(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("");
+    let initial = word.len() == 1 && word.chars().all(|c| c.is_ascii_uppercase());
+    !initial && !ABBREVIATIONS.contains(&word.to_ascii_lowercase().as_str())
+}
+
+/// 表示用に先頭 `max_chars` 文字までに縮める（縮めたら「…」を付ける）
+pub(crate) fn excerpt(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    format!("{}…", cut)
+}
+
+// 空白で区切った3文字以上の語のうち、ストップワードでないもの
+fn keyword_candidates(text: &str, matches: &[KeywordMatch<'_>]) -> Vec<String> {
+    let stop_spans: HashSet<(usize, usize)> = matches.iter()
+        .filter(|m| *m.vocabulary == Vocabulary::StopWord)
+        .map(|m| (m.start, m.end))
+        .collect();
+    let mut keywords = Vec::new();
+
+    // 重要そうな単語を抽出（長さ3文字以上）
+    for word in text.split_whitespace() {
+        let clean_word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if clean_word.len() < 3 {
+            continue;
+        }
+
+        let start = clean_word.as_ptr() as usize - text.as_ptr() as usize;
+        if !stop_spans.contains(&(start, start + clean_word.len())) {
+            keywords.push(clean_word.to_lowercase());
+        }
+    }
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentences(text: &str) -> Vec<&str> {
+        NLPProcessor::sentence_spans(text).into_iter().map(|(start, end)| text[start..end].trim()).collect()
+    }
+
+    #[test]
+    fn splits_on_japanese_and_english_terminators() {
+        assert_eq!(sentences("自由とは何か？責任が伴う。Really! Yes"), vec!["自由とは何か？", "責任が伴う。", "Really!", "Yes"]);
+    }
+
+    #[test]
+    fn keeps_abbreviations_decimals_and_initials_together() {
+        assert_eq!(
+            sentences("Dr. Smith measured 3.14 today. Virtues, e.g. courage, matter. J. S. Mill agreed."),
+            vec!["Dr. Smith measured 3.14 today.", "Virtues, e.g. courage, matter.", "J. S. Mill agreed."]
+        );
+    }
+
+    #[test]
+    fn excerpt_counts_characters() {
+        assert_eq!(excerpt("自由と責任", 10), "自由と責任");
+        assert_eq!(excerpt("自由と責任", 2), "自由…");
     }
 }
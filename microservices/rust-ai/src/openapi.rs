@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use warp::{Filter, Rejection, Reply};
+use crate::server;
+
+/// `/v1` の現行レスポンス形式
+#[derive(OpenApi)]
+#[openapi(paths(
+    server::handle_chat,
+    server::handle_analysis,
+    server::handle_analysis_batch,
+    server::handle_conviction,
+    server::handle_summarize,
+    server::handle_similar,
+    server::handle_start_clustering,
+    server::handle_cluster_job,
+    server::handle_trends,
+    server::handle_get_analysis,
+    server::handle_list_analyses,
+    server::handle_delete_analysis,
+    server::handle_delete_user_analyses,
+    server::get_personalities,
+    server::handle_put_character,
+    server::handle_delete_character,
+    server::generate_wisdom,
+))]
+struct V1Api;
+
+/// `/v2` の詳細な分析モデル
+#[derive(OpenApi)]
+#[openapi(paths(
+    server::handle_chat,
+    server::handle_analysis_v2,
+    server::handle_analysis_batch_v2,
+    server::handle_conviction,
+    server::handle_summarize,
+    server::handle_similar,
+    server::handle_start_clustering,
+    server::handle_cluster_job,
+    server::handle_trends,
+    server::handle_get_analysis_v2,
+    server::handle_list_analyses_v2,
+    server::handle_delete_analysis,
+    server::handle_delete_user_analyses,
+    server::get_personalities,
+    server::handle_put_character,
+    server::handle_delete_character,
+    server::generate_wisdom,
+))]
+struct V2Api;
+
+/// Rustの型から生成する philosophy-ai の OpenAPI 3 ドキュメント
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "philosophy-ai",
+        description = "キャラクターチャットと哲学分析のAPI。接頭辞なしのパス（`/chat` など）は `/v1` の非推奨エイリアス。クライアントIPと認証した相手（JWT の `sub` かAPIキー）ごとにレート制限があり、超えると 429 と `Retry-After` を返す。`auth.enabled` のときは `/health` と OpenAPI 以外のルートが設定された権限（public / authenticated / admin）を求め、認証情報が無いか不正なら 401、権限が足りなければ 403 を返す。"
+    ),
+    modifiers(&SecuritySchemes),
+    paths(server::health_check, server::handle_metrics),
+    nest(
+        (path = "/v1", api = V1Api),
+        (path = "/v2", api = V2Api)
+    )
+)]
+pub struct ApiDoc;
+
+/// サービス用のAPIキーと、共有の秘密鍵で署名したJWT
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))));
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+/// 公開するOpenAPIドキュメント。
+///
+/// `/v1` と `/v2` は同じハンドラーを共有するので、型生成ツールが
+/// 衝突しないように operationId にバージョンを付ける。
+pub fn spec() -> utoipa::openapi::OpenApi {
+    let mut spec = ApiDoc::openapi();
+
+    for (path, item) in spec.paths.paths.iter_mut() {
+        let version = match path.split('/').nth(1) {
+            Some(version @ ("v1" | "v2")) => version,
+            _ => continue,
+        };
+
+        let operations = [&mut item.get, &mut item.post, &mut item.put, &mut item.delete];
+        for operation in operations.into_iter().flatten() {
+            if let Some(id) = &operation.operation_id {
+                operation.operation_id = Some(format!("{}_{}", version, id));
+            }
+        }
+    }
+
+    spec
+}
+
+/// `/openapi.json` と、それを表示する `/docs` ページ
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let spec = Arc::new(spec());
+
+    let json = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(spec.as_ref()));
+
+    json.or(service_common::docs::route())
+}
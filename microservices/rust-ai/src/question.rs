@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::character_ai::Character;
+use crate::nlp::NLPProcessor;
+
+/// 質問の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionType {
+    /// 理由を尋ねる（なぜ / why）
+    Why,
+    /// 方法を尋ねる（どうすれば / how）
+    How,
+    /// 意味や定義を尋ねる（とは / what）
+    What,
+    /// 人・時・場所を尋ねる（誰 / いつ / どこ / who）
+    Fact,
+    /// はい・いいえで答える質問
+    YesNo,
+}
+
+/// メッセージ中で見つかった質問
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub kind: QuestionType,
+    /// 質問になっている文
+    pub sentence: String,
+}
+
+// 疑問詞と、それが表す質問の種類（先にあるものを優先する）
+const INTERROGATIVES: [(&str, QuestionType); 27] = [
+    ("なぜ", QuestionType::Why), ("何故", QuestionType::Why), ("どうして", QuestionType::Why),
+    ("why", QuestionType::Why),
+    ("どうすれば", QuestionType::How), ("どうやって", QuestionType::How), ("どうしたら", QuestionType::How),
+    ("どう", QuestionType::How), ("how", QuestionType::How),
+    ("とは", QuestionType::What), ("って何", QuestionType::What), ("何", QuestionType::What),
+    ("なに", QuestionType::What), ("どんな", QuestionType::What), ("どちら", QuestionType::What),
+    ("どれが", QuestionType::What), ("どれを", QuestionType::What),
+    ("what", QuestionType::What), ("which", QuestionType::What),
+    ("誰", QuestionType::Fact), ("だれ", QuestionType::Fact), ("いつ", QuestionType::Fact),
+    ("どこ", QuestionType::Fact),
+    ("who", QuestionType::Fact), ("when", QuestionType::Fact), ("where", QuestionType::Fact),
+    ("whom", QuestionType::Fact),
+];
+
+// 文頭に来ると疑問文になる英語の助動詞
+const AUXILIARIES: [&str; 14] = [
+    "is", "are", "am", "was", "were", "do", "does", "did",
+    "can", "could", "should", "would", "will", "may",
+];
+
+// 内容語として扱わない英単語
+const FUNCTION_WORDS: [&str; 12] = [
+    "this", "that", "with", "have", "your", "about", "from", "they", "there", "their", "should", "would",
+];
+
+// 文末に付けて上がり調子を表す記号
+const RISING_MARKERS: [char; 2] = ['↑', '⤴'];
+
+/// メッセージから最初の質問を見つけて種類を判定する。
+///
+/// 「？」・上がり調子の印・文末の「か」（「のか」「ですか」も含む）は疑問文にする。
+/// 普通形の文末の「の」は平叙文でも使うので、疑問詞があるときだけ疑問文にし、
+/// 「かな」などほかの終助詞は「？」か上がり調子の印があるときだけにする。
+pub fn detect(message: &str) -> Option<Question> {
+    sentences(message)
+        .into_iter()
+        .find_map(|(sentence, terminator)| {
+            let lower = sentence.to_lowercase();
+            let first_word = lower.split_whitespace().next().unwrap_or("");
+            let interrogative = INTERROGATIVES.iter()
+                .find(|(word, _)| contains_interrogative(&lower, word))
+                .map(|(_, kind)| *kind);
+            // 「！」で終わる「か」は誘いか感嘆（「行くか！」）
+            let plain_end = !matches!(terminator, Some('!' | '！'));
+
+            let asked = matches!(terminator, Some('?' | '？'))
+                || sentence.ends_with(RISING_MARKERS)
+                || (plain_end && sentence.ends_with('か'))
+                || (plain_end && interrogative.is_some() && sentence.ends_with('の'))
+                || AUXILIARIES.contains(&first_word)
+                || INTERROGATIVES.iter().any(|(word, _)| word.is_ascii() && first_word == *word);
+            if !asked {
+                return None;
+            }
+
+            Some(Question {
+                kind: interrogative.unwrap_or(QuestionType::YesNo),
+                sentence: sentence.to_string(),
+            })
+        })
+}
+
+/// 文と、その文を終えた句読点
+fn sentences(text: &str) -> Vec<(&str, Option<char>)> {
+    NLPProcessor::sentence_spans(text)
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let sentence = &text[start..end];
+            let terminator = sentence.chars().next_back()
+                .filter(|c| matches!(c, '。' | '.' | '!' | '?' | '！' | '？' | '\n'));
+            let sentence = terminator.map_or(sentence, |c| &sentence[..sentence.len() - c.len_utf8()]).trim();
+            (!sentence.is_empty()).then_some((sentence, terminator))
+        })
+        .collect()
+}
+
+/// 疑問詞として使われているか。「いつも」「誰でも」「何か食べたい」のような
+/// 不定の意味の語は数えない（文末の「何か」は疑問詞）
+fn contains_interrogative(text: &str, word: &str) -> bool {
+    if word.is_ascii() {
+        return contains_word(text, word);
+    }
+    text.match_indices(word).any(|(i, _)| {
+        let rest = &text[i + word.len()..];
+        !(rest.starts_with('も') || rest.starts_with("でも") || (rest.starts_with('か') && rest != "か"))
+    })
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    if !word.is_ascii() {
+        return text.contains(word);
+    }
+    text.split(|c: char| !c.is_ascii_alphanumeric()).any(|w| w == word)
+}
+
+/// 知恵や対話のトピックに使う内容語（漢字・カタカナの2文字以上の並びと英単語）
+pub fn content_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_kind = None;
+
+    for c in text.chars().chain(std::iter::once(' ')) {
+        let kind = if ('\u{4e00}'..='\u{9fff}').contains(&c) {
+            Some(0)
+        } else if ('\u{30a0}'..='\u{30ff}').contains(&c) {
+            Some(1)
+        } else if c.is_ascii_alphabetic() {
+            Some(2)
+        } else {
+            None
+        };
+
+        if kind != current_kind || kind.is_none() {
+            // 英語は4文字以上か、AI のような大文字の略語
+            let acronym = current_kind == Some(2) && current.len() >= 2 && current.chars().all(|c| c.is_ascii_uppercase());
+            let long_enough = match current_kind {
+                Some(2) => acronym || current.len() >= 4,
+                _ => current.chars().count() >= 2,
+            };
+            let word = if acronym { current.clone() } else { current.to_lowercase() };
+            let is_function_word = INTERROGATIVES.iter().any(|(w, _)| *w == word) || FUNCTION_WORDS.contains(&word.as_str());
+            if long_enough && !is_function_word && !words.contains(&word) {
+                words.push(word);
+            }
+            current.clear();
+        }
+        if kind.is_some() {
+            current.push(c);
+        }
+        current_kind = kind;
+    }
+    words
+}
+
+/// キャラクターの口調
+pub(crate) struct Voice {
+    pub opener: &'static str,
+    pub closer: &'static str,
+    /// 一緒に考えようと誘う言葉
+    pub invite: &'static str,
+    /// 論理の落とし穴を指摘するときの前置き
+    pub caution: &'static str,
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Voice { opener: "いい質問だね。", closer: "一緒に考えよう。", invite: "一緒に考えてみよう。", caution: "少し立ち止まろう。" }
+    }
+}
+
+pub(crate) fn voice(character: &Character) -> Voice {
+    match character.personality.language_style.as_str() {
+        "Go" => Voice { opener: "いい質問だね！", closer: "一緒に考えていこうね！", invite: "一緒にシンプルに考えてみよう！", caution: "ちょっと待って！" },
+        "Rust" => Voice { opener: "深い問いなり...", closer: "それが我の答えなり...", invite: "共に深く潜ってみるなり...", caution: "待たれよ..." },
+        "JavaScript" => Voice { opener: "おっ、いい質問だっぺ！", closer: "そういうことだっぺ！", invite: "いっちょ考えてみるっぺ！", caution: "おっと、待つっぺ！" },
+        _ => Voice::default(),
+    }
+}
+
+/// 知恵とおすすめを使って、キャラクターの口調で質問に答える
+pub fn answer(character: &Character, kind: QuestionType, wisdom: &str, recommendation: Option<&str>) -> String {
+    let voice = voice(character);
+    let body = match kind {
+        QuestionType::Why => format!("その「なぜ」には、「{}」という答えがあると思う。", wisdom),
+        QuestionType::How => format!("ヒントは「{}」ということ。", wisdom),
+        QuestionType::What => format!("それはきっと「{}」ということ。", wisdom),
+        QuestionType::Fact => format!("答えは人それぞれだけど、「{}」を思い出してほしい。", wisdom),
+        QuestionType::YesNo => format!("一概には言えないけれど、「{}」と考えてみよう。", wisdom),
+    };
+    let advice = recommendation.map(|r| format!("{}。", r)).unwrap_or_default();
+
+    format!("{} {}{}{}{}", character.emoji, voice.opener, body, advice, voice.closer)
+}
+
+/// 答えにできる知恵が無いときの、ソクラテス式の問い返し
+pub fn counter_question(character: &Character, kind: QuestionType, topic: Option<&str>) -> String {
+    let voice = voice(character);
+    let topic = topic.unwrap_or("それ");
+    let counter = match kind {
+        QuestionType::Why => format!("逆に聞かせて。あなたはなぜ「{}」が気になるの？", topic),
+        QuestionType::How => "もし何の制約もなかったら、あなたならどうする？".to_string(),
+        QuestionType::What => format!("あなたにとって「{}」とはどういう意味だろう？", topic),
+        QuestionType::Fact => format!("その答えを知ったら、「{}」について何が変わると思う？", topic),
+        QuestionType::YesNo => "そう考える根拠は何だろう？反対の立場ならどう言うかな？".to_string(),
+    };
+
+    format!("{} {}{}", character.emoji, voice.opener, counter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(message: &str) -> Option<QuestionType> {
+        detect(message).map(|question| question.kind)
+    }
+
+    #[test]
+    fn sentence_final_ka_and_interrogatives_before_no_are_questions() {
+        assert_eq!(kind("人生の意味とは何か。"), Some(QuestionType::What));
+        assert_eq!(kind("なぜ人は生きるのか。"), Some(QuestionType::Why));
+        assert_eq!(kind("自由とは何か"), Some(QuestionType::What));
+        assert_eq!(kind("どうしてそう思うの"), Some(QuestionType::Why));
+        assert_eq!(kind("明日は晴れますか"), Some(QuestionType::YesNo));
+    }
+
+    #[test]
+    fn other_plain_endings_need_a_question_mark() {
+        assert_eq!(kind("今日は雨なの"), None);
+        assert_eq!(kind("明日は晴れるかな"), None);
+        assert_eq!(kind("さあ、行くか！"), None);
+        assert_eq!(kind("今日は雨なの？"), Some(QuestionType::YesNo));
+        assert_eq!(kind("明日は晴れるかな↑"), Some(QuestionType::YesNo));
+    }
+
+    #[test]
+    fn indefinite_words_are_not_interrogatives() {
+        assert_eq!(kind("いつも遅れるの"), None);
+        assert_eq!(kind("何も分からないの"), None);
+        assert_eq!(kind("何か食べたいの？"), Some(QuestionType::YesNo));
+        assert_eq!(kind("誰でもいいの？"), Some(QuestionType::YesNo));
+    }
+
+    #[test]
+    fn content_nouns_do_not_decide_the_kind() {
+        assert_eq!(kind("人生に意味はあるの？"), Some(QuestionType::YesNo));
+        assert_eq!(kind("どれも大事だよね？"), Some(QuestionType::YesNo));
+        assert_eq!(kind("どれが正しいの？"), Some(QuestionType::What));
+        assert_eq!(kind("なぜ人は生きるのか？"), Some(QuestionType::Why));
+    }
+
+    #[test]
+    fn finds_the_first_question_in_a_message() {
+        let question = detect("自由について考えた。Why do we need rules? I wonder.").unwrap();
+        assert_eq!(question.kind, QuestionType::Why);
+        assert_eq!(question.sentence, "Why do we need rules");
+    }
+}
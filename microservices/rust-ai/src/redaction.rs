@@ -0,0 +1,196 @@
+use std::sync::{Arc, OnceLock};
+use regex::Regex;
+use crate::config::RedactionSettings;
+use crate::matcher::{outermost, KeywordMatcher, Vocabulary};
+
+const EMAIL: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}";
+const URL: &str = r#"https?://[^\s<>"'）」】]+"#;
+// 認証情報らしいクエリパラメーターの値と、URL中の `user:password@`
+const URL_SECRET: &str = r"(?i)([?&#;](?:[a-z_-]*token|[a-z_-]*key|secret|sig|signature|auth|password|passwd|pwd|code|session|sid|jwt)=)[^&#;\s]+";
+const URL_CREDENTIALS: &str = r"://[^/@\s:]+:[^/@\s]+@";
+// 電話番号（+81 などの国際表記、区切りのある日本の番号、区切りの無い 0 始まりの番号、北米の表記）
+const PHONE: &str = r"[+＋]\d{1,3}[ \-‐－−]?\(?\d{1,4}\)?(?:[ \-‐－−]?\d{1,4}){2,3}|\(?[0０]\d{1,4}\)?[ \-‐－−]\d{1,4}[ \-‐－−]\d{3,4}|[0０]\d{1,4}\(\d{1,4}\)\d{3,4}|[0０]\d{9,10}|\(\d{3}\) ?\d{3}-\d{4}|\d{3}-\d{3}-\d{4}";
+// 郵便番号（〒123-4567、123-4567、米国の 12345-6789）
+const POSTAL_CODE: &str = r"〒\s?\d{3}[\-‐－−]?\d{4}|(?:^|[^\d\-‐－−])(\d{3}[\-‐－−]\d{4}|\d{5}-\d{4})(?:$|[^\d\-‐－−])";
+
+// 電話番号とみなす数字の桁数
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 10..=15;
+
+/// ログや保存するテキストから個人情報を伏せる。
+///
+/// メールアドレス・電話番号・郵便番号・認証情報付きの URL と、
+/// 設定で与えた人名を `[EMAIL]` などの印に置き換える。
+pub struct Redactor {
+    email: Regex,
+    url: Regex,
+    url_secret: Regex,
+    url_credentials: Regex,
+    phone: Regex,
+    postal_code: Regex,
+    names: Option<KeywordMatcher>,
+    sessions: bool,
+    analyses: bool,
+}
+
+impl Redactor {
+    pub fn new(settings: &RedactionSettings) -> Self {
+        let names: Vec<(String, Vocabulary)> = settings.names.iter()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| (name.to_string(), Vocabulary::PersonName))
+            .collect();
+        let compile = |pattern: &str| Regex::new(pattern).expect("built-in redaction patterns should compile");
+
+        Redactor {
+            email: compile(EMAIL),
+            url: compile(URL),
+            url_secret: compile(URL_SECRET),
+            url_credentials: compile(URL_CREDENTIALS),
+            phone: compile(PHONE),
+            postal_code: compile(POSTAL_CODE),
+            names: (!names.is_empty()).then(|| KeywordMatcher::new(names)),
+            sessions: settings.sessions,
+            analyses: settings.analyses,
+        }
+    }
+
+    /// 会話履歴に残すテキストも伏せるか
+    pub fn applies_to_sessions(&self) -> bool {
+        self.sessions
+    }
+
+    /// 保存する分析のテキストも伏せるか
+    pub fn applies_to_analyses(&self) -> bool {
+        self.analyses
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        // (開始, 終了, 置き換え)。先に見つけた種類を優先し、重なるものは捨てる
+        let mut spans: Vec<(usize, usize, String)> = Vec::new();
+        let mut add = |start: usize, end: usize, replacement: String| {
+            if !spans.iter().any(|&(s, e, _)| s < end && start < e) {
+                spans.push((start, end, replacement));
+            }
+        };
+
+        for m in self.url.find_iter(text) {
+            let url = m.as_str();
+            let redacted = self.redact_url(url);
+            if redacted != url {
+                add(m.start(), m.end(), redacted);
+            }
+        }
+        for m in self.email.find_iter(text) {
+            add(m.start(), m.end(), "[EMAIL]".to_string());
+        }
+        for m in self.phone.find_iter(text) {
+            let digits = m.as_str().chars().filter(|c| c.is_numeric()).count();
+            if PHONE_DIGITS.contains(&digits) && !is_inside_number(text, m.start(), m.end()) {
+                add(m.start(), m.end(), "[PHONE]".to_string());
+            }
+        }
+        for captures in self.postal_code.captures_iter(text) {
+            let m = captures.get(1).or_else(|| captures.get(0)).expect("a match has group 0");
+            add(m.start(), m.end(), "[POSTAL_CODE]".to_string());
+        }
+        if let Some(names) = &self.names {
+            let matches = names.scan_ignoring_case(text);
+            for m in outermost(matches.iter().filter(|m| m.is_whole_word(text))) {
+                add(m.start, m.end, "[NAME]".to_string());
+            }
+        }
+
+        if spans.is_empty() {
+            return text.to_string();
+        }
+        spans.sort_by_key(|&(start, _, _)| start);
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, replacement) in spans {
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(&replacement);
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        redacted
+    }
+
+    /// URL の `user:password@` と認証情報らしいクエリパラメーターの値を伏せる。
+    /// `postgres://` など http 以外の接続文字列にも使える
+    pub fn redact_url(&self, url: &str) -> String {
+        let redacted = self.url_secret.replace_all(url, "${1}[REDACTED]");
+        self.url_credentials.replace_all(&redacted, "://[REDACTED]@").into_owned()
+    }
+}
+
+impl Redactor {
+    /// 既定の設定の `Redactor`。正規表現は一度だけ組み立てて共有する
+    pub fn shared() -> Arc<Redactor> {
+        static DEFAULT: OnceLock<Arc<Redactor>> = OnceLock::new();
+        DEFAULT.get_or_init(|| Arc::new(Redactor::default())).clone()
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(&RedactionSettings::default())
+    }
+}
+
+// 前後に数字が続く（もっと長い数字列の一部）なら電話番号ではない
+fn is_inside_number(text: &str, start: usize, end: usize) -> bool {
+    text[..start].chars().next_back().is_some_and(char::is_numeric)
+        || text[end..].chars().next().is_some_and(char::is_numeric)
+}
+
+static LOG_REDACTOR: OnceLock<Arc<Redactor>> = OnceLock::new();
+
+/// ログに使う `Redactor` を設定する（起動時に一度だけ）
+pub fn install(redactor: Arc<Redactor>) {
+    let _ = LOG_REDACTOR.set(redactor);
+}
+
+/// ログに出す前に個人情報を伏せる（`install` 前は組み込みの規則だけで伏せる）
+pub fn for_log(text: &str) -> String {
+    LOG_REDACTOR.get_or_init(Redactor::shared).redact(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(names: &[&str]) -> Redactor {
+        Redactor::new(&RedactionSettings {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            ..RedactionSettings::default()
+        })
+    }
+
+    #[test]
+    fn redacts_contact_details() {
+        let redactor = redactor(&[]);
+        assert_eq!(redactor.redact("連絡は taro@example.com まで"), "連絡は [EMAIL] まで");
+        assert_eq!(redactor.redact("電話 090-1234-5678 です"), "電話 [PHONE] です");
+        assert_eq!(redactor.redact("〒100-0001 東京都"), "[POSTAL_CODE] 東京都");
+        assert_eq!(
+            redactor.redact("https://example.com/a?token=abc123&page=2"),
+            "https://example.com/a?token=[REDACTED]&page=2"
+        );
+    }
+
+    #[test]
+    fn leaves_ordinary_numbers_and_urls_alone() {
+        let redactor = redactor(&[]);
+        for text in ["2024年に12345678901234567890件", "https://example.com/docs?page=2", "価格は1,980円"] {
+            assert_eq!(redactor.redact(text), text);
+        }
+    }
+
+    #[test]
+    fn redacts_names_as_whole_words_ignoring_case() {
+        let redactor = redactor(&["Alice", "Ärzte", "山田"]);
+        assert_eq!(redactor.redact("ALICE met Alicia"), "[NAME] met Alicia");
+        assert_eq!(redactor.redact("die ärzte sagen"), "die [NAME] sagen");
+        assert_eq!(redactor.redact("山田さんと話した"), "[NAME]さんと話した");
+    }
+}
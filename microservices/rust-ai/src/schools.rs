@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::matcher::{outermost, KeywordMatch, Vocabulary};
+
+/// 哲学の学派ごとの特徴語
+struct School {
+    id: &'static str,
+    label: &'static str,
+    description: &'static str,
+    /// (特徴語, 重み)。学派を象徴する語は 2.0、関連する語は 1.0
+    features: &'static [(&'static str, f32)],
+}
+
+const SCHOOLS: [School; 7] = [
+    School {
+        id: "stoic",
+        label: "ストア派",
+        description: "コントロールできないものを受け入れ、理性と徳に従う姿勢",
+        features: &[
+            ("ストア", 2.0), ("運命", 2.0), ("受け入れ", 1.0), ("平静", 2.0), ("動じない", 2.0),
+            ("理性", 1.0), ("自制", 1.0), ("心の平安", 1.0), ("コントロールできない", 2.0), ("耐える", 1.0),
+            ("stoic", 2.0), ("fate", 2.0), ("accept", 1.0), ("virtue", 1.0), ("tranquility", 2.0),
+            ("reason", 1.0), ("self-control", 1.0), ("endure", 1.0), ("out of our control", 2.0),
+        ],
+    },
+    School {
+        id: "existentialist",
+        label: "実存主義",
+        description: "自由な選択と責任によって自分の意味をつくる姿勢",
+        features: &[
+            ("実存", 2.0), ("自由", 1.0), ("選択", 1.0), ("責任", 1.0), ("不条理", 2.0),
+            ("本来の自分", 2.0), ("自分で決める", 2.0), ("意味をつくる", 2.0), ("意味を作る", 2.0), ("孤独", 1.0),
+            ("existential", 2.0), ("existence", 1.0), ("freedom", 1.0), ("choice", 1.0),
+            ("responsibility", 1.0), ("absurd", 2.0), ("authentic", 2.0), ("create meaning", 2.0),
+        ],
+    },
+    School {
+        id: "utilitarian",
+        label: "功利主義",
+        description: "結果と幸福の総量で善し悪しを判断する姿勢",
+        features: &[
+            ("功利", 2.0), ("最大多数", 2.0), ("最大幸福", 2.0), ("幸福", 1.0), ("効用", 2.0),
+            ("結果", 1.0), ("利益", 1.0), ("損得", 1.0), ("快楽", 1.0), ("苦痛", 1.0),
+            ("utilitarian", 2.0), ("greatest good", 2.0), ("utility", 2.0), ("happiness", 1.0),
+            ("consequences", 1.0), ("benefit", 1.0), ("welfare", 1.0), ("maximize", 1.0),
+        ],
+    },
+    School {
+        id: "buddhist",
+        label: "仏教",
+        description: "無常を見つめ、執着を手放して苦しみから離れる姿勢",
+        features: &[
+            ("仏教", 2.0), ("無常", 2.0), ("執着", 2.0), ("苦しみ", 1.0), ("縁起", 2.0),
+            ("悟り", 2.0), ("慈悲", 2.0), ("瞑想", 1.0), ("煩悩", 2.0), ("無我", 2.0), ("今この瞬間", 1.0),
+            ("buddhism", 2.0), ("buddhist", 2.0), ("impermanence", 2.0), ("attachment", 1.0),
+            ("suffering", 1.0), ("compassion", 1.0), ("mindfulness", 1.0), ("meditation", 1.0),
+            ("enlightenment", 2.0), ("karma", 2.0),
+        ],
+    },
+    School {
+        id: "pragmatist",
+        label: "プラグマティズム",
+        description: "役に立つか、実際に機能するかで考えを確かめる姿勢",
+        features: &[
+            ("プラグマティズム", 2.0), ("実用", 2.0), ("役に立つ", 2.0), ("実践", 1.0), ("試して", 1.0),
+            ("有用", 2.0), ("問題解決", 1.0), ("うまくいく", 1.0), ("経験", 1.0),
+            ("pragmatism", 2.0), ("pragmatic", 2.0), ("practical", 2.0), ("useful", 1.0),
+            ("what works", 2.0), ("experiment", 1.0), ("in practice", 1.0), ("problem solving", 1.0),
+        ],
+    },
+    School {
+        id: "confucian",
+        label: "儒教",
+        description: "礼と仁を重んじ、人間関係の調和と秩序を大切にする姿勢",
+        features: &[
+            ("儒教", 2.0), ("孔子", 2.0), ("礼儀", 1.0), ("仁", 2.0), ("孝行", 2.0),
+            ("調和", 1.0), ("秩序", 1.0), ("修身", 2.0), ("目上", 1.0), ("和を", 1.0),
+            ("confucian", 2.0), ("confucius", 2.0), ("filial", 2.0), ("harmony", 1.0),
+            ("benevolence", 2.0), ("propriety", 2.0), ("ritual", 1.0),
+        ],
+    },
+    School {
+        id: "nihilist",
+        label: "ニヒリズム",
+        description: "物事に本来の意味や価値はないとみなす姿勢",
+        features: &[
+            ("ニヒリズム", 2.0), ("虚無", 2.0), ("無意味", 2.0), ("意味がない", 2.0), ("意味はない", 2.0), ("価値はない", 2.0),
+            ("どうでもいい", 1.0), ("むなしい", 1.0), ("虚しい", 1.0),
+            ("nihilism", 2.0), ("nihilist", 2.0), ("meaningless", 2.0), ("nothing matters", 2.0),
+            ("pointless", 1.0), ("void", 1.0),
+        ],
+    },
+];
+
+/// 学派ごとの傾き（割合の高い順）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SchoolScore {
+    /// `stoic` / `existentialist` / `utilitarian` / `buddhist` / `pragmatist` / `confucian` / `nihilist`
+    pub school: String,
+    pub label: String,
+    /// 一致した学派全体に占める割合（合計 1.0）
+    pub probability: f32,
+    pub matched: Vec<String>,
+    pub explanation: String,
+}
+
+/// `KeywordMatcher` に登録する学派の語彙
+pub fn vocabulary() -> impl Iterator<Item = (&'static str, Vocabulary)> {
+    SCHOOLS.iter().flat_map(|school| {
+        school.features.iter().map(move |(feature, _)| (*feature, Vocabulary::School(school.id.to_string())))
+    })
+}
+
+/// `scan` 済みの一致から学派の分布を求める。どの学派にも当たらなければ空。
+pub fn classify(text: &str, matches: &[KeywordMatch<'_>]) -> Vec<SchoolScore> {
+    let candidates = matches.iter()
+        .filter(|m| matches!(m.vocabulary, Vocabulary::School(_)) && m.is_whole_word(text));
+
+    // 特徴語は学派ごとに1回だけ数える
+    let mut matched: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for m in outermost(candidates) {
+        if let Vocabulary::School(id) = m.vocabulary {
+            let features = matched.entry(id.as_str()).or_default();
+            if !features.contains(&m.keyword) {
+                features.push(m.keyword);
+            }
+        }
+    }
+
+    let weighted: Vec<(&School, f32, Vec<&str>)> = SCHOOLS.iter()
+        .filter_map(|school| {
+            let features = matched.remove(school.id)?;
+            let weight = features.iter()
+                .map(|feature| school.features.iter().find(|(f, _)| f.eq_ignore_ascii_case(feature)).map_or(1.0, |(_, w)| *w))
+                .sum::<f32>();
+            Some((school, weight, features))
+        })
+        .collect();
+    let total: f32 = weighted.iter().map(|(_, weight, _)| weight).sum();
+
+    let mut scores: Vec<SchoolScore> = weighted.into_iter()
+        .map(|(school, weight, features)| SchoolScore {
+            school: school.id.to_string(),
+            label: school.label.to_string(),
+            probability: weight / total,
+            explanation: format!(
+                "{}: {}。手がかり: {}",
+                school.label,
+                school.description,
+                features.iter().map(|f| format!("「{}」", f)).collect::<String>()
+            ),
+            matched: features.into_iter().map(str::to_string).collect(),
+        })
+        .collect();
+
+    scores.sort_by(|a, b| b.probability.total_cmp(&a.probability).then_with(|| a.school.cmp(&b.school)));
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nlp::NLPProcessor;
+
+    #[test]
+    fn the_strongest_school_comes_first_and_probabilities_sum_to_one() {
+        let scores = NLPProcessor::new().schools("無常を知り、執着を手放せば苦しみは消える。結果として幸福になる。");
+        assert_eq!(scores[0].school, "buddhist");
+        assert!(scores.iter().any(|s| s.school == "utilitarian"));
+        let total: f32 = scores.iter().map(|s| s.probability).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+        assert!(scores[0].explanation.contains("「無常」"));
+    }
+
+    #[test]
+    fn english_features_match_whole_words_only() {
+        let nlp = NLPProcessor::new();
+        let scores = nlp.schools("We must accept fate with tranquility.");
+        assert_eq!(scores[0].school, "stoic");
+        assert!(nlp.schools("The voided cheque was unreasonable.").is_empty());
+    }
+
+    #[test]
+    fn a_feature_counts_once_per_school() {
+        let scores = NLPProcessor::new().schools("虚無、虚無、虚無。");
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].matched, vec!["虚無".to_string()]);
+        assert_eq!(scores[0].probability, 1.0);
+    }
+}
@@ -0,0 +1,1046 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use crate::ai_engine::{AIEngine, AnalyzeOptions, ChatResponse, PhilosophyAnalysis, PhilosophyAnalysisV2};
+use crate::auth::{self, Access, Authenticator, Principal};
+use crate::api::{AnalyzeRequest, CharacterRequest, ChatMode, ClusterRequest, ChatRequest, ConvictionRequest, DeletedResponse, HealthResponse, SimilarRequest, SimilarResponse, SummarizeRequest, TrendQuery, UserAnalysesQuery, WisdomQuery, WisdomResponse};
+use crate::character_ai::{Character, CharacterPersonality};
+use crate::config::{AuthSettings, RateLimitSettings};
+use crate::cluster::{self, ClusterJob};
+use crate::conviction::ConvictionScore;
+use crate::error::{handle_rejection, ApiError, ErrorResponse};
+use crate::moderation::ModerationVerdict;
+use crate::batch::{self, BatchAnalyzeRequest, BatchInput, BatchItem, BodyStream};
+use crate::{metrics, openapi, rate_limit};
+use crate::rate_limit::{Gate, RateLimiter};
+use crate::redaction;
+use crate::storage::{AnalysisPage, AnalysisQuery};
+use crate::summary::Summary;
+use crate::telemetry;
+use crate::trends::TrendReport;
+
+/// HTTPレイヤーの設定
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// CORSで許可するオリジン（"*" で全て許可）
+    pub allowed_origins: Vec<String>,
+    /// テキスト系フィールドの最大文字数
+    pub max_text_length: usize,
+    /// `character` を省略したチャットで使うキャラクターID
+    pub default_character: String,
+    /// `/analyze/batch` で受け付ける最大件数
+    pub max_batch_size: usize,
+    /// ルートごとのリクエストボディの上限（バイト）。`BODY_LIMIT_ROUTES` のいずれか
+    pub max_body_bytes: BTreeMap<String, u64>,
+    pub rate_limit: RateLimitSettings,
+    pub auth: AuthSettings,
+}
+
+/// `max_body_bytes` で上限を変えられるルート
+pub const BODY_LIMIT_ROUTES: [&str; 8] = ["chat", "analyze", "analyze_batch", "conviction", "summarize", "similar", "clusters", "characters"];
+
+/// `auth.routes` で権限を変えられるルート（`analyses` は保存済みの分析の取得と一覧、
+/// `characters` はキャラクターの追加・置き換え・削除）
+pub const AUTH_ROUTES: [&str; 14] = [
+    "metrics", "chat", "analyze", "analyze_batch", "conviction", "summarize", "similar", "clusters", "trends",
+    "analyses", "delete_analyses", "personalities", "characters", "wisdom",
+];
+
+/// `/v1`・`/v2`（と非推奨エイリアス）の下にあるルート
+const API_ROUTES: [&str; 14] = [
+    "/chat", "/analyze", "/analyze/batch", "/conviction", "/summarize", "/similar", "/clusters", "/clusters/{id}",
+    "/trends", "/analysis/{id}", "/analyses", "/personalities", "/characters/{id}", "/wisdom",
+];
+/// バージョンの付かないルート
+const ROOT_ROUTES: [&str; 4] = ["/health", "/metrics", "/openapi.json", "/docs"];
+
+/// メトリクスの `route` ラベル（`telemetry::serve` に渡す）。
+///
+/// 合ったルートのテンプレートを使い、非推奨エイリアスは `/v1` と同じラベルにする。
+/// どのルートにも合わないパスは `unmatched` にまとめる。
+pub fn route_label(path: &str) -> String {
+    if let Some(route) = ROOT_ROUTES.iter().find(|route| telemetry::matches_template(route, path)) {
+        return route.to_string();
+    }
+    let (version, rest) = match path.get(..3) {
+        Some(version @ ("/v1" | "/v2")) => (version, &path[3..]),
+        _ => ("/v1", path),
+    };
+    API_ROUTES.iter()
+        .find(|route| telemetry::matches_template(route, rest))
+        .map(|route| format!("{}{}", version, route))
+        .unwrap_or_else(|| telemetry::UNMATCHED_ROUTE.to_string())
+}
+
+impl ServerConfig {
+    /// ルートのリクエストボディの上限（バイト）。
+    ///
+    /// 指定が無ければテキストの最大文字数から決める（UTF-8 で1文字最大4バイト + JSONの余白）。
+    pub fn body_limit(&self, route: &str) -> u64 {
+        if let Some(&limit) = self.max_body_bytes.get(route) {
+            return limit;
+        }
+        let text = (self.max_text_length * 4 + 64) as u64;
+        match route {
+            "analyze_batch" => self.max_batch_size as u64 * text,
+            "clusters" => cluster::MAX_DOCUMENTS as u64 * text + 1024,
+            // `message` と `context`
+            "chat" => 2 * text + 1024,
+            _ => text + 1024,
+        }
+    }
+
+    /// ルートを呼ぶのに必要な権限。
+    ///
+    /// 指定が無ければ、キャラクター一覧は公開、分析の削除とキャラクターの管理は管理者だけ、
+    /// それ以外（メトリクスを含む）は認証が必要。
+    pub fn access(&self, route: &str) -> Access {
+        if let Some(&access) = self.auth.routes.get(route) {
+            return access;
+        }
+        match route {
+            "personalities" => Access::Public,
+            "delete_analyses" | "characters" => Access::Admin,
+            _ => Access::Authenticated,
+        }
+    }
+}
+
+/// 既定で許可するオリジン（ローカルのフロントエンド）。全て許可するには設定で "*" を指定する
+pub const DEFAULT_ALLOWED_ORIGIN: &str = "http://localhost:3000";
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            allowed_origins: vec![DEFAULT_ALLOWED_ORIGIN.to_string()],
+            max_text_length: 5000,
+            default_character: "snowman".to_string(),
+            max_batch_size: 1000,
+            max_body_bytes: BTreeMap::new(),
+            rate_limit: RateLimitSettings::default(),
+            auth: AuthSettings::default(),
+        }
+    }
+}
+
+/// 単体で `warp::serve` できるアプリ全体（ルート + JSONエラー + CORS）。
+/// `warp::test::request()` で叩くときもこちらを使う。
+pub fn app(
+    ai_engine: AIEngine,
+    config: ServerConfig,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let cors = cors(&config);
+    routes(ai_engine, config)
+        .recover(handle_rejection)
+        .with(cors)
+}
+
+/// philosophy-ai の全ルートを組み立てる。
+///
+/// - `/v1/...` 既存クライアント向けのレスポンス形式
+/// - `/v2/...` 詳細な分析モデル（`PhilosophyAnalysisV2`）
+/// - `/chat` など接頭辞なしのパスは `/v1` の非推奨エイリアスで、
+///   `Deprecation` ヘッダーを付けて返す
+/// - `/health`・`/metrics`・OpenAPI 以外はクライアントIPと認証した相手ごとの
+///   レート制限がかかる（どのルートにも当たらないリクエストは数えない）
+/// - `auth.enabled` のときは `ServerConfig::access` の権限を求める
+///   （`/health` と OpenAPI は常に公開）
+///
+/// リジェクションはそのまま返すので、別のバイナリから
+/// `warp::path("ai").and(routes(engine, config))` のように他のルートと
+/// 組み合わせてマウントできる。その場合は外側で
+/// `error::handle_rejection` と `cors` を適用すること。
+pub fn routes(
+    ai_engine: AIEngine,
+    config: ServerConfig,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // Health check
+    let health = warp::path("health")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(health_check);
+
+    let limiter = Arc::new(RateLimiter::new(&config.rate_limit));
+    let auth = Arc::new(Authenticator::new(&config.auth));
+    let config = Arc::new(config);
+
+    // Prometheus metrics
+    let metrics = warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::guard(auth.clone(), config.access("metrics")))
+        .and(with_engine(ai_engine.clone()))
+        .and_then(handle_metrics);
+
+    let gate = Gate::new(auth.clone(), limiter);
+    let v1 = v1_routes(ai_engine.clone(), config.clone(), gate.clone());
+    let v2 = v2_routes(ai_engine, config, gate);
+
+    let legacy = v1.clone().map(|reply| {
+        let reply = warp::reply::with_header(reply, "Deprecation", "true");
+        warp::reply::with_header(reply, "Link", "</v1>; rel=\"successor-version\"")
+    });
+
+    let api = warp::path("v1").and(v1)
+        .or(warp::path("v2").and(v2))
+        .or(legacy);
+
+    health
+        .or(metrics)
+        .or(openapi::routes())
+        .or(api)
+}
+
+fn v1_routes(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // Philosophy analysis
+    let analyze = warp::path("analyze")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.require(config.access("analyze")))
+        .and(json_body(config.body_limit("analyze")))
+        .and(with_engine(ai_engine.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handle_analysis);
+
+    chat(ai_engine.clone(), config.clone(), gate.clone())
+        .or(analyze)
+        .or(analyze_batch(ai_engine.clone(), config.clone(), gate.clone(), handle_analysis_batch))
+        .or(conviction(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(summarize(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(similar(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(clusters(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(trends(ai_engine.clone(), &config, gate.clone()))
+        .or(stored_analysis(ai_engine.clone(), &config, gate.clone(), handle_get_analysis))
+        .or(stored_analyses(ai_engine.clone(), &config, gate.clone(), handle_list_analyses))
+        .or(delete_analyses(ai_engine.clone(), &config, gate.clone()))
+        .or(personalities(ai_engine.clone(), &config, gate.clone()))
+        .or(characters(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(wisdom(ai_engine, config, gate))
+}
+
+fn v2_routes(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // Detailed philosophy analysis
+    let analyze = warp::path("analyze")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.require(config.access("analyze")))
+        .and(json_body(config.body_limit("analyze")))
+        .and(with_engine(ai_engine.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handle_analysis_v2);
+
+    chat(ai_engine.clone(), config.clone(), gate.clone())
+        .or(analyze)
+        .or(analyze_batch(ai_engine.clone(), config.clone(), gate.clone(), handle_analysis_batch_v2))
+        .or(conviction(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(summarize(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(similar(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(clusters(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(trends(ai_engine.clone(), &config, gate.clone()))
+        .or(stored_analysis(ai_engine.clone(), &config, gate.clone(), handle_get_analysis_v2))
+        .or(stored_analyses(ai_engine.clone(), &config, gate.clone(), handle_list_analyses_v2))
+        .or(delete_analyses(ai_engine.clone(), &config, gate.clone()))
+        .or(personalities(ai_engine.clone(), &config, gate.clone()))
+        .or(characters(ai_engine.clone(), config.clone(), gate.clone()))
+        .or(wisdom(ai_engine, config, gate))
+}
+
+// Batch analysis endpoint (JSON or NDJSON in, NDJSON out)
+fn analyze_batch<H, Fut>(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+    handler: H,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
+where
+    H: Fn(Option<Principal>, Option<String>, BodyStream, AIEngine, Arc<ServerConfig>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<warp::reply::Response, Rejection>> + Send,
+{
+    let max_body = config.body_limit("analyze_batch");
+
+    warp::path("analyze")
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.require(config.access("analyze_batch")))
+        .and(warp::header::optional::<String>("content-type"))
+        .and(batch::body(max_body))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handler)
+}
+
+// Conviction level estimation
+fn conviction(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("conviction")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.guard(config.access("conviction")))
+        .and(json_body(config.body_limit("conviction")))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handle_conviction)
+}
+
+// Extractive summarization
+fn summarize(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("summarize")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.guard(config.access("summarize")))
+        .and(json_body(config.body_limit("summarize")))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handle_summarize)
+}
+
+// Nearest-neighbour search over stored analyses and wisdom
+fn similar(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("similar")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.require(config.access("similar")))
+        .and(json_body(config.body_limit("similar")))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handle_similar)
+}
+
+// Topic clustering jobs (start, then poll by job id)
+fn clusters(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let start = warp::path("clusters")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.require(config.access("clusters")))
+        .and(json_body(config.body_limit("clusters")))
+        .and(with_engine(ai_engine.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handle_start_clustering);
+
+    let job = warp::path!("clusters" / String)
+        .and(warp::get())
+        .and(gate.guard(config.access("clusters")))
+        .and(with_engine(ai_engine))
+        .and_then(handle_cluster_job);
+
+    start.or(job)
+}
+
+// Sentiment and theme trends over stored analyses
+fn trends(
+    ai_engine: AIEngine,
+    config: &ServerConfig,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("trends")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(gate.require(config.access("trends")))
+        .and(warp::query())
+        .and(with_engine(ai_engine))
+        .and_then(handle_trends)
+}
+
+// Stored analysis lookup
+fn stored_analysis<H, Fut, R>(
+    ai_engine: AIEngine,
+    config: &ServerConfig,
+    gate: Gate,
+    handler: H,
+) -> impl Filter<Extract = (R,), Error = Rejection> + Clone
+where
+    H: Fn(String, Option<Principal>, AIEngine) -> Fut + Clone + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<R, Rejection>> + Send,
+    R: Reply,
+{
+    warp::path("analysis")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(gate.require(config.access("analyses")))
+        .and(with_engine(ai_engine))
+        .and_then(handler)
+}
+
+// Stored analyses listing with filters and pagination
+fn stored_analyses<H, Fut, R>(
+    ai_engine: AIEngine,
+    config: &ServerConfig,
+    gate: Gate,
+    handler: H,
+) -> impl Filter<Extract = (R,), Error = Rejection> + Clone
+where
+    H: Fn(Option<Principal>, AnalysisQuery, AIEngine) -> Fut + Clone + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<R, Rejection>> + Send,
+    R: Reply,
+{
+    warp::path("analyses")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(gate.require(config.access("analyses")))
+        .and(warp::query())
+        .and(with_engine(ai_engine))
+        .and_then(handler)
+}
+
+// Deletion of stored analyses (single or per user for GDPR requests)
+fn delete_analyses(
+    ai_engine: AIEngine,
+    config: &ServerConfig,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let access = config.access("delete_analyses");
+    let single = warp::path("analysis")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(gate.require(access))
+        .and(with_engine(ai_engine.clone()))
+        .and_then(handle_delete_analysis);
+
+    let by_user = warp::path("analyses")
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(gate.require(access))
+        .and(warp::query())
+        .and(with_engine(ai_engine))
+        .and_then(handle_delete_user_analyses);
+
+    single.or(by_user)
+}
+
+// Character chat endpoint
+fn chat(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("chat")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(gate.guard(config.access("chat")))
+        .and(json_body(config.body_limit("chat")))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handle_chat)
+}
+
+// Character personalities
+fn personalities(
+    ai_engine: AIEngine,
+    config: &ServerConfig,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("personalities")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(gate.guard(config.access("personalities")))
+        .and(with_engine(ai_engine))
+        .and_then(get_personalities)
+}
+
+// Character management (add, replace or remove a character)
+fn characters(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let access = config.access("characters");
+    let put = warp::path!("characters" / String)
+        .and(warp::put())
+        .and(gate.guard(access))
+        .and(json_body(config.body_limit("characters")))
+        .and(with_engine(ai_engine.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handle_put_character);
+
+    let delete = warp::path!("characters" / String)
+        .and(warp::delete())
+        .and(gate.guard(access))
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(handle_delete_character);
+
+    put.or(delete)
+}
+
+// Wisdom generation
+fn wisdom(
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+    gate: Gate,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("wisdom")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(gate.guard(config.access("wisdom")))
+        .and(warp::query())
+        .and(with_engine(ai_engine))
+        .and(with_config(config))
+        .and_then(generate_wisdom)
+}
+
+fn with_engine(ai_engine: AIEngine) -> impl Filter<Extract = (AIEngine,), Error = Infallible> + Clone {
+    warp::any().map(move || ai_engine.clone())
+}
+
+// 上限付きのJSONボディ（超えたら 413）
+fn json_body<T: DeserializeOwned + Send>(max_bytes: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(max_bytes).and(warp::body::json())
+}
+
+fn with_config(config: Arc<ServerConfig>) -> impl Filter<Extract = (Arc<ServerConfig>,), Error = Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+pub fn cors(config: &ServerConfig) -> warp::cors::Builder {
+    let builder = warp::cors()
+        .allow_headers(vec!["content-type", "authorization", rate_limit::API_KEY_HEADER, telemetry::REQUEST_ID_HEADER, telemetry::TRACEPARENT_HEADER])
+        .expose_headers(vec!["retry-after", "www-authenticate", telemetry::REQUEST_ID_HEADER, telemetry::TRACEPARENT_HEADER])
+        .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"]);
+
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        builder.allow_any_origin()
+    } else {
+        builder.allow_origins(config.allowed_origins.iter().map(String::as_str))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, body = HealthResponse))
+)]
+pub(crate) async fn health_check() -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&HealthResponse {
+        status: "healthy".to_string(),
+        service: "philosophy-ai".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    description = "Prometheus のテキスト形式。ルート別のリクエスト数と処理時間、感情・キャラクター・知恵の検索結果の件数。",
+    responses((status = 200, description = "Prometheus text exposition format", content_type = "text/plain", body = String))
+)]
+pub(crate) async fn handle_metrics(ai_engine: AIEngine) -> Result<impl Reply, Rejection> {
+    let body = metrics::render(&ai_engine.stats());
+    Ok(warp::reply::with_header(body, "content-type", metrics::CONTENT_TYPE))
+}
+
+#[utoipa::path(
+    post,
+    path = "/chat",
+    tag = "chat",
+    description = "メッセージと `context` はまずモデレーションにかけ、止めたときはキャラクターが定型の返答をする（自傷の兆候には支援的な返答）。",
+    request_body = ChatRequest,
+    responses(
+        (status = 200, body = ChatResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 422, description = "Unknown character", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_chat(request: ChatRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    tracing::debug!(
+        character = ?request.character,
+        mode = ?request.mode,
+        session_id = ?request.session_id,
+        text = %redaction::for_log(&request.message),
+        context = ?request.context.as_deref().map(redaction::for_log),
+        "chat request"
+    );
+    request.validate(&ai_engine, config.max_text_length).map_err(warp::reject::custom)?;
+
+    let character = request.character.as_deref().unwrap_or(&config.default_character);
+    // 文脈もメッセージと一緒に返答に使うので、メッセージが通れば文脈も同じ基準で確かめる
+    let verdict = match (ai_engine.moderate(&request.message), request.context.as_deref()) {
+        (verdict, Some(context)) if verdict.is_allowed() => ai_engine.moderate(context),
+        (verdict, _) => verdict,
+    };
+    if !verdict.is_allowed() {
+        let response = ai_engine.moderated_reply(request.session_id.as_deref(), character, &request.message, verdict);
+        return Ok(warp::reply::json(&response));
+    }
+
+    let mut response = match (request.mode, request.session_id.as_deref()) {
+        (ChatMode::Socratic, Some(session_id)) => ai_engine.socratic_in_session(session_id, character, &request.message).await,
+        _ => ai_engine.chat_in_session(request.session_id.as_deref(), character, &request.message, request.context.as_deref()).await,
+    };
+    response.moderation = Some(verdict);
+
+    Ok(warp::reply::json(&response))
+}
+
+// 保存する分析の持ち主。自分のデータだけを扱う相手なら `user_id` の指定より自分を優先する
+fn owner_for_write(principal: &Option<Principal>, requested: Option<String>) -> Option<String> {
+    match principal {
+        Some(principal) => principal.owner().map(str::to_string).or(requested).or_else(|| principal.user_id.clone()),
+        None => requested,
+    }
+}
+
+fn analyze_options(request: &AnalyzeRequest, config: &ServerConfig, verdict: ModerationVerdict) -> AnalyzeOptions {
+    AnalyzeOptions {
+        character: Some(request.character.clone().unwrap_or_else(|| config.default_character.clone())),
+        summary_ratio: request.summary_ratio(),
+        session_id: request.session_id.clone(),
+        moderation: Some(verdict),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/analyze",
+    tag = "analysis",
+    request_body = AnalyzeRequest,
+    responses(
+        (status = 200, body = PhilosophyAnalysis),
+        (status = 400, body = ErrorResponse),
+        (status = 422, description = "Unknown character or blocked by moderation", body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_analysis(principal: Option<Principal>, request: AnalyzeRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    let verdict = request.validate(&ai_engine, config.max_text_length).map_err(warp::reject::custom)?;
+    let user_id = owner_for_write(&principal, request.user_id.clone());
+    let options = analyze_options(&request, &config, verdict);
+    let analysis: PhilosophyAnalysis = ai_engine.analyze_and_store(&request.text, user_id.as_deref(), options).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?
+        .into();
+
+    Ok(warp::reply::json(&analysis))
+}
+
+#[utoipa::path(
+    post,
+    path = "/analyze",
+    tag = "analysis",
+    request_body = AnalyzeRequest,
+    responses(
+        (status = 200, body = PhilosophyAnalysisV2),
+        (status = 400, body = ErrorResponse),
+        (status = 422, description = "Unknown character or blocked by moderation", body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_analysis_v2(principal: Option<Principal>, request: AnalyzeRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    let verdict = request.validate(&ai_engine, config.max_text_length).map_err(warp::reject::custom)?;
+    let user_id = owner_for_write(&principal, request.user_id.clone());
+    let options = analyze_options(&request, &config, verdict);
+    let analysis = ai_engine.analyze_and_store(&request.text, user_id.as_deref(), options).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+
+    Ok(warp::reply::json(&analysis))
+}
+
+#[utoipa::path(
+    post,
+    path = "/analyze/batch",
+    tag = "analysis",
+    description = "JSON（`{\"texts\": [...]}`）または NDJSON（1行に `{\"text\": ...}`）を受け取り、入力順の NDJSON を返す。",
+    request_body(content(
+        (BatchAnalyzeRequest = "application/json"),
+        (AnalyzeRequest = "application/x-ndjson")
+    )),
+    responses(
+        (status = 200, description = "1行ごとに `BatchItem`（`result` は `PhilosophyAnalysis`）", content_type = "application/x-ndjson", body = BatchItem<PhilosophyAnalysis>),
+        (status = 400, body = ErrorResponse),
+        (status = 413, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_analysis_batch(
+    principal: Option<Principal>,
+    content_type: Option<String>,
+    body: BodyStream,
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+) -> Result<warp::reply::Response, Rejection> {
+    analysis_batch::<PhilosophyAnalysis>(principal, content_type, body, ai_engine, config).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/analyze/batch",
+    tag = "analysis",
+    description = "JSON（`{\"texts\": [...]}`）または NDJSON（1行に `{\"text\": ...}`）を受け取り、入力順の NDJSON を返す。",
+    request_body(content(
+        (BatchAnalyzeRequest = "application/json"),
+        (AnalyzeRequest = "application/x-ndjson")
+    )),
+    responses(
+        (status = 200, description = "1行ごとに `BatchItem`（`result` は `PhilosophyAnalysisV2`）", content_type = "application/x-ndjson", body = BatchItem<PhilosophyAnalysisV2>),
+        (status = 400, body = ErrorResponse),
+        (status = 413, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_analysis_batch_v2(
+    principal: Option<Principal>,
+    content_type: Option<String>,
+    body: BodyStream,
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+) -> Result<warp::reply::Response, Rejection> {
+    analysis_batch::<PhilosophyAnalysisV2>(principal, content_type, body, ai_engine, config).await
+}
+
+async fn analysis_batch<T>(
+    principal: Option<Principal>,
+    content_type: Option<String>,
+    body: BodyStream,
+    ai_engine: AIEngine,
+    config: Arc<ServerConfig>,
+) -> Result<warp::reply::Response, Rejection>
+where
+    T: Serialize + From<PhilosophyAnalysisV2> + Send + 'static,
+{
+    let input = BatchInput::new(body, content_type.as_deref(), config.body_limit("analyze_batch"), config.max_batch_size)
+        .await
+        .map_err(warp::reject::custom)?;
+    let prepare = move |request: &mut AnalyzeRequest| {
+        request.user_id = owner_for_write(&principal, request.user_id.take());
+    };
+
+    Ok(batch::stream_analyses::<T, _>(ai_engine, input, prepare, config.max_text_length, config.default_character.clone()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/conviction",
+    tag = "analysis",
+    description = "ぼかし・断定・根拠・反語の表現から、テキストの確信度を 0〜10 で見積もる。",
+    request_body = ConvictionRequest,
+    responses((status = 200, body = ConvictionScore), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn handle_conviction(request: ConvictionRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    request.validate(config.max_text_length).map_err(warp::reject::custom)?;
+    Ok(warp::reply::json(&ai_engine.estimate_conviction(&request.text)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/summarize",
+    tag = "analysis",
+    description = "文を TextRank で順位付けし、上位の文を元の順に並べた抽出型の要約を返す。",
+    request_body = SummarizeRequest,
+    responses((status = 200, body = Summary), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn handle_summarize(request: SummarizeRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    request.validate(config.max_text_length).map_err(warp::reject::custom)?;
+    Ok(warp::reply::json(&ai_engine.summarize(&request.text, request.compression_ratio())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/similar",
+    tag = "analysis",
+    description = "文字 n-gram の埋め込みで、保存済みの分析と知恵からコサイン類似度の高いものを返す。認証が有効なら、サービスと管理者以外には自分の分析だけを返す。",
+    request_body = SimilarRequest,
+    responses(
+        (status = 200, body = SimilarResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_similar(principal: Option<Principal>, request: SimilarRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    request.validate(config.max_text_length).map_err(warp::reject::custom)?;
+
+    // 自分のデータだけを扱う相手には、他人の分析を起点にも結果にもさせない
+    let owner = principal.as_ref().and_then(Principal::owner);
+    let text = match (&request.text, &request.analysis_id) {
+        (Some(text), _) => text.clone(),
+        (None, Some(id)) => ai_engine.find_analysis(id, owner).await
+            .map_err(|e| warp::reject::custom(ApiError::from(e)))?
+            .ok_or_else(|| warp::reject::custom(ApiError::NotFound { resource: "analysis", id: id.clone() }))?
+            .text,
+        (None, None) => unreachable!("validated above"),
+    };
+    let results = ai_engine.find_similar(&text, request.kind, request.limit(), request.analysis_id.as_deref(), owner).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+
+    Ok(warp::reply::json(&SimilarResponse { results }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clusters",
+    tag = "analysis",
+    description = "テキスト（または保存済みの分析の時間窓）を TF-IDF と k-means でトピックに分けるジョブを始める。結果は `GET /clusters/{id}` で取得する。時間窓は管理者とサービス以外は自分の分析だけを集める。同時に走れるジョブの数には上限があり、埋まっていれば 503 を返す。",
+    request_body = ClusterRequest,
+    responses(
+        (status = 202, body = ClusterJob),
+        (status = 400, body = ErrorResponse),
+        (status = 413, body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_start_clustering(principal: Option<Principal>, request: ClusterRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    request.validate(config.max_text_length, config.max_batch_size).map_err(warp::reject::custom)?;
+    let owner = principal.as_ref().and_then(Principal::owner);
+    let job = ai_engine.start_clustering(request.source(owner), request.k)
+        .ok_or_else(|| warp::reject::custom(ApiError::Busy { resource: "clustering job", max: cluster::MAX_RUNNING_JOBS }))?;
+
+    Ok(warp::reply::with_status(warp::reply::json(&job), warp::http::StatusCode::ACCEPTED))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clusters/{id}",
+    tag = "analysis",
+    params(("id" = String, Path, description = "ジョブID")),
+    responses((status = 200, body = ClusterJob), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn handle_cluster_job(id: String, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection> {
+    match ai_engine.cluster_job(&id) {
+        Some(job) => Ok(warp::reply::json(&job)),
+        None => Err(warp::reject::custom(ApiError::NotFound { resource: "clustering job", id })),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/trends",
+    tag = "analysis",
+    description = "保存済みの分析から、感情とテーマの区間ごとの推移・移動平均・変化点を返す。管理者とサービス以外は自分の分析だけを集計する。",
+    params(TrendQuery),
+    responses((status = 200, body = TrendReport), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn handle_trends(principal: Option<Principal>, query: TrendQuery, ai_engine: AIEngine) -> Result<impl Reply, Rejection> {
+    query.validate().map_err(warp::reject::custom)?;
+    let mut spec = query.spec();
+    // 自分のデータだけを扱う相手は、自分の分析（とそのセッション）だけを集計する
+    if let Some(owner) = principal.as_ref().and_then(Principal::owner) {
+        spec.user_id = Some(owner.to_string());
+    }
+    let report = ai_engine.trends(spec).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+
+    Ok(warp::reply::json(&report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analysis/{id}",
+    tag = "analysis",
+    params(("id" = String, Path, description = "分析ID")),
+    responses((status = 200, body = PhilosophyAnalysis), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn handle_get_analysis(id: String, principal: Option<Principal>, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection> {
+    stored::<PhilosophyAnalysis>(id, principal, ai_engine).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/analysis/{id}",
+    tag = "analysis",
+    params(("id" = String, Path, description = "分析ID")),
+    responses((status = 200, body = PhilosophyAnalysisV2), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn handle_get_analysis_v2(id: String, principal: Option<Principal>, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection> {
+    stored::<PhilosophyAnalysisV2>(id, principal, ai_engine).await
+}
+
+async fn stored<T>(id: String, principal: Option<Principal>, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection>
+where
+    T: Serialize + From<PhilosophyAnalysisV2>,
+{
+    // 他人の分析は有無も明かさず 404 にする
+    let owner = principal.as_ref().and_then(Principal::owner);
+    let analysis = ai_engine.find_analysis(&id, owner).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound { resource: "analysis", id }))?;
+
+    Ok(warp::reply::json(&T::from(analysis)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analyses",
+    tag = "analysis",
+    description = "保存済みの分析を新しい順に返す。",
+    params(AnalysisQuery),
+    responses((status = 200, body = AnalysisPage<PhilosophyAnalysis>), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn handle_list_analyses(principal: Option<Principal>, query: AnalysisQuery, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection> {
+    list::<PhilosophyAnalysis>(principal, query, ai_engine).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/analyses",
+    tag = "analysis",
+    description = "保存済みの分析を新しい順に返す。",
+    params(AnalysisQuery),
+    responses((status = 200, body = AnalysisPage<PhilosophyAnalysisV2>), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn handle_list_analyses_v2(principal: Option<Principal>, query: AnalysisQuery, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection> {
+    list::<PhilosophyAnalysisV2>(principal, query, ai_engine).await
+}
+
+async fn list<T>(principal: Option<Principal>, mut query: AnalysisQuery, ai_engine: AIEngine) -> Result<warp::reply::Json, Rejection>
+where
+    T: Serialize + From<PhilosophyAnalysisV2>,
+{
+    if let Some(owner) = principal.as_ref().and_then(Principal::owner) {
+        query.user_id = Some(owner.to_string());
+    }
+    let (limit, offset) = (query.limit(), query.offset());
+    let (items, total) = ai_engine.list_analyses(query).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+
+    Ok(warp::reply::json(&AnalysisPage {
+        items: items.into_iter().map(T::from).collect::<Vec<_>>(),
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/analysis/{id}",
+    tag = "analysis",
+    description = "管理者とサービス以外は自分の分析だけを削除できる（他人の分析は 404）。",
+    params(("id" = String, Path, description = "分析ID")),
+    responses((status = 204, description = "Deleted"), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn handle_delete_analysis(id: String, principal: Option<Principal>, ai_engine: AIEngine) -> Result<impl Reply, Rejection> {
+    let owner = principal.as_ref().and_then(Principal::owner);
+    let deleted = ai_engine.delete_analysis(&id, owner).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+    if !deleted {
+        return Err(warp::reject::custom(ApiError::NotFound { resource: "analysis", id }));
+    }
+
+    Ok(warp::http::StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/analyses",
+    tag = "analysis",
+    description = "ユーザーの分析をすべて削除する（GDPRの削除要求向け）。管理者とサービス以外は自分の `user_id` だけを指定できる。",
+    params(UserAnalysesQuery),
+    responses(
+        (status = 200, body = DeletedResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, description = "Another user's analyses", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_delete_user_analyses(principal: Option<Principal>, query: UserAnalysesQuery, ai_engine: AIEngine) -> Result<impl Reply, Rejection> {
+    let user_id = query.validate().map_err(warp::reject::custom)?;
+    if principal.as_ref().and_then(Principal::owner).is_some_and(|owner| owner != user_id) {
+        return Err(warp::reject::custom(auth::AuthError::Forbidden));
+    }
+    let deleted = ai_engine.delete_user_analyses(user_id).await
+        .map_err(|e| warp::reject::custom(ApiError::from(e)))?;
+
+    Ok(warp::reply::json(&DeletedResponse { deleted }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/personalities",
+    tag = "characters",
+    responses((status = 200, body = HashMap<String, CharacterPersonality>))
+)]
+pub(crate) async fn get_personalities(ai_engine: AIEngine) -> Result<impl Reply, Rejection> {
+    let personalities = ai_engine.get_character_personalities().await;
+    Ok(warp::reply::json(&personalities))
+}
+
+#[utoipa::path(
+    put,
+    path = "/characters/{id}",
+    tag = "characters",
+    description = "キャラクターを追加するか置き換える。処理中の会話は古い定義を使い切る。",
+    params(("id" = String, Path, description = "キャラクターID（英数字・`-`・`_`）")),
+    request_body = CharacterRequest,
+    responses(
+        (status = 200, description = "Replaced", body = Character),
+        (status = 201, description = "Created", body = Character),
+        (status = 400, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_put_character(id: String, request: CharacterRequest, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    request.validate(&id, config.max_text_length).map_err(warp::reject::custom)?;
+    let character = request.into_character();
+    let created = ai_engine.upsert_character(&id, character.clone());
+    tracing::info!(character = %id, created, "character saved");
+
+    let status = if created { warp::http::StatusCode::CREATED } else { warp::http::StatusCode::OK };
+    Ok(warp::reply::with_status(warp::reply::json(&character), status))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/characters/{id}",
+    tag = "characters",
+    params(("id" = String, Path, description = "キャラクターID")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "The default character cannot be deleted", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn handle_delete_character(id: String, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    if id == config.default_character {
+        return Err(warp::reject::custom(ApiError::Conflict(format!("`{}` is the default character and cannot be deleted", id))));
+    }
+    if !ai_engine.remove_character(&id) {
+        return Err(warp::reject::custom(ApiError::NotFound { resource: "character", id }));
+    }
+    tracing::info!(character = %id, "character deleted");
+    Ok(warp::http::StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/wisdom",
+    tag = "wisdom",
+    params(WisdomQuery),
+    responses((status = 200, body = WisdomResponse), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn generate_wisdom(query: WisdomQuery, ai_engine: AIEngine, config: Arc<ServerConfig>) -> Result<impl Reply, Rejection> {
+    query.validate(config.max_text_length).map_err(warp::reject::custom)?;
+    let theme = query.theme.unwrap_or_else(|| "life".to_string());
+    let wisdom = ai_engine.generate_wisdom(&theme).await;
+
+    Ok(warp::reply::json(&WisdomResponse {
+        theme,
+        wisdom,
+        timestamp: Utc::now(),
+    }))
+}
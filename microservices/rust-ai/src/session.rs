@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::socratic::SocraticDialogue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Speaker {
+    User,
+    Character,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Turn {
+    pub speaker: Speaker,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 1つの会話セッション（キャラクターとのやり取りの履歴）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Session {
+    pub id: String,
+    pub character: String,
+    pub turns: Vec<Turn>,
+    pub updated_at: DateTime<Utc>,
+    /// 進行中（または直前に終わった）ソクラテス式対話
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialogue: Option<SocraticDialogue>,
+}
+
+/// セッション履歴のストア。
+///
+/// `AIEngine` のクローン間で共有されるので、内部で `RwLock` を使う。
+/// セッション数とターン数には上限があり、超えたら古いものから捨てる。
+pub struct SessionStore {
+    sessions: RwLock<HashMap<String, Session>>,
+    max_sessions: usize,
+    max_turns: usize,
+}
+
+impl SessionStore {
+    pub fn new(max_sessions: usize, max_turns: usize) -> Self {
+        SessionStore {
+            sessions: RwLock::new(HashMap::new()),
+            max_sessions,
+            max_turns,
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<Session> {
+        self.sessions.read().unwrap().get(session_id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// ユーザーの発言とキャラクターの返答を1往復として記録する
+    pub fn record_exchange(&self, session_id: &str, character: &str, message: &str, reply: &str) {
+        let now = Utc::now();
+        let mut sessions = self.sessions.write().unwrap();
+
+        if !sessions.contains_key(session_id) && sessions.len() >= self.max_sessions {
+            let oldest = sessions.values()
+                .min_by_key(|session| session.updated_at)
+                .map(|session| session.id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+
+        let session = sessions.entry(session_id.to_string()).or_insert_with(|| Session {
+            id: session_id.to_string(),
+            character: character.to_string(),
+            turns: Vec::new(),
+            updated_at: now,
+            dialogue: None,
+        });
+
+        session.character = character.to_string();
+        session.turns.push(Turn { speaker: Speaker::User, text: message.to_string(), timestamp: now });
+        session.turns.push(Turn { speaker: Speaker::Character, text: reply.to_string(), timestamp: now });
+        session.updated_at = now;
+
+        if session.turns.len() > self.max_turns {
+            let excess = session.turns.len() - self.max_turns;
+            session.turns.drain(..excess);
+        }
+    }
+
+    /// セッションの対話状態を更新する（セッションが無ければ何もしない）
+    pub fn set_dialogue(&self, session_id: &str, dialogue: Option<SocraticDialogue>) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(session_id) {
+            session.dialogue = dialogue;
+        }
+    }
+
+    pub fn remove(&self, session_id: &str) -> Option<Session> {
+        self.sessions.write().unwrap().remove(session_id)
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(10_000, 100)
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::nlp::excerpt;
+use crate::storage::StoreError;
+
+/// 埋め込みベクトルの次元（特徴量ハッシュのバケット数）
+pub const DIMENSIONS: u32 = 2048;
+pub const DEFAULT_LIMIT: usize = 5;
+pub const MAX_LIMIT: usize = 20;
+
+// 結果に載せる本文の最大文字数
+const EXCERPT_CHARS: usize = 200;
+
+/// 検索対象の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    /// 保存済みの分析
+    Analysis,
+    /// 知恵データベースの一文
+    Wisdom,
+}
+
+/// 近い順に並んだ検索結果の1件
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarItem {
+    pub id: String,
+    pub kind: DocumentKind,
+    pub text: String,
+    /// コサイン類似度（-1.0〜1.0）
+    pub score: f32,
+}
+
+/// 疎な埋め込みベクトル（バケット番号の昇順、L2ノルム 1）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Embedding(Vec<(u32, f32)>);
+
+impl Embedding {
+    /// 文字 n-gram（2〜3文字）と英単語を特徴量ハッシュで固定次元に落とす。
+    ///
+    /// 外部のモデルを使わず、日本語のように空白で区切らない文章でも
+    /// 言い回しの重なりを拾える。ハッシュは FNV-1a で、保存した索引を
+    /// 別のビルドで読み込んでも同じベクトルになる。
+    pub fn of(text: &str) -> Self {
+        let normalized: String = text.to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+            .collect();
+
+        let mut counts: HashMap<u32, f32> = HashMap::new();
+        let mut add = |feature: &str| {
+            let hash = fnv1a(feature.as_bytes());
+            let bucket = (hash % DIMENSIONS as u64) as u32;
+            // 衝突の偏りを打ち消すため、別のビットで符号を決める
+            let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+            *counts.entry(bucket).or_insert(0.0) += sign;
+        };
+
+        for word in normalized.split_whitespace() {
+            let chars: Vec<char> = word.chars().collect();
+            if word.is_ascii() {
+                add(&format!("w:{}", word));
+            }
+            for n in 2..=3 {
+                for gram in chars.windows(n) {
+                    add(&format!("{}:{}", n, gram.iter().collect::<String>()));
+                }
+            }
+            if chars.len() == 1 {
+                add(&format!("1:{}", word));
+            }
+        }
+
+        // 出現回数は対数で抑える
+        let mut weights: Vec<(u32, f32)> = counts.into_iter()
+            .filter(|(_, count)| *count != 0.0)
+            .map(|(bucket, count)| (bucket, count.signum() * (1.0 + count.abs().ln())))
+            .collect();
+        let norm = weights.iter().map(|(_, w)| w * w).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            weights.iter_mut().for_each(|(_, w)| *w /= norm);
+        }
+        weights.sort_by_key(|(bucket, _)| *bucket);
+        Embedding(weights)
+    }
+
+    /// どちらも正規化済みなので内積がそのままコサイン類似度になる
+    pub fn cosine(&self, other: &Embedding) -> f32 {
+        let (mut i, mut j, mut dot) = (0, 0, 0.0);
+        while i < self.0.len() && j < other.0.len() {
+            let (a, b) = (self.0[i], other.0[j]);
+            match a.0.cmp(&b.0) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    dot += a.1 * b.1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        dot
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    text: String,
+    vector: Embedding,
+    /// 分析の持ち主（知恵と、持ち主の無い分析には無い）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+}
+
+// ID から位置を引ける分析の一覧（読み込みでの置き換えと削除を O(1) にする）
+#[derive(Default)]
+struct Entries {
+    entries: Vec<Entry>,
+    positions: HashMap<String, usize>,
+}
+
+impl Entries {
+    // 同じ ID があれば置き換えて `true` を返す
+    fn insert(&mut self, entry: Entry) -> bool {
+        match self.positions.get(&entry.id) {
+            Some(&position) => {
+                self.entries[position] = entry;
+                true
+            }
+            None => {
+                self.positions.insert(entry.id.clone(), self.entries.len());
+                self.entries.push(entry);
+                false
+            }
+        }
+    }
+
+    // 末尾の要素を空いた位置へ移して詰める（検索は全件を比べるので順序は要らない）
+    fn remove(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+        self.entries.swap_remove(position);
+        if let Some(moved) = self.entries.get(position) {
+            self.positions.insert(moved.id.clone(), position);
+        }
+        true
+    }
+}
+
+// 索引ファイルの1行。追記だけで更新し、読み込み時に置き換えと削除を反映して詰め直す。
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Insert(Entry),
+    Remove { id: String },
+}
+
+/// 保存済みの分析と知恵を対象にした近傍検索の索引。
+///
+/// 分析は保存のたびに追加し、ファイルを開いていれば JSON Lines で追記する。
+/// 知恵はエンジンのデータから毎回作り直すのでファイルには書かない。
+/// 件数は分析ストアと同程度（数万件）を想定し、検索は全件のコサイン類似度を比べる。
+/// 分析には持ち主を持たせ、検索をその人の分析に絞れるようにする
+/// （持ち主を記録する前の索引ファイルは、消せば起動時にストアから作り直す）。
+pub struct SimilarityIndex {
+    analyses: RwLock<Entries>,
+    wisdom: RwLock<Vec<Entry>>,
+    file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl SimilarityIndex {
+    /// ファイルに保存しない索引
+    pub fn in_memory() -> Self {
+        SimilarityIndex {
+            analyses: RwLock::new(Entries::default()),
+            wisdom: RwLock::new(Vec::new()),
+            file: None,
+        }
+    }
+
+    /// 索引ファイルを読み込み（無ければ作り）、以後の追加をそこへ追記する
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| StoreError::Backend(format!("cannot create {}: {}", parent.display(), e)))?;
+        }
+
+        let mut entries = Entries::default();
+        let mut stale = false;
+        if path.exists() {
+            let reader = BufReader::new(File::open(path).map_err(|e| io_error(path, e))?);
+            for line in reader.lines() {
+                let line = line.map_err(|e| io_error(path, e))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Record>(&line)? {
+                    Record::Insert(entry) => stale |= entries.insert(entry),
+                    Record::Remove { id } => {
+                        entries.remove(&id);
+                        stale = true;
+                    }
+                }
+            }
+        }
+
+        // 置き換えか削除で古くなった行があれば、今の中身だけで書き直す
+        if stale {
+            let compacted = path.with_extension("jsonl.tmp");
+            let mut writer = BufWriter::new(File::create(&compacted).map_err(|e| io_error(&compacted, e))?);
+            for entry in &entries.entries {
+                write_record(&mut writer, &Record::Insert(entry.clone())).map_err(|e| io_error(&compacted, e))?;
+            }
+            writer.flush().map_err(|e| io_error(&compacted, e))?;
+            std::fs::rename(&compacted, path).map_err(|e| io_error(path, e))?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| io_error(path, e))?;
+        Ok(SimilarityIndex {
+            analyses: RwLock::new(entries),
+            wisdom: RwLock::new(Vec::new()),
+            file: Some(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// 知恵の一覧を入れ替える（ID は `wisdom-{番号}`）
+    pub fn set_wisdom(&self, wisdom: &[String]) {
+        let entries = wisdom.iter()
+            .enumerate()
+            .map(|(i, text)| Entry { id: format!("wisdom-{}", i), text: text.clone(), vector: Embedding::of(text), owner: None })
+            .collect();
+        *self.wisdom.write().unwrap() = entries;
+    }
+
+    /// 分析を追加する（同じ ID があれば置き換える）
+    pub fn insert(&self, id: &str, text: &str, owner: Option<&str>) -> Result<(), StoreError> {
+        let entry = Entry { id: id.to_string(), text: excerpt(text, EXCERPT_CHARS), vector: Embedding::of(text), owner: owner.map(str::to_string) };
+        self.append(&Record::Insert(entry.clone()))?;
+
+        self.analyses.write().unwrap().insert(entry);
+        Ok(())
+    }
+
+    /// 分析を取り除く。索引に無ければ `false`。
+    pub fn remove(&self, id: &str) -> Result<bool, StoreError> {
+        let removed = self.analyses.write().unwrap().remove(id);
+        if removed {
+            self.append(&Record::Remove { id: id.to_string() })?;
+        }
+        Ok(removed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.analyses.read().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `query` に近い順に返す。`kind` で対象を絞り、`exclude` の ID は除く。
+    /// `owner` を指定すると、分析はその持ち主のものだけにする（知恵は絞らない）。
+    pub fn search(&self, query: &str, kind: Option<DocumentKind>, limit: usize, exclude: Option<&str>, owner: Option<&str>) -> Vec<SimilarItem> {
+        let query = Embedding::of(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let analyses = self.analyses.read().unwrap();
+        let wisdom = self.wisdom.read().unwrap();
+        let candidates = [(DocumentKind::Analysis, &analyses.entries), (DocumentKind::Wisdom, &*wisdom)];
+
+        let mut results: Vec<SimilarItem> = candidates.iter()
+            .filter(|(k, _)| kind.is_none_or(|kind| kind == *k))
+            .flat_map(|(k, entries)| entries.iter().map(move |entry| (*k, entry)))
+            .filter(|(_, entry)| exclude != Some(entry.id.as_str()))
+            .filter(|(k, entry)| *k == DocumentKind::Wisdom || owner.is_none() || entry.owner.as_deref() == owner)
+            .map(|(kind, entry)| SimilarItem {
+                id: entry.id.clone(),
+                kind,
+                text: entry.text.clone(),
+                score: query.cosine(&entry.vector),
+            })
+            .filter(|item| item.score > 0.0)
+            .collect();
+
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        results.truncate(limit);
+        results.iter_mut().for_each(|item| item.score = (item.score * 1000.0).round() / 1000.0);
+        results
+    }
+
+    fn append(&self, record: &Record) -> Result<(), StoreError> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut writer = file.lock().unwrap();
+        write_record(&mut *writer, record)
+            .and_then(|_| writer.flush())
+            .map_err(|e| StoreError::Backend(format!("cannot write similarity index: {}", e)))
+    }
+}
+
+impl Default for SimilarityIndex {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+/// 索引ファイルの置き場所（分析をメモリに保存するなら索引もメモリだけにする）
+pub fn index_path(backend: &str, data_dir: &Path) -> Option<PathBuf> {
+    (backend != "memory").then(|| data_dir.join("similarity.jsonl"))
+}
+
+fn write_record(writer: &mut impl Write, record: &Record) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> StoreError {
+    StoreError::Backend(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("philosophy-ai-similarity-{}", uuid::Uuid::new_v4())).join("similarity.jsonl")
+    }
+
+    #[test]
+    fn replays_inserts_replacements_and_removals() {
+        let path = temp_path();
+        {
+            let index = SimilarityIndex::open(&path).unwrap();
+            index.insert("a", "自由と責任について", Some("alice")).unwrap();
+            index.insert("b", "宇宙と星の話", Some("bob")).unwrap();
+            index.insert("c", "幸福とは何か", None).unwrap();
+            index.insert("b", "自由な宇宙", Some("bob")).unwrap();
+            assert!(index.remove("c").unwrap());
+            assert!(!index.remove("missing").unwrap());
+        }
+
+        let index = SimilarityIndex::open(&path).unwrap();
+        assert_eq!(index.len(), 2);
+        let ids: Vec<String> = index.search("自由", None, 10, None, None).into_iter().map(|item| item.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(index.search("幸福", None, 10, None, None).is_empty());
+        let owned: Vec<String> = index.search("自由", None, 10, None, Some("alice")).into_iter().map(|item| item.id).collect();
+        assert_eq!(owned, vec!["a".to_string()]);
+
+        // 古い行は読み込み時に詰め直している
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 2);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn excludes_and_filters_by_kind() {
+        let index = SimilarityIndex::in_memory();
+        index.set_wisdom(&["自由は責任を伴う".to_string()]);
+        index.insert("a", "自由と責任", None).unwrap();
+
+        let kinds: Vec<DocumentKind> = index.search("自由と責任", None, 10, None, None).into_iter().map(|item| item.kind).collect();
+        assert_eq!(kinds.len(), 2);
+        let wisdom = index.search("自由と責任", Some(DocumentKind::Wisdom), 10, None, None);
+        assert_eq!(wisdom.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec!["wisdom-0"]);
+        assert!(index.search("自由と責任", Some(DocumentKind::Analysis), 10, Some("a"), None).is_empty());
+    }
+}
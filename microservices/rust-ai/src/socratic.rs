@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::character_ai::Character;
+use crate::nlp::excerpt;
+use crate::question::voice;
+
+/// ソクラテス式対話の段階（この順に進む）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SocraticStage {
+    /// 主張の意味をはっきりさせる
+    Clarify,
+    /// 前提を疑う
+    Assumptions,
+    /// 根拠を求める
+    Evidence,
+    /// 別の見方を考える
+    Alternatives,
+    /// 帰結を考える
+    Consequences,
+    /// 振り返る
+    Reflect,
+    /// まとめを返して終了
+    Done,
+}
+
+impl SocraticStage {
+    pub const STEPS: usize = 6;
+
+    pub fn next(self) -> SocraticStage {
+        match self {
+            SocraticStage::Clarify => SocraticStage::Assumptions,
+            SocraticStage::Assumptions => SocraticStage::Evidence,
+            SocraticStage::Evidence => SocraticStage::Alternatives,
+            SocraticStage::Alternatives => SocraticStage::Consequences,
+            SocraticStage::Consequences => SocraticStage::Reflect,
+            SocraticStage::Reflect | SocraticStage::Done => SocraticStage::Done,
+        }
+    }
+
+    /// 1始まりの段階番号
+    pub fn step(self) -> usize {
+        match self {
+            SocraticStage::Clarify => 1,
+            SocraticStage::Assumptions => 2,
+            SocraticStage::Evidence => 3,
+            SocraticStage::Alternatives => 4,
+            SocraticStage::Consequences => 5,
+            SocraticStage::Reflect | SocraticStage::Done => 6,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SocraticStage::Clarify => "明確化",
+            SocraticStage::Assumptions => "前提",
+            SocraticStage::Evidence => "根拠",
+            SocraticStage::Alternatives => "別の見方",
+            SocraticStage::Consequences => "帰結",
+            SocraticStage::Reflect => "振り返り",
+            SocraticStage::Done => "まとめ",
+        }
+    }
+}
+
+/// 段階ごとの答え
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReasoningStep {
+    pub stage: SocraticStage,
+    pub answer: String,
+}
+
+/// セッションに保存する対話の状態
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SocraticDialogue {
+    pub stage: SocraticStage,
+    /// 対話のきっかけになった主張
+    pub claim: String,
+    pub topic: String,
+    pub steps: Vec<ReasoningStep>,
+    /// 今の段階で短い答えを掘り下げ済みか
+    #[serde(default)]
+    pub probed: bool,
+    /// 主張の確信度（0〜10）
+    pub initial_conviction: u8,
+}
+
+/// 対話の最後に返すユーザーの考えのまとめ
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SocraticSummary {
+    pub claim: String,
+    pub topic: String,
+    pub themes: Vec<String>,
+    pub steps: Vec<ReasoningStep>,
+    pub conviction_before: u8,
+    pub conviction_after: u8,
+}
+
+/// チャットのレスポンスに付ける対話の進み具合
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SocraticTurn {
+    pub stage: SocraticStage,
+    pub step: usize,
+    pub total_steps: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<SocraticSummary>,
+}
+
+// この文字数より短い答えは、次に進む前に一度だけ掘り下げる
+const SHORT_ANSWER: usize = 8;
+
+// まとめに引用する答えの長さ
+const EXCERPT_CHARS: usize = 60;
+
+// 段階ごとの問い（テーマ別の問いを優先し、足りなければ共通の問いを使う）。
+// `{topic}` は対話のトピック、`{focus}` は直前の答えの内容語に置き換える。
+const QUESTIONS: [(SocraticStage, Option<&str>, &str); 26] = [
+    (SocraticStage::Clarify, Some("technology"), "「{topic}」は、技術そのものの話？それとも使う人間の話？"),
+    (SocraticStage::Clarify, Some("philosophy"), "ここで言う「{topic}」を、自分の言葉で定義するとどうなる？"),
+    (SocraticStage::Clarify, Some("life"), "「{topic}」は、あなた自身の経験から来ている考え？"),
+    (SocraticStage::Clarify, Some("connection"), "「{topic}」で思い浮かぶのは、誰との関係？"),
+    (SocraticStage::Clarify, None, "「{topic}」とは、具体的にどういうことを指しているの？"),
+    (SocraticStage::Assumptions, Some("technology"), "技術が進めば必ず良くなる、という前提を置いていないかな？"),
+    (SocraticStage::Assumptions, Some("philosophy"), "その考えが正しいためには、何が真でなければならないだろう？"),
+    (SocraticStage::Assumptions, Some("life"), "それは誰の人生にも当てはまると考えている？"),
+    (SocraticStage::Assumptions, Some("connection"), "相手も同じように感じている、と仮定していないかな？"),
+    (SocraticStage::Assumptions, None, "その考えは、どんな前提の上に立っているんだろう？"),
+    (SocraticStage::Evidence, Some("technology"), "実際に使ってみて、「{focus}」だと感じた出来事はある？"),
+    (SocraticStage::Evidence, Some("life"), "これまでの人生で、「{focus}」を確かめた瞬間はあった？"),
+    (SocraticStage::Evidence, None, "「{focus}」と言える根拠は何だろう？"),
+    (SocraticStage::Evidence, None, "その考えを支える経験やデータはある？"),
+    (SocraticStage::Alternatives, Some("philosophy"), "別の哲学者なら、「{topic}」をどう捉えると思う？"),
+    (SocraticStage::Alternatives, Some("connection"), "相手の立場から見ると、どう見えるだろう？"),
+    (SocraticStage::Alternatives, None, "反対の立場の人なら、どう反論するかな？"),
+    (SocraticStage::Alternatives, None, "「{topic}」について、まったく別の見方をするとしたら？"),
+    (SocraticStage::Consequences, Some("technology"), "その考えのとおりに技術が広まったら、10年後に何が変わっている？"),
+    (SocraticStage::Consequences, Some("life"), "その考えで生きていくと、5年後のあなたはどうなっている？"),
+    (SocraticStage::Consequences, Some("connection"), "みんながそう考えたら、人と人との関係はどう変わるだろう？"),
+    (SocraticStage::Consequences, None, "もしその考えが正しいとしたら、次に何が起こる？"),
+    (SocraticStage::Consequences, None, "その考えを貫いたとき、失うものはあるかな？"),
+    (SocraticStage::Reflect, None, "ここまで考えてみて、最初の「{topic}」についての考えは変わった？"),
+    (SocraticStage::Reflect, None, "今の考えを一言でまとめるとしたら？"),
+    (SocraticStage::Reflect, Some("philosophy"), "この対話で、一番揺らいだ前提はどれだった？"),
+];
+
+const PROBE: &str = "もう少し詳しく聞かせてくれる？「{focus}」と思ったのはなぜ？";
+
+impl SocraticDialogue {
+    pub fn start(claim: &str, topic: String, initial_conviction: u8) -> Self {
+        SocraticDialogue {
+            stage: SocraticStage::Clarify,
+            claim: claim.to_string(),
+            topic,
+            steps: Vec::new(),
+            probed: false,
+            initial_conviction,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.stage == SocraticStage::Done
+    }
+
+    /// ユーザーの答えを記録して次の段階に進む。
+    /// 短すぎる答えには一度だけ同じ段階で掘り下げる（戻り値 `false`）。
+    pub fn record_answer(&mut self, answer: &str) -> bool {
+        let answer = answer.trim();
+        if answer.chars().count() < SHORT_ANSWER && !self.probed {
+            self.probed = true;
+            self.steps.push(ReasoningStep { stage: self.stage, answer: answer.to_string() });
+            return false;
+        }
+
+        self.steps.push(ReasoningStep { stage: self.stage, answer: answer.to_string() });
+        self.stage = self.stage.next();
+        self.probed = false;
+        true
+    }
+
+    /// 対話でユーザーが書いたすべての文
+    pub fn user_text(&self) -> String {
+        std::iter::once(self.claim.as_str())
+            .chain(self.steps.iter().map(|step| step.answer.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn turn(&self, summary: Option<SocraticSummary>) -> SocraticTurn {
+        SocraticTurn {
+            stage: self.stage,
+            step: self.stage.step(),
+            total_steps: SocraticStage::STEPS,
+            summary,
+        }
+    }
+
+    /// 今の段階の問いを、対話の主なテーマと直前の答えに合わせて選ぶ
+    pub fn next_question(&self, character: &Character, themes: &[String], focus: Option<&str>, probing: bool) -> String {
+        let voice = voice(character);
+        let focus = focus.unwrap_or(&self.topic);
+
+        let template = if probing {
+            PROBE
+        } else {
+            let candidates: Vec<&str> = themes.iter()
+                .flat_map(|theme| QUESTIONS.iter().filter(move |(stage, t, _)| *stage == self.stage && *t == Some(theme.as_str())))
+                .chain(QUESTIONS.iter().filter(|(stage, t, _)| *stage == self.stage && t.is_none()))
+                .map(|(_, _, question)| *question)
+                .collect();
+            // 同じ段階を掘り下げた後は別の問いにする
+            let asked = self.steps.iter().filter(|step| step.stage == self.stage).count();
+            candidates.get(asked % candidates.len().max(1)).copied().unwrap_or(PROBE)
+        };
+        let question = template.replace("{topic}", &self.topic).replace("{focus}", focus);
+
+        let lead = match (self.stage, self.steps.is_empty()) {
+            (SocraticStage::Clarify, true) => format!("「{}」について、{}", self.topic, voice.invite),
+            _ => String::new(),
+        };
+        format!("{} {}[{}/{} {}] {}", character.emoji, lead, self.stage.step(), SocraticStage::STEPS, self.stage.label(), question)
+    }
+
+    pub fn summary(&self, themes: Vec<String>, conviction_after: u8) -> SocraticSummary {
+        SocraticSummary {
+            claim: self.claim.clone(),
+            topic: self.topic.clone(),
+            themes,
+            steps: self.steps.clone(),
+            conviction_before: self.initial_conviction,
+            conviction_after,
+        }
+    }
+}
+
+/// まとめをキャラクターの口調で文章にする
+pub fn render_summary(character: &Character, summary: &SocraticSummary) -> String {
+    let voice = voice(character);
+    let mut lines = vec![
+        format!("{} ここまでの対話をまとめるね。", character.emoji),
+        format!("・主張: {}", excerpt(&summary.claim, EXCERPT_CHARS)),
+    ];
+    for step in &summary.steps {
+        lines.push(format!("・{}: {}", step.stage.label(), excerpt(&step.answer, EXCERPT_CHARS)));
+    }
+    lines.push(format!("確信度: {} → {}", summary.conviction_before, summary.conviction_after));
+    lines.push(voice.closer.to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_ai::CharacterPersonality;
+
+    fn frog() -> Character {
+        Character {
+            name: "寂しガエル".to_string(),
+            emoji: "🐸".to_string(),
+            personality: CharacterPersonality {
+                traits: Vec::new(),
+                language_style: "Rust".to_string(),
+                response_patterns: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn stages_advance_in_order_and_short_answers_are_probed_once() {
+        let mut dialogue = SocraticDialogue::start("自由には責任が伴う", "自由".to_string(), 7);
+        assert!(!dialogue.record_answer("はい"));
+        assert_eq!(dialogue.stage, SocraticStage::Clarify);
+        assert!(dialogue.record_answer("うん"));
+        assert_eq!(dialogue.stage, SocraticStage::Assumptions);
+
+        for _ in 0..5 {
+            assert!(dialogue.record_answer("自分で選んだことの結果を引き受けることだと思う"));
+        }
+        assert!(dialogue.is_finished());
+        assert_eq!(dialogue.turn(None).step, SocraticStage::STEPS);
+        assert_eq!(dialogue.steps.len(), 7);
+        assert!(dialogue.user_text().starts_with("自由には責任が伴う\nはい\n"));
+    }
+
+    #[test]
+    fn questions_prefer_the_dialogue_theme_and_fill_in_the_topic() {
+        let dialogue = SocraticDialogue::start("技術は人を幸せにする", "技術".to_string(), 5);
+        let question = dialogue.next_question(&frog(), &["technology".to_string()], None, false);
+        assert!(question.starts_with("🐸 「技術」について、共に深く潜ってみるなり..."));
+        assert!(question.contains("[1/6 明確化]"));
+        assert!(question.contains("「技術」は、技術そのものの話？"));
+
+        let probe = dialogue.next_question(&frog(), &[], Some("幸せ"), true);
+        assert!(probe.contains("「幸せ」と思ったのはなぜ？"));
+    }
+
+    #[test]
+    fn the_summary_quotes_each_step() {
+        let mut dialogue = SocraticDialogue::start("自由には責任が伴う", "自由".to_string(), 7);
+        dialogue.record_answer("選んだ結果を引き受けること");
+        let summary = dialogue.summary(vec!["philosophy".to_string()], 5);
+        let text = render_summary(&frog(), &summary);
+        assert!(text.contains("・主張: 自由には責任が伴う"));
+        assert!(text.contains("・明確化: 選んだ結果を引き受けること"));
+        assert!(text.contains("確信度: 7 → 5"));
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// エンジンの利用統計。
+///
+/// `AIEngine` のクローン間で共有されるカウンター群。
+#[derive(Default)]
+pub struct EngineStats {
+    chats: AtomicU64,
+    analyses: AtomicU64,
+    wisdom_hits: AtomicU64,
+    wisdom_fallbacks: AtomicU64,
+    characters: Mutex<HashMap<String, u64>>,
+    sentiments: Mutex<HashMap<String, u64>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct StatsSnapshot {
+    pub chats: u64,
+    pub analyses: u64,
+    pub wisdom_hits: u64,
+    pub wisdom_fallbacks: u64,
+    pub characters: HashMap<String, u64>,
+    pub sentiments: HashMap<String, u64>,
+}
+
+impl EngineStats {
+    pub fn record_chat(&self, character: &str, sentiment: &str) {
+        self.chats.fetch_add(1, Ordering::Relaxed);
+        *self.characters.lock().unwrap().entry(character.to_string()).or_insert(0) += 1;
+        self.record_sentiment(sentiment);
+    }
+
+    pub fn record_analysis(&self, sentiment: &str) {
+        self.analyses.fetch_add(1, Ordering::Relaxed);
+        self.record_sentiment(sentiment);
+    }
+
+    pub fn record_wisdom(&self, hit: bool) {
+        let counter = if hit { &self.wisdom_hits } else { &self.wisdom_fallbacks };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_sentiment(&self, sentiment: &str) {
+        *self.sentiments.lock().unwrap().entry(sentiment.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            chats: self.chats.load(Ordering::Relaxed),
+            analyses: self.analyses.load(Ordering::Relaxed),
+            wisdom_hits: self.wisdom_hits.load(Ordering::Relaxed),
+            wisdom_fallbacks: self.wisdom_fallbacks.load(Ordering::Relaxed),
+            characters: self.characters.lock().unwrap().clone(),
+            sentiments: self.sentiments.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::ai_engine::PhilosophyAnalysisV2;
+use crate::config::StorageSettings;
+
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Backend(String),
+    Serialization(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Backend(message) => write!(f, "storage backend error: {}", message),
+            StoreError::Serialization(message) => write!(f, "failed to (de)serialize analysis: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Serialization(e.to_string())
+    }
+}
+
+/// 保存済み分析の一覧取得条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalysisQuery {
+    /// このテーマを含む分析だけを返す
+    pub theme: Option<String>,
+    /// 感情ラベル（positive / negative / neutral）
+    pub sentiment: Option<String>,
+    /// 持ち主で絞る（認証が有効なら、サービスと管理者以外は自分の分析だけになる）
+    pub user_id: Option<String>,
+    /// この日時以降の分析だけを返す（RFC3339）
+    pub since: Option<DateTime<Utc>>,
+    /// この日時より前の分析だけを返す（RFC3339）
+    pub until: Option<DateTime<Utc>>,
+    /// 1ページの件数（既定 20、最大 100）
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl AnalysisQuery {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    fn matches(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> bool {
+        self.theme.as_ref().is_none_or(|theme| analysis.themes.iter().any(|t| &t.theme == theme))
+            && self.sentiment.as_ref().is_none_or(|sentiment| &analysis.sentiment.label == sentiment)
+            && self.user_id.as_deref().is_none_or(|id| user_id == Some(id))
+            && self.since.is_none_or(|since| analysis.timestamp >= since)
+            && self.until.is_none_or(|until| analysis.timestamp < until)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AnalysisPage<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// 分析結果の保存先。
+///
+/// 同期APIなので、非同期コンテキストからは `spawn_blocking` 経由で呼ぶ
+/// （`AIEngine` の `*_analysis` メソッドがそうしている）。
+pub trait AnalysisStore: Send + Sync {
+    fn save(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> Result<(), StoreError>;
+    /// 分析と、その持ち主のユーザーID
+    fn get(&self, id: &str) -> Result<Option<(PhilosophyAnalysisV2, Option<String>)>, StoreError>;
+    /// 新しい順に1ページ分と、条件に合う総件数を返す
+    fn list(&self, query: &AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError>;
+    fn delete(&self, id: &str) -> Result<bool, StoreError>;
+    /// ユーザーの分析をすべて削除する（GDPR対応）
+    fn delete_by_user(&self, user_id: &str) -> Result<usize, StoreError>;
+}
+
+/// プロセス内だけに保持するストア。上限を超えたら古いものから捨てる。
+pub struct MemoryStore {
+    entries: RwLock<Vec<(PhilosophyAnalysisV2, Option<String>)>>,
+    capacity: usize,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            entries: RwLock::new(Vec::new()),
+            capacity,
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+impl AnalysisStore for MemoryStore {
+    fn save(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> Result<(), StoreError> {
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|(existing, _)| existing.id != analysis.id);
+        entries.push((analysis.clone(), user_id.map(str::to_string)));
+        if entries.len() > self.capacity {
+            let excess = entries.len() - self.capacity;
+            entries.drain(..excess);
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<(PhilosophyAnalysisV2, Option<String>)>, StoreError> {
+        let entries = self.entries.read().unwrap();
+        Ok(entries.iter().find(|(analysis, _)| analysis.id == id).cloned())
+    }
+
+    fn list(&self, query: &AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+        let entries = self.entries.read().unwrap();
+        let mut matching: Vec<&PhilosophyAnalysisV2> = entries.iter()
+            .filter(|(analysis, user_id)| query.matches(analysis, user_id.as_deref()))
+            .map(|(analysis, _)| analysis)
+            .collect();
+        matching.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.id.cmp(&b.id)));
+
+        let total = matching.len();
+        let items = matching.into_iter()
+            .skip(query.offset())
+            .take(query.limit())
+            .cloned()
+            .collect();
+        Ok((items, total))
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, StoreError> {
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        entries.retain(|(analysis, _)| analysis.id != id);
+        Ok(entries.len() != before)
+    }
+
+    fn delete_by_user(&self, user_id: &str) -> Result<usize, StoreError> {
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        entries.retain(|(_, owner)| owner.as_deref() != Some(user_id));
+        Ok(before - entries.len())
+    }
+}
+
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS philosophy_analyses (
+    id TEXT PRIMARY KEY,
+    user_id TEXT,
+    text TEXT NOT NULL,
+    sentiment TEXT NOT NULL,
+    themes TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_created_at ON philosophy_analyses(created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_sentiment ON philosophy_analyses(sentiment);
+CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_user_id ON philosophy_analyses(user_id);
+";
+
+// テーマはJSON配列で持ち、json_each で絞り込む
+const SQLITE_FILTER: &str = "
+    (?1 IS NULL OR EXISTS (SELECT 1 FROM json_each(philosophy_analyses.themes) WHERE json_each.value = ?1))
+    AND (?2 IS NULL OR sentiment = ?2)
+    AND (?3 IS NULL OR user_id = ?3)
+    AND (?4 IS NULL OR created_at >= ?4)
+    AND (?5 IS NULL OR created_at < ?5)
+";
+
+/// SQLiteに保存するストア（デフォルト）
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| StoreError::Backend(format!("cannot create {}: {}", parent.display(), e)))?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(SQLITE_SCHEMA)?;
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+}
+
+fn theme_names(analysis: &PhilosophyAnalysisV2) -> Vec<String> {
+    analysis.themes.iter().map(|t| t.theme.clone()).collect()
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+impl AnalysisStore for SqliteStore {
+    fn save(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> Result<(), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO philosophy_analyses (id, user_id, text, sentiment, themes, payload, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                analysis.id,
+                user_id,
+                analysis.text,
+                analysis.sentiment.label,
+                serde_json::to_string(&theme_names(analysis))?,
+                serde_json::to_string(analysis)?,
+                timestamp(&analysis.timestamp),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<(PhilosophyAnalysisV2, Option<String>)>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let row: Option<(String, Option<String>)> = conn
+            .query_row("SELECT payload, user_id FROM philosophy_analyses WHERE id = ?1", params![id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        row.map(|(payload, user_id)| Ok((serde_json::from_str(&payload)?, user_id))).transpose()
+    }
+
+    fn list(&self, query: &AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let since = query.since.as_ref().map(timestamp);
+        let until = query.until.as_ref().map(timestamp);
+        let filter = params![query.theme, query.sentiment, query.user_id, since, until];
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM philosophy_analyses WHERE {}", SQLITE_FILTER),
+            filter,
+            |row| row.get(0),
+        )?;
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT payload FROM philosophy_analyses WHERE {} ORDER BY created_at DESC, id LIMIT ?6 OFFSET ?7",
+            SQLITE_FILTER
+        ))?;
+        let rows = statement.query_map(
+            params![query.theme, query.sentiment, query.user_id, since, until, query.limit() as i64, query.offset() as i64],
+            |row| row.get::<_, String>(0),
+        )?;
+
+        let mut items = Vec::new();
+        for payload in rows {
+            items.push(serde_json::from_str(&payload?)?);
+        }
+        Ok((items, total as usize))
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM philosophy_analyses WHERE id = ?1", params![id])? > 0)
+    }
+
+    fn delete_by_user(&self, user_id: &str) -> Result<usize, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM philosophy_analyses WHERE user_id = ?1", params![user_id])?)
+    }
+}
+
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresStore;
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use tokio::runtime::Handle;
+    use tokio_postgres::{Client, NoTls};
+    use super::{theme_names, AnalysisQuery, AnalysisStore, StoreError};
+    use crate::ai_engine::PhilosophyAnalysisV2;
+
+    // db/schema.sql の philosophy_analyses と同じ定義
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS philosophy_analyses (
+            id VARCHAR(36) PRIMARY KEY,
+            user_id VARCHAR(100),
+            text TEXT NOT NULL,
+            sentiment VARCHAR(20) NOT NULL,
+            themes TEXT[] NOT NULL DEFAULT '{}',
+            payload JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_created_at ON philosophy_analyses(created_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_sentiment ON philosophy_analyses(sentiment);
+        CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_user_id ON philosophy_analyses(user_id);
+        CREATE INDEX IF NOT EXISTS idx_philosophy_analyses_themes ON philosophy_analyses USING GIN(themes);
+    ";
+
+    const FILTER: &str = "
+        ($1::text IS NULL OR $1 = ANY(themes))
+        AND ($2::text IS NULL OR sentiment = $2)
+        AND ($3::text IS NULL OR user_id = $3)
+        AND ($4::timestamptz IS NULL OR created_at >= $4)
+        AND ($5::timestamptz IS NULL OR created_at < $5)
+    ";
+
+    /// PostgreSQLに保存するストア（`postgres` feature）
+    pub struct PostgresStore {
+        client: Client,
+        handle: Handle,
+    }
+
+    impl From<tokio_postgres::Error> for StoreError {
+        fn from(e: tokio_postgres::Error) -> Self {
+            StoreError::Backend(e.to_string())
+        }
+    }
+
+    impl PostgresStore {
+        pub async fn connect(url: &str) -> Result<Self, StoreError> {
+            let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    tracing::error!(error = %e, "postgres connection error");
+                }
+            });
+            client.batch_execute(SCHEMA).await?;
+            Ok(PostgresStore { client, handle: Handle::current() })
+        }
+    }
+
+    impl AnalysisStore for PostgresStore {
+        fn save(&self, analysis: &PhilosophyAnalysisV2, user_id: Option<&str>) -> Result<(), StoreError> {
+            let payload = serde_json::to_value(analysis)?;
+            self.handle.block_on(self.client.execute(
+                "INSERT INTO philosophy_analyses (id, user_id, text, sentiment, themes, payload, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (id) DO UPDATE SET payload = EXCLUDED.payload",
+                &[
+                    &analysis.id,
+                    &user_id,
+                    &analysis.text,
+                    &analysis.sentiment.label,
+                    &theme_names(analysis),
+                    &payload,
+                    &analysis.timestamp,
+                ],
+            ))?;
+            Ok(())
+        }
+
+        fn get(&self, id: &str) -> Result<Option<(PhilosophyAnalysisV2, Option<String>)>, StoreError> {
+            let row = self.handle.block_on(self.client.query_opt(
+                "SELECT payload, user_id FROM philosophy_analyses WHERE id = $1",
+                &[&id],
+            ))?;
+            row.map(|row| Ok((serde_json::from_value(row.get(0))?, row.get(1)))).transpose()
+        }
+
+        fn list(&self, query: &AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+            let total: i64 = self.handle.block_on(self.client.query_one(
+                &format!("SELECT COUNT(*) FROM philosophy_analyses WHERE {}", FILTER),
+                &[&query.theme, &query.sentiment, &query.user_id, &query.since, &query.until],
+            ))?.get(0);
+
+            let rows = self.handle.block_on(self.client.query(
+                &format!("SELECT payload FROM philosophy_analyses WHERE {} ORDER BY created_at DESC, id LIMIT $6 OFFSET $7", FILTER),
+                &[&query.theme, &query.sentiment, &query.user_id, &query.since, &query.until, &(query.limit() as i64), &(query.offset() as i64)],
+            ))?;
+
+            let items = rows.into_iter()
+                .map(|row| serde_json::from_value(row.get(0)).map_err(StoreError::from))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((items, total as usize))
+        }
+
+        fn delete(&self, id: &str) -> Result<bool, StoreError> {
+            let deleted = self.handle.block_on(self.client.execute(
+                "DELETE FROM philosophy_analyses WHERE id = $1",
+                &[&id],
+            ))?;
+            Ok(deleted > 0)
+        }
+
+        fn delete_by_user(&self, user_id: &str) -> Result<usize, StoreError> {
+            let deleted = self.handle.block_on(self.client.execute(
+                "DELETE FROM philosophy_analyses WHERE user_id = $1",
+                &[&user_id],
+            ))?;
+            Ok(deleted as usize)
+        }
+    }
+}
+
+/// 設定に従ってストアを開く（SQLiteは `data_dir/analyses.db`）
+pub async fn open_store(settings: &StorageSettings) -> Result<Arc<dyn AnalysisStore>, StoreError> {
+    match settings.backend.as_str() {
+        "memory" => Ok(Arc::new(MemoryStore::default())),
+        "sqlite" => Ok(Arc::new(SqliteStore::open(&settings.data_dir.join("analyses.db"))?)),
+        #[cfg(feature = "postgres")]
+        "postgres" => {
+            let url = settings.database_url.as_deref()
+                .ok_or_else(|| StoreError::Backend("`storage.database_url` is required for postgres".to_string()))?;
+            Ok(Arc::new(PostgresStore::connect(url).await?))
+        }
+        other => Err(StoreError::Backend(format!("unsupported storage backend `{}`", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::*;
+    use crate::ai_engine::{AIEngine, AnalyzeOptions};
+
+    fn analysis(engine: &AIEngine, text: &str, minutes_ago: i64) -> PhilosophyAnalysisV2 {
+        let mut analysis = engine.analyze_text(text, &AnalyzeOptions::default());
+        analysis.timestamp = Utc::now() - Duration::minutes(minutes_ago);
+        analysis
+    }
+
+    fn ids(items: &[PhilosophyAnalysisV2]) -> Vec<&str> {
+        items.iter().map(|a| a.id.as_str()).collect()
+    }
+
+    fn check_store(store: &dyn AnalysisStore) {
+        let engine = AIEngine::new();
+        let oldest = analysis(&engine, "幸せで嬉しい一日だった。", 30);
+        let middle = analysis(&engine, "悲しくて辛い。", 20);
+        let newest = analysis(&engine, "技術と哲学について考える。", 10);
+        store.save(&oldest, Some("alice")).unwrap();
+        store.save(&middle, Some("bob")).unwrap();
+        store.save(&newest, Some("alice")).unwrap();
+
+        let (got, owner) = store.get(&middle.id).unwrap().unwrap();
+        assert_eq!(got.text, middle.text);
+        assert_eq!(owner.as_deref(), Some("bob"));
+        assert!(store.get("missing").unwrap().is_none());
+
+        // 新しい順のページ分けと総件数
+        let page = AnalysisQuery { limit: Some(2), ..AnalysisQuery::default() };
+        let (items, total) = store.list(&page).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(ids(&items), vec![newest.id.as_str(), middle.id.as_str()]);
+        let (items, _) = store.list(&AnalysisQuery { offset: Some(2), ..page }).unwrap();
+        assert_eq!(ids(&items), vec![oldest.id.as_str()]);
+
+        let by_user = AnalysisQuery { user_id: Some("alice".to_string()), ..AnalysisQuery::default() };
+        let (items, total) = store.list(&by_user).unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(ids(&items), vec![newest.id.as_str(), oldest.id.as_str()]);
+
+        let by_sentiment = AnalysisQuery { sentiment: Some(middle.sentiment.label.clone()), user_id: Some("bob".to_string()), ..AnalysisQuery::default() };
+        assert_eq!(ids(&store.list(&by_sentiment).unwrap().0), vec![middle.id.as_str()]);
+
+        let theme = newest.themes[0].theme.clone();
+        let (items, _) = store.list(&AnalysisQuery { theme: Some(theme.clone()), ..AnalysisQuery::default() }).unwrap();
+        assert!(items.iter().all(|a| a.themes.iter().any(|t| t.theme == theme)));
+        assert!(ids(&items).contains(&newest.id.as_str()));
+
+        let window = AnalysisQuery {
+            since: Some(Utc::now() - Duration::minutes(25)),
+            until: Some(Utc::now() - Duration::minutes(15)),
+            ..AnalysisQuery::default()
+        };
+        assert_eq!(ids(&store.list(&window).unwrap().0), vec![middle.id.as_str()]);
+
+        assert_eq!(store.delete_by_user("alice").unwrap(), 2);
+        assert_eq!(store.list(&AnalysisQuery::default()).unwrap().1, 1);
+        assert!(store.delete(&middle.id).unwrap());
+        assert!(!store.delete(&middle.id).unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_filters_pages_and_deletes() {
+        check_store(&SqliteStore::in_memory().unwrap());
+    }
+
+    #[test]
+    fn memory_store_filters_pages_and_deletes() {
+        check_store(&MemoryStore::default());
+    }
+}
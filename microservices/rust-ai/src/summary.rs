@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::matcher::{char_offset, KeywordMatch, Vocabulary};
+use crate::nlp::NLPProcessor;
+use crate::question::content_words;
+
+/// 要約で残す文の割合の既定値
+pub const DEFAULT_COMPRESSION_RATIO: f32 = 0.3;
+
+// TextRank の減衰係数と反復回数
+const DAMPING: f32 = 0.85;
+const ITERATIONS: usize = 30;
+// 語彙（テーマ・学派・専門用語）に当たった語1つあたりの加点の割合
+const KEYWORD_BOOST: f32 = 0.2;
+
+/// 要約に選ばれた文
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SummarySentence {
+    /// 元テキストでの文の番号（0始まり）
+    pub index: usize,
+    pub text: String,
+    /// 元テキストでの位置（文字単位、`end` は含まない）
+    pub start: usize,
+    pub end: usize,
+    pub score: f32,
+}
+
+/// 抽出型の要約（選んだ文を元の順に並べたもの）
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Summary {
+    pub text: String,
+    pub sentences: Vec<SummarySentence>,
+    /// 要求された圧縮率（残す文の割合）
+    pub compression_ratio: f32,
+    pub total_sentences: usize,
+}
+
+/// `scan` 済みの一致を使って、文を TextRank で順位付けし上位の文を残す。
+///
+/// 文どうしの類似度は共通する内容語の数を文の長さ（対数）で割ったもの。
+/// テーマ・学派・専門用語に当たる語を含む文は少し上げる。
+/// 残す文の数は `ceil(文の数 × compression_ratio)`（最低1文）。
+pub fn summarize(text: &str, matches: &[KeywordMatch<'_>], compression_ratio: f32) -> Summary {
+    let spans: Vec<(usize, usize)> = NLPProcessor::sentence_spans(text).into_iter()
+        .map(|(start, end)| trimmed(text, start, end))
+        .collect();
+    let words: Vec<HashSet<String>> = spans.iter()
+        .map(|&(start, end)| content_words(&text[start..end]).into_iter().map(|w| w.to_lowercase()).collect())
+        .collect();
+
+    let ranks = text_rank(&words);
+    let scores: Vec<f32> = spans.iter()
+        .zip(ranks)
+        .map(|(&(start, end), rank)| rank * (1.0 + KEYWORD_BOOST * keyword_hits(matches, start, end) as f32))
+        .collect();
+
+    let keep = ((spans.len() as f32 * compression_ratio).ceil() as usize).clamp(1, spans.len().max(1));
+    let mut order: Vec<usize> = (0..spans.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then_with(|| a.cmp(&b)));
+    order.truncate(keep);
+    order.sort();
+
+    let sentences: Vec<SummarySentence> = order.into_iter()
+        .map(|index| {
+            let (start, end) = spans[index];
+            SummarySentence {
+                index,
+                text: text[start..end].to_string(),
+                start: char_offset(text, start),
+                end: char_offset(text, end),
+                score: (scores[index] * 1000.0).round() / 1000.0,
+            }
+        })
+        .collect();
+
+    Summary {
+        text: join(&sentences),
+        sentences,
+        compression_ratio,
+        total_sentences: spans.len(),
+    }
+}
+
+/// 文をノード、類似度を重みとするグラフで PageRank を回す
+fn text_rank(words: &[HashSet<String>]) -> Vec<f32> {
+    let n = words.len();
+    let mut weights = vec![vec![0.0_f32; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let shared = words[i].intersection(&words[j]).count() as f32;
+            if shared == 0.0 {
+                continue;
+            }
+            let norm = (words[i].len() as f32).ln() + (words[j].len() as f32).ln();
+            let similarity = if norm > 0.0 { shared / norm } else { shared };
+            weights[i][j] = similarity;
+            weights[j][i] = similarity;
+        }
+    }
+    let out_weights: Vec<f32> = weights.iter().map(|row| row.iter().sum()).collect();
+
+    let mut ranks = vec![1.0_f32; n];
+    for _ in 0..ITERATIONS {
+        ranks = (0..n)
+            .map(|i| {
+                let incoming: f32 = (0..n)
+                    .filter(|&j| out_weights[j] > 0.0)
+                    .map(|j| weights[j][i] / out_weights[j] * ranks[j])
+                    .sum();
+                (1.0 - DAMPING) + DAMPING * incoming
+            })
+            .collect();
+    }
+    ranks
+}
+
+// 文の中で当たったテーマ・学派・専門用語の種類数
+fn keyword_hits(matches: &[KeywordMatch<'_>], start: usize, end: usize) -> usize {
+    matches.iter()
+        .filter(|m| m.start >= start && m.end <= end)
+        .filter(|m| matches!(m.vocabulary, Vocabulary::Theme(_) | Vocabulary::Tech | Vocabulary::School(_)))
+        .map(|m| m.keyword)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+fn trimmed(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let sentence = &text[start..end];
+    let start = start + (sentence.len() - sentence.trim_start().len());
+    (start, start + sentence.trim().len())
+}
+
+// 英語の文の間だけ空白を入れる
+fn join(sentences: &[SummarySentence]) -> String {
+    let mut joined = String::new();
+    for sentence in sentences {
+        if joined.chars().next_back().is_some_and(|c| c.is_ascii()) {
+            joined.push(' ');
+        }
+        joined.push_str(&sentence.text);
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nlp::NLPProcessor;
+
+    const TEXT: &str = "自由には責任が伴う。責任を果たすことで自由は意味を持つ。今日は雨だった。自由と責任は哲学の中心的な問いである。";
+
+    fn summarize(text: &str, ratio: f32) -> Summary {
+        NLPProcessor::new().summarize(text, ratio)
+    }
+
+    #[test]
+    fn ranks_connected_sentences_above_isolated_ones() {
+        let summary = summarize(TEXT, 1.0);
+        assert_eq!(summary.total_sentences, 4);
+        let isolated = summary.sentences.iter().find(|s| s.text == "今日は雨だった。").unwrap();
+        assert!(summary.sentences.iter().filter(|s| s.index != isolated.index).all(|s| s.score > isolated.score));
+    }
+
+    #[test]
+    fn keeps_the_ratio_in_original_order() {
+        let summary = summarize(TEXT, 0.5);
+        let indices: Vec<usize> = summary.sentences.iter().map(|s| s.index).collect();
+        assert_eq!(indices.len(), 2);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(!indices.contains(&2));
+    }
+
+    #[test]
+    fn positions_are_in_characters() {
+        let summary = summarize(TEXT, 1.0);
+        for sentence in &summary.sentences {
+            let text: String = TEXT.chars().skip(sentence.start).take(sentence.end - sentence.start).collect();
+            assert_eq!(text, sentence.text);
+        }
+    }
+}
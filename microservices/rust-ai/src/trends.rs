@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 時系列にする保存済み分析の最大件数
+pub const MAX_ANALYSES: usize = 20_000;
+/// 1系列あたりの区間数の上限
+pub const MAX_BUCKETS: usize = 366;
+/// 移動平均の区間数の上限
+pub const MAX_WINDOW: usize = 30;
+/// 返す系列の数の上限（件数の多い順に残す）
+pub const MAX_SERIES: usize = 20;
+
+// 直前の区間のばらつき（標準偏差）の何倍ずれたら変化点とするか
+const CHANGE_THRESHOLD: f32 = 2.0;
+// ばらつきが小さいときでも、これ未満のずれは変化点にしない
+const MIN_SENTIMENT_SHIFT: f32 = 0.3;
+const MIN_COUNT_SHIFT: f32 = 3.0;
+// 件数は直前の平均の半分以上ずれたときだけ数える
+const RELATIVE_COUNT_SHIFT: f32 = 0.5;
+
+/// 集計の区間（UTC で区切り、週は月曜始まり）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    #[default]
+    Day,
+    Week,
+}
+
+impl Interval {
+    fn length(self) -> Duration {
+        match self {
+            Interval::Day => Duration::days(1),
+            Interval::Week => Duration::weeks(1),
+        }
+    }
+
+    /// 範囲を省略したときに遡る長さ
+    pub fn default_range(self) -> Duration {
+        match self {
+            Interval::Day => Duration::days(30),
+            Interval::Week => Duration::weeks(12),
+        }
+    }
+
+    /// 移動平均の既定の区間数
+    pub fn default_window(self) -> usize {
+        match self {
+            Interval::Day => 7,
+            Interval::Week => 4,
+        }
+    }
+
+    /// `at` を含む区間の始まり
+    pub fn floor(self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let day = at.date_naive();
+        let day = match self {
+            Interval::Day => day,
+            Interval::Week => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+        };
+        day.and_time(NaiveTime::MIN).and_utc()
+    }
+
+    /// `since..until` を覆う区間の数
+    pub fn buckets(self, since: DateTime<Utc>, until: DateTime<Utc>) -> usize {
+        let span = until - self.floor(since);
+        let length = self.length();
+        ((span.num_seconds() + length.num_seconds() - 1) / length.num_seconds()).max(1) as usize
+    }
+}
+
+/// 系列の分け方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    /// すべてを1系列にする
+    #[default]
+    All,
+    /// 分析を求めたキャラクターごと
+    Character,
+    /// 会話（セッション）ごと
+    Session,
+    /// テキストの言語ごと
+    Language,
+}
+
+/// 集計の指定（`TrendQuery` の既定値を埋めたもの）
+#[derive(Debug, Clone)]
+pub struct TrendSpec {
+    pub interval: Interval,
+    pub group_by: GroupBy,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub window: usize,
+    pub user_id: Option<String>,
+}
+
+/// 集計する分析1件分
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    /// 系列の名前（`GroupBy::All` なら `all`）
+    pub group: String,
+    pub sentiment: String,
+    /// -1.0〜1.0
+    pub score: f32,
+    pub themes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// 直前の区間から大きく外れた値
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChangePoint {
+    /// `sentiment`・`count`・`theme:{テーマ名}` のいずれか
+    pub metric: String,
+    pub direction: Direction,
+    pub value: f32,
+    /// 直前の区間（移動平均の幅）の平均
+    pub baseline: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SentimentCounts {
+    pub positive: usize,
+    pub negative: usize,
+    pub neutral: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrendPoint {
+    /// 区間の始まり
+    pub start: DateTime<Utc>,
+    pub count: usize,
+    /// 感情スコアの平均（分析が無い区間は null）
+    pub average_sentiment: Option<f32>,
+    /// 直近の区間での感情スコアの平均（件数で重み付け）
+    pub sentiment_moving_average: Option<f32>,
+    /// 直近の区間での1区間あたりの件数
+    pub count_moving_average: f32,
+    pub sentiments: SentimentCounts,
+    /// テーマごとの出現件数
+    pub themes: BTreeMap<String, usize>,
+    pub change_points: Vec<ChangePoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrendSeries {
+    pub group: String,
+    pub total: usize,
+    pub points: Vec<TrendPoint>,
+}
+
+/// ダッシュボード向けの時系列
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrendReport {
+    pub interval: Interval,
+    pub group_by: GroupBy,
+    /// 最初の区間の始まり
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// 移動平均と変化点に使う区間数
+    pub window: usize,
+    /// 集計した分析の件数
+    pub analyses: usize,
+    /// 件数の上限（`MAX_ANALYSES`）で打ち切ったか。打ち切ると古い区間の件数が
+    /// 欠けるので、変化点は返さない
+    pub truncated: bool,
+    pub series: Vec<TrendSeries>,
+}
+
+#[derive(Default, Clone)]
+struct Bucket {
+    count: usize,
+    score: f32,
+    sentiments: SentimentCounts,
+    themes: BTreeMap<String, usize>,
+}
+
+/// 分析を区間ごと・系列ごとに集計し、移動平均と変化点を付ける。
+///
+/// 分析の無い区間も0件として並べるので、どの系列も同じ区間の並びになる。
+/// 変化点は直前 `window` 区間の平均から、標準偏差の2倍（かつ最小幅）以上
+/// ずれた区間に付ける。直前の区間が足りないうちは判定しない。
+pub fn aggregate(samples: &[Sample], spec: &TrendSpec) -> Vec<TrendSeries> {
+    let TrendSpec { interval, group_by, since, until, window, .. } = *spec;
+    let first = interval.floor(since);
+    let length = interval.length();
+    let count = interval.buckets(since, until);
+
+    let mut groups: HashMap<&str, Vec<Bucket>> = HashMap::new();
+    for sample in samples.iter().filter(|s| s.timestamp >= since && s.timestamp < until) {
+        let index = ((sample.timestamp - first).num_seconds() / length.num_seconds()) as usize;
+        let buckets = groups.entry(sample.group.as_str()).or_insert_with(|| vec![Bucket::default(); count]);
+        let bucket = &mut buckets[index.min(count - 1)];
+        bucket.count += 1;
+        bucket.score += sample.score;
+        match sample.sentiment.as_str() {
+            "positive" => bucket.sentiments.positive += 1,
+            "negative" => bucket.sentiments.negative += 1,
+            _ => bucket.sentiments.neutral += 1,
+        }
+        for theme in &sample.themes {
+            *bucket.themes.entry(theme.clone()).or_insert(0) += 1;
+        }
+    }
+    if groups.is_empty() && group_by == GroupBy::All {
+        groups.insert("all", vec![Bucket::default(); count]);
+    }
+
+    let mut series: Vec<TrendSeries> = groups.into_iter()
+        .map(|(group, buckets)| TrendSeries {
+            group: group.to_string(),
+            total: buckets.iter().map(|b| b.count).sum(),
+            points: points(&buckets, first, length, window),
+        })
+        .collect();
+    series.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.group.cmp(&b.group)));
+    series.truncate(MAX_SERIES);
+    series
+}
+
+fn points(buckets: &[Bucket], first: DateTime<Utc>, length: Duration, window: usize) -> Vec<TrendPoint> {
+    buckets.iter()
+        .enumerate()
+        .map(|(i, bucket)| {
+            let recent = &buckets[(i + 1).saturating_sub(window)..=i];
+            let recent_count: usize = recent.iter().map(|b| b.count).sum();
+            let previous = &buckets[i.saturating_sub(window)..i];
+
+            let average = (bucket.count > 0).then(|| bucket.score / bucket.count as f32);
+            let mut change_points = Vec::new();
+            if let Some(average) = average {
+                let history: Vec<f32> = previous.iter()
+                    .filter(|b| b.count > 0)
+                    .map(|b| b.score / b.count as f32)
+                    .collect();
+                change_points.extend(change_point("sentiment", &history, average, MIN_SENTIMENT_SHIFT, 0.0));
+            }
+            if previous.len() == window {
+                let history: Vec<f32> = previous.iter().map(|b| b.count as f32).collect();
+                change_points.extend(change_point("count", &history, bucket.count as f32, MIN_COUNT_SHIFT, RELATIVE_COUNT_SHIFT));
+
+                let themes: BTreeSet<&String> = previous.iter().chain([bucket]).flat_map(|b| b.themes.keys()).collect();
+                for theme in themes {
+                    let frequency = |b: &Bucket| b.themes.get(theme).copied().unwrap_or(0) as f32;
+                    let history: Vec<f32> = previous.iter().map(frequency).collect();
+                    let metric = format!("theme:{}", theme);
+                    change_points.extend(change_point(&metric, &history, frequency(bucket), MIN_COUNT_SHIFT, RELATIVE_COUNT_SHIFT));
+                }
+            }
+
+            TrendPoint {
+                start: first + length * i as i32,
+                count: bucket.count,
+                average_sentiment: average.map(rounded),
+                sentiment_moving_average: (recent_count > 0)
+                    .then(|| rounded(recent.iter().map(|b| b.score).sum::<f32>() / recent_count as f32)),
+                count_moving_average: rounded(recent_count as f32 / recent.len() as f32),
+                sentiments: bucket.sentiments.clone(),
+                themes: bucket.themes.clone(),
+                change_points,
+            }
+        })
+        .collect()
+}
+
+// 直前の値の平均から `max(2σ, min_shift, relative × 平均)` を超えてずれていれば変化点
+fn change_point(metric: &str, history: &[f32], value: f32, min_shift: f32, relative: f32) -> Option<ChangePoint> {
+    if history.len() < 2 {
+        return None;
+    }
+    let mean = history.iter().sum::<f32>() / history.len() as f32;
+    let variance = history.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / history.len() as f32;
+    let threshold = (CHANGE_THRESHOLD * variance.sqrt()).max(min_shift).max(relative * mean.abs());
+    ((value - mean).abs() > threshold).then(|| ChangePoint {
+        metric: metric.to_string(),
+        direction: if value > mean { Direction::Up } else { Direction::Down },
+        value: rounded(value),
+        baseline: rounded(mean),
+    })
+}
+
+fn rounded(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn day(n: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, n, 12, 0, 0).unwrap()
+    }
+
+    fn sample(n: u32, score: f32) -> Sample {
+        Sample { timestamp: day(n), group: "all".to_string(), sentiment: "neutral".to_string(), score, themes: Vec::new() }
+    }
+
+    fn spec(days: u32) -> TrendSpec {
+        TrendSpec {
+            interval: Interval::Day,
+            group_by: GroupBy::All,
+            since: Interval::Day.floor(day(1)),
+            until: Interval::Day.floor(day(days + 1)),
+            window: 3,
+            user_id: None,
+        }
+    }
+
+    fn change_points(series: &[TrendSeries]) -> Vec<(usize, String, Direction)> {
+        series[0].points.iter()
+            .enumerate()
+            .flat_map(|(i, point)| point.change_points.iter().map(move |c| (i, c.metric.clone(), c.direction)))
+            .collect()
+    }
+
+    #[test]
+    fn fills_empty_buckets_and_averages() {
+        let samples = [sample(1, 0.5), sample(1, -0.5), sample(3, 1.0)];
+        let series = aggregate(&samples, &spec(4));
+        assert_eq!(series.len(), 1);
+        let counts: Vec<usize> = series[0].points.iter().map(|p| p.count).collect();
+        assert_eq!(counts, vec![2, 0, 1, 0]);
+        assert_eq!(series[0].points[0].average_sentiment, Some(0.0));
+        assert_eq!(series[0].points[1].average_sentiment, None);
+    }
+
+    #[test]
+    fn flags_a_jump_after_a_steady_baseline() {
+        let mut samples: Vec<Sample> = (1..=5).map(|n| sample(n, 0.0)).collect();
+        samples.push(sample(6, 0.9));
+        let points = change_points(&aggregate(&samples, &spec(6)));
+        assert_eq!(points, vec![(5, "sentiment".to_string(), Direction::Up)]);
+
+        let mut samples: Vec<Sample> = (1..=5).map(|n| sample(n, 0.0)).collect();
+        samples.extend((0..10).map(|_| sample(6, 0.0)));
+        let points = change_points(&aggregate(&samples, &spec(6)));
+        assert_eq!(points, vec![(5, "count".to_string(), Direction::Up)]);
+    }
+
+    #[test]
+    fn steady_or_short_series_have_no_change_points() {
+        let samples: Vec<Sample> = (1..=6).map(|n| sample(n, 0.2)).collect();
+        assert!(change_points(&aggregate(&samples, &spec(6))).is_empty());
+
+        // 直前の区間が窓に満たないうちは判定しない
+        let samples = [sample(1, 0.0), sample(2, 1.0)];
+        assert!(change_points(&aggregate(&samples, &spec(2))).is_empty());
+    }
+}
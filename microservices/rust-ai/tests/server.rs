@@ -0,0 +1,398 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use serde_json::Value;
+use warp::http::StatusCode;
+use philosophy_ai::auth::Access;
+use philosophy_ai::config::{AuthSettings, BucketSettings, RateLimitSettings};
+use philosophy_ai::server::{self, ServerConfig};
+use philosophy_ai::storage::{AnalysisQuery, AnalysisStore, StoreError};
+use philosophy_ai::{AIEngine, PhilosophyAnalysisV2};
+
+fn app(config: ServerConfig) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    server::app(AIEngine::new(), config)
+}
+
+const API_KEY: &str = "test-api-key-0123456789";
+const JWT_SECRET: &str = "test-jwt-secret";
+
+fn auth_config(roles: &[&str]) -> ServerConfig {
+    ServerConfig {
+        auth: AuthSettings {
+            enabled: true,
+            api_keys: vec![API_KEY.to_string()],
+            api_key_roles: roles.iter().map(|role| role.to_string()).collect(),
+            jwt_secret: Some(JWT_SECRET.to_string()),
+            ..AuthSettings::default()
+        },
+        ..ServerConfig::default()
+    }
+}
+
+// `sub` と `roles` を持つ1時間有効な Bearer トークン
+fn bearer(sub: &str, roles: &[&str]) -> String {
+    let exp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 3600;
+    let claims = serde_json::json!({ "sub": sub, "roles": roles, "exp": exp });
+    let key = jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes());
+    format!("Bearer {}", jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap())
+}
+
+fn json(body: &[u8]) -> Value {
+    serde_json::from_slice(body).expect("the response body should be JSON")
+}
+
+#[tokio::test]
+async fn the_router_serves_health_and_personalities() {
+    let app = app(ServerConfig::default());
+
+    let response = warp::test::request().path("/health").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = json(response.body());
+    assert_eq!(body["status"], "healthy");
+    assert_eq!(body["service"], "philosophy-ai");
+
+    let response = warp::test::request().path("/v1/personalities").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = json(response.body());
+    for character in ["frog", "fugu", "snowman"] {
+        assert!(body[character].is_object(), "{} should be listed", character);
+    }
+}
+
+#[tokio::test]
+async fn validation_errors_use_the_json_error_shape() {
+    let app = app(ServerConfig::default());
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze")
+        .json(&serde_json::json!({ "text": "   " }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = json(response.body());
+    assert_eq!(body["code"], "missing_field");
+    assert_eq!(body["field"], "text");
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze")
+        .json(&serde_json::json!({ "text": "自由とは何か", "character": "nobody" }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json(response.body())["code"], "unknown_character");
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze")
+        .header("content-type", "application/json")
+        .body("{not json")
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(json(response.body())["code"], "invalid_body");
+
+    let response = warp::test::request().path("/v1/nowhere").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(json(response.body())["code"], "not_found");
+}
+
+#[tokio::test]
+async fn versions_return_their_own_models_and_legacy_paths_are_deprecated() {
+    let app = app(ServerConfig::default());
+    let analyze = |path: &'static str| {
+        warp::test::request()
+            .method("POST")
+            .path(path)
+            .json(&serde_json::json!({ "text": "人生の意味とは何か。自由には責任が伴う。" }))
+            .reply(&app)
+    };
+
+    let v1 = analyze("/v1/analyze").await;
+    assert_eq!(v1.status(), StatusCode::OK);
+    assert!(v1.headers().get("deprecation").is_none());
+    assert!(json(v1.body())["sentiment"].is_string());
+
+    let v2 = analyze("/v2/analyze").await;
+    assert_eq!(v2.status(), StatusCode::OK);
+    assert!(v2.headers().get("deprecation").is_none());
+    let body = json(v2.body());
+    assert!(body["sentiment"].is_object());
+    assert!(body["fallacies"].is_array());
+
+    let legacy = analyze("/analyze").await;
+    assert_eq!(legacy.status(), StatusCode::OK);
+    assert_eq!(legacy.headers()["deprecation"], "true");
+    assert_eq!(legacy.headers()["link"], "</v1>; rel=\"successor-version\"");
+    assert!(json(legacy.body())["sentiment"].is_string());
+}
+
+#[tokio::test]
+async fn docs_are_self_contained_and_describe_the_openapi_document() {
+    let app = app(ServerConfig::default());
+
+    let response = warp::test::request().path("/openapi.json").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let spec = json(response.body());
+    assert!(spec["paths"]["/v1/analyze"]["post"].is_object());
+
+    let response = warp::test::request().path("/docs").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-security-policy"].to_str().unwrap().contains("connect-src 'self'"));
+    let page = String::from_utf8_lossy(response.body());
+    assert!(page.contains("fetch(\"openapi.json\")"));
+    assert!(!page.contains("<script src="));
+}
+
+#[tokio::test]
+async fn batches_over_the_size_limit_are_rejected() {
+    let app = app(ServerConfig { max_batch_size: 2, ..ServerConfig::default() });
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze/batch")
+        .json(&serde_json::json!({ "texts": ["一", "二", "三"] }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = json(response.body());
+    assert_eq!(body["code"], "batch_too_large");
+    assert_eq!(body["field"], "texts");
+}
+
+#[tokio::test]
+async fn ndjson_batches_report_bad_lines_without_failing_the_batch() {
+    let app = app(ServerConfig::default());
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze/batch")
+        .header("content-type", "application/x-ndjson")
+        .body("{\"text\": \"自由とは何か\"}\nnot json\n\n{\"text\": \"幸福とは何か\"}\n")
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let lines: Vec<Value> = response.body()
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(json)
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["index"], 0);
+    assert!(lines[0]["result"].is_object());
+    assert_eq!(lines[1]["error"]["code"], "invalid_body");
+    assert_eq!(lines[2]["index"], 2);
+    assert!(lines[2]["result"].is_object());
+}
+
+struct FailingStore;
+
+impl AnalysisStore for FailingStore {
+    fn save(&self, _: &PhilosophyAnalysisV2, _: Option<&str>) -> Result<(), StoreError> {
+        Err(StoreError::Backend("disk full".to_string()))
+    }
+    fn get(&self, _: &str) -> Result<Option<(PhilosophyAnalysisV2, Option<String>)>, StoreError> {
+        Ok(None)
+    }
+    fn list(&self, _: &AnalysisQuery) -> Result<(Vec<PhilosophyAnalysisV2>, usize), StoreError> {
+        Ok((Vec::new(), 0))
+    }
+    fn delete(&self, _: &str) -> Result<bool, StoreError> {
+        Ok(false)
+    }
+    fn delete_by_user(&self, _: &str) -> Result<usize, StoreError> {
+        Ok(0)
+    }
+}
+
+#[tokio::test]
+async fn analyses_that_cannot_be_stored_are_reported_as_unavailable() {
+    let app = server::app(AIEngine::new().with_store(Arc::new(FailingStore)), ServerConfig::default());
+
+    for path in ["/v1/analyze", "/v2/analyze"] {
+        let response = warp::test::request()
+            .method("POST")
+            .path(path)
+            .json(&serde_json::json!({ "text": "自由とは何か" }))
+            .reply(&app)
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", path);
+        assert_eq!(json(response.body())["code"], "storage_unavailable");
+    }
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze/batch")
+        .json(&serde_json::json!({ "texts": ["自由とは何か"] }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let line = json(response.body().split(|&b| b == b'\n').next().unwrap());
+    assert_eq!(line["error"]["code"], "storage_unavailable");
+}
+
+#[tokio::test]
+async fn missing_or_invalid_credentials_are_unauthorized() {
+    let app = app(auth_config(&["service"]));
+
+    let response = warp::test::request().path("/v1/wisdom").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    assert_eq!(json(response.body())["code"], "unauthorized");
+
+    let response = warp::test::request()
+        .path("/v1/wisdom")
+        .header("x-api-key", "wrong-key-0123456789")
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer error=\"invalid_token\"");
+
+    let response = warp::test::request().path("/v1/wisdom").header("x-api-key", API_KEY).reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request().path("/v1/wisdom").header("authorization", bearer("alice", &[])).reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 公開ルートと /health は認証が要らない
+    let response = warp::test::request().path("/v1/personalities").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request().path("/health").reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_routes_are_forbidden_without_the_admin_role() {
+    let app = app(auth_config(&[]));
+
+    let response = warp::test::request()
+        .method("DELETE")
+        .path("/v1/characters/snowman")
+        .header("x-api-key", API_KEY)
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(json(response.body())["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn cors_preflight_allows_every_method_the_api_serves() {
+    let app = app(ServerConfig::default());
+
+    for method in ["GET", "POST", "PUT", "DELETE"] {
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/v1/characters/owl")
+            .header("origin", server::DEFAULT_ALLOWED_ORIGIN)
+            .header("access-control-request-method", method)
+            .reply(&app)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", method);
+        let allowed = response.headers()["access-control-allow-methods"].to_str().unwrap();
+        assert!(allowed.contains(method), "{} should be allowed: {}", method, allowed);
+    }
+}
+
+#[tokio::test]
+async fn users_can_only_delete_their_own_analyses() {
+    let mut config = auth_config(&[]);
+    config.auth.routes = BTreeMap::from([("delete_analyses".to_string(), Access::Authenticated)]);
+    let app = app(config);
+    let (alice, bob, admin) = (bearer("alice", &[]), bearer("bob", &[]), bearer("root", &["admin"]));
+
+    let mut ids = Vec::new();
+    for text in ["自由とは何か", "幸福とは何か"] {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/v2/analyze")
+            .header("authorization", &alice)
+            .json(&serde_json::json!({ "text": text }))
+            .reply(&app)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        ids.push(json(response.body())["id"].as_str().unwrap().to_string());
+    }
+    let delete = |path: String, token: &String| {
+        warp::test::request().method("DELETE").path(&path).header("authorization", token).reply(&app)
+    };
+
+    // 他人の分析は無いものとして扱う
+    assert_eq!(delete(format!("/v1/analysis/{}", ids[0]), &bob).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(delete(format!("/v1/analysis/{}", ids[0]), &alice).await.status(), StatusCode::NO_CONTENT);
+
+    let response = delete("/v1/analyses?user_id=alice".to_string(), &bob).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(json(response.body())["code"], "forbidden");
+
+    let response = delete("/v1/analyses?user_id=alice".to_string(), &admin).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response.body())["deleted"], 1);
+}
+
+#[tokio::test]
+async fn clients_over_the_limit_get_429_with_retry_after() {
+    let config = ServerConfig {
+        rate_limit: RateLimitSettings {
+            per_ip: BucketSettings { per_second: 0.1, burst: 2 },
+            ..RateLimitSettings::default()
+        },
+        ..ServerConfig::default()
+    };
+    let app = app(config);
+    let remote: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+    let get = |path: &'static str| warp::test::request().path(path).remote_addr(remote).reply(&app);
+
+    // どのルートにも当たらないリクエストは数えない
+    for _ in 0..5 {
+        assert_eq!(get("/v1/nowhere").await.status(), StatusCode::NOT_FOUND);
+    }
+    assert_eq!(get("/v1/wisdom").await.status(), StatusCode::OK);
+    assert_eq!(get("/v1/wisdom").await.status(), StatusCode::OK);
+
+    let response = get("/v1/wisdom").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after >= 1);
+    assert_eq!(json(response.body())["code"], "rate_limited");
+
+    // 別のクライアントは別に数える
+    let other: SocketAddr = "192.0.2.2:40000".parse().unwrap();
+    let response = warp::test::request().path("/v1/wisdom").remote_addr(other).reply(&app).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn bodies_over_the_limit_are_rejected_with_413() {
+    let config = ServerConfig {
+        max_body_bytes: BTreeMap::from([("analyze".to_string(), 64), ("analyze_batch".to_string(), 256)]),
+        ..ServerConfig::default()
+    };
+    let app = app(config);
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze")
+        .json(&serde_json::json!({ "text": "自由".repeat(40) }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(json(response.body())["code"], "payload_too_large");
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze/batch")
+        .json(&serde_json::json!({ "texts": ["自由".repeat(100)] }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(json(response.body())["message"], "request body must be at most 256 bytes");
+
+    // 上限より小さければ通る
+    let response = warp::test::request()
+        .method("POST")
+        .path("/v1/analyze")
+        .json(&serde_json::json!({ "text": "自由とは何か" }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,431 @@
+// 設定の読み込み
+// 優先順位は デフォルト < TOMLファイル < 環境変数 < CLIフラグ
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use crate::auth::Access;
+use crate::rate_limit;
+
+pub use service_common::settings::{AuthSettings, LoggingSettings, RateLimitSettings};
+
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+const LOG_FORMATS: [&str; 2] = ["text", "json"];
+// auth.routes で権限を変えられるルート
+pub const AUTH_ROUTES: [&str; 3] = ["metrics", "optimize", "status"];
+// HS256 の鍵として短すぎない長さ
+const MIN_JWT_SECRET_BYTES: usize = 32;
+const MIN_API_KEY_LENGTH: usize = 16;
+// 既定で許可するオリジン（ローカルのフロントエンド）。全て許可するには "*" を指定する
+const DEFAULT_ALLOWED_ORIGIN: &str = "http://localhost:3000";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerSettings,
+    pub logging: LoggingSettings,
+    pub limits: LimitSettings,
+    pub snowman: SnowmanSettings,
+    pub rate_limit: RateLimitSettings,
+    pub auth: AuthSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: String,
+    pub port: u16,
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    pub max_task_length: usize,
+    // /optimize のリクエストボディの上限（バイト）。省略時は max_task_length から決める
+    pub max_body_bytes: Option<u64>,
+}
+
+// 雪だるまチャンの初期ステータス
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnowmanSettings {
+    pub name: String,
+    pub perfection_level: u8,
+    pub warmth_factor: u8,
+    pub efficiency_score: u8,
+    pub mood: String,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 5002,
+            allowed_origins: vec![DEFAULT_ALLOWED_ORIGIN.to_string()],
+        }
+    }
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        Self {
+            max_task_length: 2000,
+            max_body_bytes: None,
+        }
+    }
+}
+
+impl Default for SnowmanSettings {
+    fn default() -> Self {
+        Self {
+            name: "雪だるまチャン".to_string(),
+            perfection_level: 8,
+            warmth_factor: 9,
+            efficiency_score: 7,
+            mood: "optimistic".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "rust-perfection", version, about = "Rust Perfection Service (雪だるまチャン)")]
+pub struct Cli {
+    /// TOML config file
+    #[arg(long, env = "RUST_PERFECTION_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "RUST_PERFECTION_BIND")]
+    pub bind: Option<String>,
+    #[arg(long, env = "PORT")]
+    pub port: Option<u16>,
+    /// Allowed CORS origin (repeatable; comma separated in the environment)
+    #[arg(long = "allowed-origin", env = "RUST_PERFECTION_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub allowed_origins: Vec<String>,
+    #[arg(long, env = "RUST_PERFECTION_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Log format (text / json)
+    #[arg(long, env = "RUST_PERFECTION_LOG_FORMAT")]
+    pub log_format: Option<String>,
+    #[arg(long, env = "RUST_PERFECTION_MAX_TASK_LENGTH")]
+    pub max_task_length: Option<usize>,
+    #[arg(long, env = "RUST_PERFECTION_MAX_BODY_BYTES")]
+    pub max_body_bytes: Option<u64>,
+    /// Requests per second allowed per client IP
+    #[arg(long, env = "RUST_PERFECTION_RATE_LIMIT_PER_SECOND")]
+    pub rate_limit_per_second: Option<f64>,
+    /// Requests a client IP may send in a burst
+    #[arg(long, env = "RUST_PERFECTION_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+    /// Trusted proxy IP or CIDR (repeatable; comma separated in the environment)
+    #[arg(long = "trusted-proxy", env = "RUST_PERFECTION_TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Vec<String>,
+    /// Require an API key or JWT (true / false)
+    #[arg(long, env = "RUST_PERFECTION_AUTH_ENABLED")]
+    pub auth_enabled: Option<bool>,
+    /// Service API key (repeatable; comma separated in the environment)
+    #[arg(long = "api-key", env = "RUST_PERFECTION_API_KEYS", value_delimiter = ',', hide_env_values = true)]
+    pub api_keys: Vec<String>,
+    /// Shared secret for verifying JWTs
+    #[arg(long, env = "RUST_PERFECTION_JWT_SECRET", hide_env_values = true)]
+    pub jwt_secret: Option<String>,
+    #[arg(long, env = "RUST_PERFECTION_SNOWMAN_NAME")]
+    pub snowman_name: Option<String>,
+    #[arg(long, env = "RUST_PERFECTION_PERFECTION_LEVEL")]
+    pub perfection_level: Option<u8>,
+    #[arg(long, env = "RUST_PERFECTION_WARMTH_FACTOR")]
+    pub warmth_factor: Option<u8>,
+    #[arg(long, env = "RUST_PERFECTION_EFFICIENCY_SCORE")]
+    pub efficiency_score: Option<u8>,
+    #[arg(long, env = "RUST_PERFECTION_MOOD")]
+    pub mood: Option<String>,
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: toml::de::Error },
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "failed to read config file {}: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, source } => {
+                write!(f, "failed to parse config file {}: {}", path.display(), source)
+            }
+            ConfigError::Invalid { key, message } => write!(f, "invalid `{}`: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&content).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(bind) = &cli.bind {
+            self.server.bind = bind.clone();
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if !cli.allowed_origins.is_empty() {
+            self.server.allowed_origins = cli.allowed_origins.clone();
+        }
+        if let Some(level) = &cli.log_level {
+            self.logging.level = level.to_lowercase();
+        }
+        if let Some(format) = &cli.log_format {
+            self.logging.format = format.to_lowercase();
+        }
+        if let Some(max_task_length) = cli.max_task_length {
+            self.limits.max_task_length = max_task_length;
+        }
+        if let Some(max_body_bytes) = cli.max_body_bytes {
+            self.limits.max_body_bytes = Some(max_body_bytes);
+        }
+        if let Some(per_second) = cli.rate_limit_per_second {
+            self.rate_limit.per_ip.per_second = per_second;
+        }
+        if let Some(burst) = cli.rate_limit_burst {
+            self.rate_limit.per_ip.burst = burst;
+        }
+        if !cli.trusted_proxies.is_empty() {
+            self.rate_limit.trusted_proxies = cli.trusted_proxies.clone();
+        }
+        if let Some(enabled) = cli.auth_enabled {
+            self.auth.enabled = enabled;
+        }
+        if !cli.api_keys.is_empty() {
+            self.auth.api_keys = cli.api_keys.clone();
+        }
+        if let Some(secret) = &cli.jwt_secret {
+            self.auth.jwt_secret = Some(secret.clone());
+        }
+        if let Some(name) = &cli.snowman_name {
+            self.snowman.name = name.clone();
+        }
+        if let Some(level) = cli.perfection_level {
+            self.snowman.perfection_level = level;
+        }
+        if let Some(warmth) = cli.warmth_factor {
+            self.snowman.warmth_factor = warmth;
+        }
+        if let Some(score) = cli.efficiency_score {
+            self.snowman.efficiency_score = score;
+        }
+        if let Some(mood) = &cli.mood {
+            self.snowman.mood = mood.clone();
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        fn invalid(key: &'static str, message: impl Into<String>) -> Result<(), ConfigError> {
+            Err(ConfigError::Invalid { key, message: message.into() })
+        }
+
+        if self.server.bind.parse::<IpAddr>().is_err() {
+            return invalid("server.bind", format!("`{}` is not an IP address (e.g. 0.0.0.0)", self.server.bind));
+        }
+        if self.server.port == 0 {
+            return invalid("server.port", "must be between 1 and 65535");
+        }
+        if self.server.allowed_origins.is_empty() {
+            return invalid("server.allowed_origins", "must not be empty (use [\"*\"] to allow any origin)");
+        }
+        for origin in &self.server.allowed_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                return invalid("server.allowed_origins", format!("`{}` must be \"*\" or start with http:// or https://", origin));
+            }
+        }
+        if !LOG_LEVELS.contains(&self.logging.level.as_str()) {
+            return invalid("logging.level", format!("`{}` is not one of {}", self.logging.level, LOG_LEVELS.join(", ")));
+        }
+        if !LOG_FORMATS.contains(&self.logging.format.as_str()) {
+            return invalid("logging.format", format!("`{}` is not one of {}", self.logging.format, LOG_FORMATS.join(", ")));
+        }
+        if self.limits.max_task_length == 0 {
+            return invalid("limits.max_task_length", "must be greater than 0");
+        }
+        if self.limits.max_body_bytes == Some(0) {
+            return invalid("limits.max_body_bytes", "must be greater than 0");
+        }
+        for (key, bucket) in [("rate_limit.per_ip", self.rate_limit.per_ip), ("rate_limit.per_principal", self.rate_limit.per_principal)] {
+            if !(bucket.per_second.is_finite() && bucket.per_second > 0.0) {
+                return invalid(key, "`per_second` must be greater than 0");
+            }
+            if bucket.burst == 0 {
+                return invalid(key, "`burst` must be greater than 0");
+            }
+        }
+        if let Some(proxy) = self.rate_limit.trusted_proxies.iter().find(|proxy| rate_limit::parse_network(proxy).is_none()) {
+            return invalid("rate_limit.trusted_proxies", format!("`{}` is not an IP address or CIDR (e.g. 10.0.0.0/8)", proxy));
+        }
+        if self.auth.enabled && self.auth.api_keys.is_empty() && self.auth.jwt_secret.is_none() {
+            return invalid("auth", "set `api_keys` or `jwt_secret` when `enabled` is true");
+        }
+        if self.auth.api_keys.iter().any(|key| key.len() < MIN_API_KEY_LENGTH) {
+            return invalid("auth.api_keys", format!("keys must be at least {} characters", MIN_API_KEY_LENGTH));
+        }
+        if self.auth.jwt_secret.as_ref().is_some_and(|secret| secret.len() < MIN_JWT_SECRET_BYTES) {
+            return invalid("auth.jwt_secret", format!("must be at least {} bytes", MIN_JWT_SECRET_BYTES));
+        }
+        if self.auth.admin_role.trim().is_empty() {
+            return invalid("auth.admin_role", "must not be empty");
+        }
+        if let Some(route) = self.auth.routes.keys().find(|route| !AUTH_ROUTES.contains(&route.as_str())) {
+            return invalid("auth.routes", format!("unknown route `{}` (allowed: {})", route, AUTH_ROUTES.join(", ")));
+        }
+        if self.snowman.name.trim().is_empty() {
+            return invalid("snowman.name", "must not be empty");
+        }
+        for (key, value) in [
+            ("snowman.perfection_level", self.snowman.perfection_level),
+            ("snowman.warmth_factor", self.snowman.warmth_factor),
+            ("snowman.efficiency_score", self.snowman.efficiency_score),
+        ] {
+            if value > 10 {
+                return invalid(key, format!("{} is out of range (0-10)", value));
+            }
+        }
+        Ok(())
+    }
+
+    // /optimize のボディの上限（UTF-8 で1文字最大4バイト + JSONの余白）
+    pub fn max_body_bytes(&self) -> u64 {
+        self.limits.max_body_bytes.unwrap_or((self.limits.max_task_length * 4 + 1024) as u64)
+    }
+
+    // ルートを呼ぶのに必要な権限。指定が無ければ /metrics も含めて認証が必要
+    pub fn access(&self, route: &str) -> Access {
+        self.auth.routes.get(route).copied().unwrap_or(Access::Authenticated)
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip = self.server.bind.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
+        SocketAddr::new(ip, self.server.port)
+    }
+
+    // --print-config 用。APIキーと秘密鍵は伏せる
+    pub fn to_toml(&self) -> String {
+        let mut config = self.clone();
+        config.auth.api_keys.iter_mut().for_each(|key| *key = "<redacted>".to_string());
+        if let Some(secret) = &mut config.auth.jwt_secret {
+            *secret = "<redacted>".to_string();
+        }
+        toml::to_string_pretty(&config).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_key(config: &Config) -> Option<&'static str> {
+        match config.validate() {
+            Err(ConfigError::Invalid { key, .. }) => Some(key),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.socket_addr(), "0.0.0.0:5002".parse().unwrap());
+        assert_eq!(config.server.allowed_origins, vec![DEFAULT_ALLOWED_ORIGIN.to_string()]);
+        assert_eq!(config.max_body_bytes(), 2000 * 4 + 1024);
+        assert_eq!(config.access("metrics"), Access::Authenticated);
+    }
+
+    #[test]
+    fn cli_flags_override_the_file() {
+        let path = std::env::temp_dir().join(format!("rust-perfection-{}.toml", std::process::id()));
+        std::fs::write(&path, "[server]\nport = 6000\n\n[limits]\nmax_task_length = 100\n\n[snowman]\nmood = \"calm\"\n").unwrap();
+        let cli = Cli::parse_from(["rust-perfection", "--config", path.to_str().unwrap(), "--port", "7000", "--log-level", "DEBUG"]);
+        let config = Config::load(&cli);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.server.port, 7000);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.limits.max_task_length, 100);
+        assert_eq!(config.max_body_bytes(), 100 * 4 + 1024);
+        assert_eq!(config.snowman.mood, "calm");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("[server]\nhost = \"0.0.0.0\"\n").is_err());
+        assert!(toml::from_str::<Config>("[snowman]\nperfection_level = 9\n").is_ok());
+    }
+
+    #[test]
+    fn invalid_values_name_their_key() {
+        type Change = fn(&mut Config);
+        let cases: [(&str, Change); 8] = [
+            ("server.bind", |c| c.server.bind = "localhost".to_string()),
+            ("server.port", |c| c.server.port = 0),
+            ("server.allowed_origins", |c| c.server.allowed_origins = vec!["localhost:3000".to_string()]),
+            ("logging.format", |c| c.logging.format = "xml".to_string()),
+            ("rate_limit.per_ip", |c| c.rate_limit.per_ip.per_second = 0.0),
+            ("rate_limit.trusted_proxies", |c| c.rate_limit.trusted_proxies = vec!["proxy".to_string()]),
+            ("auth", |c| c.auth.enabled = true),
+            ("snowman.warmth_factor", |c| c.snowman.warmth_factor = 11),
+        ];
+        for (key, change) in cases {
+            let mut config = Config::default();
+            change(&mut config);
+            assert_eq!(invalid_key(&config), Some(key));
+        }
+
+        let mut config = Config::default();
+        config.auth.api_keys = vec!["short".to_string()];
+        assert_eq!(invalid_key(&config), Some("auth.api_keys"));
+        config.auth.api_keys = vec!["a".repeat(MIN_API_KEY_LENGTH)];
+        config.auth.routes.insert("optimise".to_string(), Access::Public);
+        assert_eq!(invalid_key(&config), Some("auth.routes"));
+    }
+
+    #[test]
+    fn print_config_hides_secrets() {
+        let mut config = Config::default();
+        config.auth.api_keys = vec!["service-key-0123456789".to_string()];
+        config.auth.jwt_secret = Some("s".repeat(MIN_JWT_SECRET_BYTES));
+
+        let printed = config.to_toml();
+        assert!(!printed.contains("service-key-0123456789"));
+        assert!(!printed.contains(&"s".repeat(MIN_JWT_SECRET_BYTES)));
+        assert!(printed.contains("<redacted>"));
+        // 伏せても読み直せる
+        assert!(toml::from_str::<Config>(&printed).is_ok());
+    }
+}
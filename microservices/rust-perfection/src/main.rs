@@ -1,13 +1,21 @@
 // Rust Perfection Microservice
 // AIキャラクター「雪だるまチャン」の完璧主義・効率重視エンジン
 
+mod config;
+mod metrics;
+
+use clap::Parser;
+use service_common::{auth, docs, rate_limit, telemetry};
+use auth::{AuthError, Authenticator};
+use config::{Cli, Config, SnowmanSettings};
+use rate_limit::{Gate, RateLimited, RateLimiter};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use utoipa::{OpenApi, ToSchema};
 use warp::Filter;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct SnowmanCharacter {
     name: String,
     perfection_level: u8,
@@ -16,13 +24,13 @@ struct SnowmanCharacter {
     current_mood: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct OptimizationRequest {
     task: String,
     current_efficiency: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct OptimizationResponse {
     original_task: String,
     optimized_solution: String,
@@ -31,7 +39,7 @@ struct OptimizationResponse {
     snowman_comment: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct HealthResponse {
     status: String,
     service: String,
@@ -40,16 +48,31 @@ struct HealthResponse {
     rust_advantages: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+impl ErrorResponse {
+    fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
 type SharedSnowman = Arc<Mutex<SnowmanCharacter>>;
 
 impl SnowmanCharacter {
-    fn new() -> Self {
+    fn new(settings: &SnowmanSettings) -> Self {
         Self {
-            name: "雪だるまチャン".to_string(),
-            perfection_level: 8,
-            warmth_factor: 9,
-            efficiency_score: 7,
-            current_mood: "optimistic".to_string(),
+            name: settings.name.clone(),
+            perfection_level: settings.perfection_level,
+            warmth_factor: settings.warmth_factor,
+            efficiency_score: settings.efficiency_score,
+            current_mood: settings.mood.clone(),
         }
     }
 
@@ -121,6 +144,7 @@ impl SnowmanCharacter {
 }
 
 // Health check endpoint
+#[utoipa::path(get, path = "/health", responses((status = 200, body = HealthResponse)))]
 async fn health_check(snowman: SharedSnowman) -> Result<impl warp::Reply, warp::Rejection> {
     let snowman_guard = snowman.lock().await;
     let response = HealthResponse {
@@ -134,61 +158,266 @@ async fn health_check(snowman: SharedSnowman) -> Result<impl warp::Reply, warp::
 }
 
 // Task optimization endpoint
+#[utoipa::path(
+    post,
+    path = "/optimize",
+    request_body = OptimizationRequest,
+    responses(
+        (status = 200, body = OptimizationResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key or token (when `auth.enabled`)", body = ErrorResponse),
+        (status = 413, body = ErrorResponse),
+        (status = 429, description = "Rate limited (see the Retry-After header)", body = ErrorResponse)
+    )
+)]
 async fn optimize_task(
     request: OptimizationRequest,
     snowman: SharedSnowman,
+    max_task_length: usize,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(error) = validate_optimization(&request, max_task_length) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&error),
+            warp::http::StatusCode::BAD_REQUEST,
+        ));
+    }
+
     let mut snowman_guard = snowman.lock().await;
+    let previous_mood = snowman_guard.current_mood.clone();
     let response = snowman_guard.optimize_task(&request.task, request.current_efficiency);
-    Ok(warp::reply::json(&response))
+    metrics::snowman().record_optimization(response.efficiency_improvement, &previous_mood, &snowman_guard.current_mood);
+    tracing::debug!(
+        efficiency_improvement = response.efficiency_improvement,
+        mood = %snowman_guard.current_mood,
+        "task optimized"
+    );
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+fn validate_optimization(request: &OptimizationRequest, max_task_length: usize) -> Option<ErrorResponse> {
+    if request.task.trim().is_empty() {
+        return Some(ErrorResponse::new("missing_field", "`task` is required"));
+    }
+    if request.task.chars().count() > max_task_length {
+        return Some(ErrorResponse::new(
+            "too_long",
+            format!("`task` must be at most {} characters", max_task_length),
+        ));
+    }
+    if request.current_efficiency > 10 {
+        return Some(ErrorResponse::new("out_of_range", "`current_efficiency` must be between 0 and 10"));
+    }
+    None
 }
 
 // Character status endpoint
+#[utoipa::path(get, path = "/status", responses((status = 200, body = SnowmanCharacter)))]
 async fn character_status(snowman: SharedSnowman) -> Result<impl warp::Reply, warp::Rejection> {
     let snowman_guard = snowman.lock().await;
     Ok(warp::reply::json(&*snowman_guard))
 }
 
+// Prometheus metrics endpoint
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Prometheus text exposition format", content_type = "text/plain", body = String))
+)]
+async fn prometheus_metrics(snowman: SharedSnowman) -> Result<impl warp::Reply, warp::Rejection> {
+    let body = metrics::render(&snowman.lock().await.current_mood);
+    Ok(warp::reply::with_header(body, "content-type", metrics::CONTENT_TYPE))
+}
+
+// OpenAPI document generated from the request/response types above
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "rust-perfection", description = "雪だるまチャンの最適化API"),
+    paths(health_check, optimize_task, character_status, prometheus_metrics)
+)]
+struct ApiDoc;
+
+// Auth, rate limit and body size rejections as JSON errors (others keep warp's default response)
+async fn handle_rejection(err: warp::Rejection) -> Result<warp::reply::Response, warp::Rejection> {
+    use warp::http::{header, HeaderValue, StatusCode};
+    use warp::Reply;
+
+    let (status, error) = if let Some(e @ AuthError::Forbidden) = err.find::<AuthError>() {
+        (StatusCode::FORBIDDEN, ErrorResponse::new("forbidden", e.message()))
+    } else if let Some(e) = err.find::<AuthError>() {
+        (StatusCode::UNAUTHORIZED, ErrorResponse::new("unauthorized", e.message()))
+    } else if let Some(e) = err.find::<RateLimited>() {
+        (StatusCode::TOO_MANY_REQUESTS, ErrorResponse::new("rate_limited", format!("too many requests, retry after {} seconds", e.retry_after)))
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, ErrorResponse::new("payload_too_large", "request body is too large"))
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        (StatusCode::LENGTH_REQUIRED, ErrorResponse::new("length_required", "a Content-Length header is required"))
+    } else {
+        return Err(err);
+    };
+
+    let mut response = warp::reply::with_status(warp::reply::json(&error), status).into_response();
+    if let Some(e) = err.find::<RateLimited>() {
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(e.retry_after));
+    }
+    match err.find::<AuthError>() {
+        Some(AuthError::Missing) => {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        Some(AuthError::Invalid(_)) => {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer error=\"invalid_token\""));
+        }
+        _ => {}
+    }
+    Ok(response)
+}
+
 // CORS headers
-fn with_cors() -> warp::filters::cors::Builder {
-    warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["GET", "POST", "PUT", "DELETE"])
+fn with_cors(allowed_origins: &[String]) -> warp::filters::cors::Builder {
+    let cors = warp::cors()
+        .allow_headers(vec!["content-type", "authorization", rate_limit::API_KEY_HEADER, telemetry::REQUEST_ID_HEADER, telemetry::TRACEPARENT_HEADER])
+        .expose_headers(vec!["retry-after", "www-authenticate", telemetry::REQUEST_ID_HEADER, telemetry::TRACEPARENT_HEADER])
+        .allow_methods(vec!["GET", "POST", "PUT", "DELETE"]);
+
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        cors.allow_any_origin()
+    } else {
+        cors.allow_origins(allowed_origins.iter().map(String::as_str))
+    }
+}
+
+// Metrics route label: the matched route, or "unmatched" for every other path
+const ROUTES: [&str; 6] = ["/health", "/metrics", "/openapi.json", "/docs", "/optimize", "/status"];
+
+fn route_label(path: &str) -> String {
+    let route = ROUTES.iter().find(|route| telemetry::matches_template(route, path));
+    route.copied().unwrap_or(telemetry::UNMATCHED_ROUTE).to_string()
 }
 
 #[tokio::main]
 async fn main() {
-    println!("☃️ Rust Perfection Service (雪だるまチャン) starting...");
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ Invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    telemetry::init(&config.logging);
+    tracing::info!("☃️ Rust Perfection Service (雪だるまチャン) starting...");
     
-    let snowman = Arc::new(Mutex::new(SnowmanCharacter::new()));
+    let snowman = Arc::new(Mutex::new(SnowmanCharacter::new(&config.snowman)));
+    let max_task_length = config.limits.max_task_length;
+    let max_body_bytes = config.max_body_bytes();
+    let limiter = Arc::new(RateLimiter::new(&config.rate_limit));
+    let authenticator = Arc::new(Authenticator::new(&config.auth));
+    // /optimize と /status は認証の前後でレート制限をかける
+    let gate = Gate::new(authenticator.clone(), limiter);
+    if !config.auth.enabled {
+        tracing::warn!("⚠️ Authentication is disabled; every route is public (set `auth.enabled`)");
+    }
+    let with_snowman = warp::any().map(move || snowman.clone());
 
     // Health check route
     let health = warp::path("health")
         .and(warp::get())
-        .and(warp::any().map(move || snowman.clone()))
+        .and(with_snowman.clone())
         .and_then(health_check);
 
     // Task optimization route
     let optimize = warp::path("optimize")
         .and(warp::post())
+        .and(gate.guard(config.access("optimize")))
+        .and(warp::body::content_length_limit(max_body_bytes))
         .and(warp::body::json())
-        .and(warp::any().map(move || snowman.clone()))
+        .and(with_snowman.clone())
+        .and(warp::any().map(move || max_task_length))
         .and_then(optimize_task);
 
     // Character status route
     let status = warp::path("status")
         .and(warp::get())
-        .and(warp::any().map(move || snowman.clone()))
+        .and(gate.guard(config.access("status")))
+        .and(with_snowman.clone())
         .and_then(character_status);
 
+    // Prometheus metrics route
+    let metrics = warp::path("metrics")
+        .and(warp::get())
+        .and(auth::guard(authenticator, config.access("metrics")))
+        .and(with_snowman.clone())
+        .and_then(prometheus_metrics);
+
+    // OpenAPI document and docs page
+    let openapi_spec = Arc::new(ApiDoc::openapi());
+    let openapi = warp::path("openapi.json")
+        .and(warp::get())
+        .map(move || warp::reply::json(openapi_spec.as_ref()));
+    let docs = docs::route();
+
     let routes = health
+        .or(metrics)
+        .or(openapi)
+        .or(docs)
         .or(optimize)
         .or(status)
-        .with(with_cors());
+        .recover(handle_rejection)
+        .with(with_cors(&config.server.allowed_origins));
+
+    let addr = config.socket_addr();
+    tracing::info!("☃️ Server running on http://{}", addr);
+    if let Err(e) = telemetry::serve(routes, addr, route_label).await {
+        tracing::error!(%addr, error = %e, "❌ Server error");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(task: &str, current_efficiency: u8) -> OptimizationRequest {
+        OptimizationRequest { task: task.to_string(), current_efficiency }
+    }
 
-    println!("☃️ Server running on http://0.0.0.0:5002");
-    warp::serve(routes)
-        .run(([0, 0, 0, 0], 5002))
-        .await;
+    #[test]
+    fn optimization_requests_are_validated() {
+        assert!(validate_optimization(&request("速度を上げたい", 5), 10).is_none());
+        let code = |request: OptimizationRequest| validate_optimization(&request, 10).map(|error| error.code);
+        assert_eq!(code(request("   ", 5)).as_deref(), Some("missing_field"));
+        assert_eq!(code(request(&"あ".repeat(11), 5)).as_deref(), Some("too_long"));
+        assert_eq!(code(request("安全", 11)).as_deref(), Some("out_of_range"));
+    }
+
+    #[test]
+    fn snowman_starts_from_the_configured_settings() {
+        let settings = SnowmanSettings { name: "ゆきだるま".to_string(), mood: "calm".to_string(), ..SnowmanSettings::default() };
+        let mut snowman = SnowmanCharacter::new(&settings);
+        assert_eq!(snowman.name, "ゆきだるま");
+        assert_eq!(snowman.current_mood, "calm");
+
+        let response = snowman.optimize_task("Concurrent jobs", 2);
+        assert_eq!(response.efficiency_improvement, 16);
+        assert!(response.optimized_solution.contains("Tokio"));
+        assert_eq!(snowman.current_mood, "delighted");
+        assert_eq!(snowman.optimize_task("速度", 9).efficiency_improvement, 1);
+        assert_eq!(snowman.current_mood, "contemplative");
+    }
+
+    #[test]
+    fn metrics_label_known_routes_and_group_the_rest() {
+        assert_eq!(route_label("/optimize"), "/optimize");
+        assert_eq!(route_label("/metrics"), "/metrics");
+        assert_eq!(route_label("/optimize/extra"), telemetry::UNMATCHED_ROUTE);
+        assert_eq!(route_label("/wp-admin"), telemetry::UNMATCHED_ROUTE);
+    }
 }
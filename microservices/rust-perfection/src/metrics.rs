@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use service_common::metrics::{escape, header, http};
+
+pub use service_common::metrics::CONTENT_TYPE;
+
+/// 雪だるまチャンの最適化の記録
+#[derive(Default)]
+pub struct SnowmanMetrics {
+    inner: Mutex<SnowmanCounts>,
+}
+
+#[derive(Default)]
+struct SnowmanCounts {
+    optimizations: u64,
+    efficiency_improvement: u64,
+    // (前の気分, 新しい気分) ごとの回数。気分が変わったときだけ数える
+    mood_transitions: BTreeMap<(String, String), u64>,
+}
+
+impl SnowmanMetrics {
+    pub fn record_optimization(&self, efficiency_improvement: u8, from_mood: &str, to_mood: &str) {
+        let mut counts = self.inner.lock().unwrap();
+        counts.optimizations += 1;
+        counts.efficiency_improvement += efficiency_improvement as u64;
+        if from_mood != to_mood {
+            *counts.mood_transitions.entry((from_mood.to_string(), to_mood.to_string())).or_insert(0) += 1;
+        }
+    }
+
+    fn render(&self, out: &mut String, current_mood: &str) {
+        let counts = self.inner.lock().unwrap();
+        header(out, "rust_perfection_optimizations_total", "counter", "Tasks optimized.");
+        let _ = writeln!(out, "rust_perfection_optimizations_total {}", counts.optimizations);
+        header(out, "rust_perfection_efficiency_improvement", "summary", "efficiency_improvement of optimized tasks (average = sum / count).");
+        let _ = writeln!(out, "rust_perfection_efficiency_improvement_sum {}", counts.efficiency_improvement);
+        let _ = writeln!(out, "rust_perfection_efficiency_improvement_count {}", counts.optimizations);
+        header(out, "rust_perfection_efficiency_improvement_average", "gauge", "Average efficiency_improvement since start.");
+        let average = if counts.optimizations == 0 { 0.0 } else { counts.efficiency_improvement as f64 / counts.optimizations as f64 };
+        let _ = writeln!(out, "rust_perfection_efficiency_improvement_average {}", average);
+        header(out, "rust_perfection_mood_transitions_total", "counter", "Snowman mood changes by previous and new mood.");
+        for ((from, to), count) in counts.mood_transitions.iter() {
+            let _ = writeln!(out, "rust_perfection_mood_transitions_total{{from=\"{}\",to=\"{}\"}} {}", escape(from), escape(to), count);
+        }
+        header(out, "rust_perfection_mood", "gauge", "Current snowman mood (1 for the current mood).");
+        let _ = writeln!(out, "rust_perfection_mood{{mood=\"{}\"}} 1", escape(current_mood));
+    }
+}
+
+static SNOWMAN_METRICS: OnceLock<SnowmanMetrics> = OnceLock::new();
+
+pub fn snowman() -> &'static SnowmanMetrics {
+    SNOWMAN_METRICS.get_or_init(SnowmanMetrics::default)
+}
+
+/// `/metrics` の本文（Prometheus のテキスト形式）。
+///
+/// メトリクス名は `rust_perfection_` で始まり、アラートから参照されるので変えないこと。
+pub fn render(current_mood: &str) -> String {
+    let mut out = String::new();
+    header(&mut out, "rust_perfection_build_info", "gauge", "Build information.");
+    let _ = writeln!(out, "rust_perfection_build_info{{version=\"{}\"}} 1", env!("CARGO_PKG_VERSION"));
+    http().render(&mut out, "rust_perfection");
+    snowman().render(&mut out, current_mood);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimizations_and_mood_changes_are_counted() {
+        let metrics = SnowmanMetrics::default();
+        metrics.record_optimization(16, "optimistic", "delighted");
+        metrics.record_optimization(4, "delighted", "delighted");
+        metrics.record_optimization(1, "delighted", "contemplative");
+
+        let mut out = String::new();
+        metrics.render(&mut out, "contemplative");
+        assert!(out.contains("rust_perfection_optimizations_total 3\n"));
+        assert!(out.contains("rust_perfection_efficiency_improvement_sum 21\n"));
+        assert!(out.contains("rust_perfection_efficiency_improvement_average 7\n"));
+        // 気分が変わらなかった回は数えない
+        assert!(out.contains("rust_perfection_mood_transitions_total{from=\"optimistic\",to=\"delighted\"} 1\n"));
+        assert!(out.contains("rust_perfection_mood_transitions_total{from=\"delighted\",to=\"contemplative\"} 1\n"));
+        assert!(!out.contains("from=\"delighted\",to=\"delighted\""));
+        assert!(out.contains("rust_perfection_mood{mood=\"contemplative\"} 1\n"));
+    }
+
+    #[test]
+    fn the_average_is_zero_before_any_optimization() {
+        let mut out = String::new();
+        SnowmanMetrics::default().render(&mut out, "optimistic\"");
+        assert!(out.contains("rust_perfection_efficiency_improvement_average 0\n"));
+        assert!(out.contains("rust_perfection_mood{mood=\"optimistic\\\"\"} 1\n"));
+    }
+}
@@ -0,0 +1,313 @@
+use std::sync::Arc;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use warp::http::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+use crate::settings::AuthSettings;
+use crate::rate_limit::API_KEY_HEADER;
+
+/// 全ユーザーの保存済みデータを扱えるロール（管理者ロールも同じく扱える）
+pub const SERVICE_ROLE: &str = "service";
+
+/// ルートを呼ぶのに必要な権限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// 認証なしで呼べる
+    Public,
+    /// APIキーか有効なJWTが必要
+    Authenticated,
+    /// 管理者ロールを持つ相手だけ
+    Admin,
+}
+
+/// 認証に失敗したときのリジェクション（401 か 403 になる）
+#[derive(Debug)]
+pub enum AuthError {
+    /// 認証情報が無い
+    Missing,
+    /// APIキーかトークンが正しくない（理由）
+    Invalid(&'static str),
+    /// 認証できたが権限が足りない
+    Forbidden,
+}
+
+impl warp::reject::Reject for AuthError {}
+
+impl AuthError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            AuthError::Missing => "an X-Api-Key header or a Bearer token is required",
+            AuthError::Invalid(reason) => reason,
+            AuthError::Forbidden => "this route requires the admin role",
+        }
+    }
+}
+
+/// 認証した相手
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// 相手ごとに数えるための識別子（`user:{sub}` か `api-key:{番号}`）
+    pub id: String,
+    /// JWT の `sub`（APIキーのときは無い）
+    pub user_id: Option<String>,
+    pub roles: Vec<String>,
+    /// サービスか管理者のロールを持つ（全ユーザーのデータを扱える）
+    pub privileged: bool,
+}
+
+impl Principal {
+    /// 自分のデータだけに絞るべき相手ならそのユーザーID。
+    ///
+    /// `sub` の無い相手（サービスでも管理者でもないAPIキー）は空文字になり、
+    /// 誰の分析も読めない。
+    pub fn owner(&self) -> Option<&str> {
+        (!self.privileged).then(|| self.user_id.as_deref().unwrap_or(""))
+    }
+}
+
+// JWT のクレーム（`exp` は `Validation` が確かめる）
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+/// サービス用の固定のAPIキーか、共有の秘密鍵で署名したJWT（HS256/384/512）で認証する。
+///
+/// APIキーは `X-Api-Key` か `Authorization: Bearer` で受け取り、`api_key_roles` を持つ
+/// 相手として扱う。JWT は `sub` をユーザーID、`roles` をロールとして読む。
+pub struct Authenticator {
+    enabled: bool,
+    api_keys: Vec<String>,
+    api_key_roles: Vec<String>,
+    jwt: Option<(DecodingKey, Validation)>,
+    admin_role: String,
+}
+
+impl Authenticator {
+    pub fn new(settings: &AuthSettings) -> Self {
+        let jwt = settings.jwt_secret.as_deref().filter(|secret| !secret.is_empty()).map(|secret| {
+            let mut validation = Validation::new(Algorithm::HS256);
+            validation.algorithms = vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512];
+            validation.set_required_spec_claims(&["exp", "sub"]);
+            if let Some(issuer) = &settings.jwt_issuer {
+                validation.set_issuer(&[issuer]);
+            }
+            match &settings.jwt_audience {
+                Some(audience) => validation.set_audience(&[audience]),
+                None => validation.validate_aud = false,
+            }
+            (DecodingKey::from_secret(secret.as_bytes()), validation)
+        });
+
+        Authenticator {
+            enabled: settings.enabled,
+            api_keys: settings.api_keys.clone(),
+            api_key_roles: settings.api_key_roles.clone(),
+            jwt,
+            admin_role: settings.admin_role.clone(),
+        }
+    }
+
+    /// `access` のルートを呼べるか確かめる（認証が無効なら常に通す）
+    pub fn authorize(&self, access: Access, authorization: Option<&str>, api_key: Option<&str>) -> Result<Option<Principal>, AuthError> {
+        if !self.enabled || access == Access::Public {
+            return Ok(None);
+        }
+        let principal = self.authenticate(authorization, api_key)?;
+        if access == Access::Admin && !principal.roles.iter().any(|role| role == &self.admin_role) {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(Some(principal))
+    }
+
+    pub fn authenticate(&self, authorization: Option<&str>, api_key: Option<&str>) -> Result<Principal, AuthError> {
+        if let Some(api_key) = api_key {
+            return self.api_key(api_key).ok_or(AuthError::Invalid("invalid API key"));
+        }
+        let authorization = authorization.ok_or(AuthError::Missing)?;
+        let token = match authorization.trim().split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+            _ => return Err(AuthError::Invalid("the Authorization header must use the Bearer scheme")),
+        };
+        if let Some(principal) = self.api_key(token) {
+            return Ok(principal);
+        }
+        self.decode(token)
+    }
+
+    fn api_key(&self, candidate: &str) -> Option<Principal> {
+        // 一致するかどうかで時間が変わらないように全てのキーと比べる
+        let matched = self.api_keys.iter().enumerate().fold(None, |matched, (index, key)| {
+            if constant_time_eq(key.as_bytes(), candidate.as_bytes()) { Some(index) } else { matched }
+        });
+        matched.map(|index| self.principal(format!("api-key:{}", index), None, self.api_key_roles.clone()))
+    }
+
+    fn decode(&self, token: &str) -> Result<Principal, AuthError> {
+        let (key, validation) = self.jwt.as_ref().ok_or(AuthError::Invalid("invalid API key"))?;
+        let claims = jsonwebtoken::decode::<Claims>(token, key, validation).map_err(|e| {
+            AuthError::Invalid(match e.kind() {
+                ErrorKind::ExpiredSignature => "the token has expired",
+                ErrorKind::ImmatureSignature => "the token is not valid yet",
+                ErrorKind::InvalidSignature => "the token signature is invalid",
+                ErrorKind::InvalidAlgorithm => "the token must be signed with HS256, HS384 or HS512",
+                ErrorKind::InvalidIssuer => "the token has the wrong issuer",
+                ErrorKind::InvalidAudience => "the token has the wrong audience",
+                ErrorKind::MissingRequiredClaim(_) => "the token must carry `sub` and `exp`",
+                _ => "invalid token",
+            })
+        })?.claims;
+        Ok(self.principal(format!("user:{}", claims.sub), Some(claims.sub), claims.roles))
+    }
+
+    fn principal(&self, id: String, user_id: Option<String>, roles: Vec<String>) -> Principal {
+        let privileged = roles.iter().any(|role| role == SERVICE_ROLE || role == &self.admin_role);
+        Principal { id, user_id, roles, privileged }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `access` の権限を求めるフィルター（足りなければ `AuthError` でリジェクトする）。
+///
+/// 認証した相手を取り出し（認証が無効か公開ルートなら `None`）、
+/// リクエストのスパンの `user_id` に記録する。
+pub fn require(auth: Arc<Authenticator>, access: Access) -> impl Filter<Extract = (Option<Principal>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(AUTHORIZATION.as_str())
+        .and(warp::header::optional::<String>(API_KEY_HEADER))
+        .and_then(move |authorization: Option<String>, api_key: Option<String>| {
+            let auth = auth.clone();
+            async move {
+                let principal = auth.authorize(access, authorization.as_deref(), api_key.as_deref()).map_err(warp::reject::custom)?;
+                if let Some(Principal { user_id, .. }) = &principal {
+                    tracing::Span::current().record("user_id", user_id.as_deref().unwrap_or("api-key"));
+                }
+                Ok::<_, Rejection>(principal)
+            }
+        })
+}
+
+/// 相手を使わないルート向けの `require`
+pub fn guard(auth: Arc<Authenticator>, access: Access) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    require(auth, access).map(|_| ()).untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use jsonwebtoken::{EncodingKey, Header};
+    use super::*;
+
+    const SECRET: &str = "test-jwt-secret";
+
+    #[derive(Serialize)]
+    struct TestClaims {
+        sub: &'static str,
+        roles: Vec<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exp: Option<u64>,
+    }
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(&AuthSettings {
+            enabled: true,
+            api_keys: vec!["test-api-key-0123456789".to_string()],
+            jwt_secret: Some(SECRET.to_string()),
+            ..AuthSettings::default()
+        })
+    }
+
+    // 今から `offset` 秒後に切れるトークン
+    fn token(algorithm: Algorithm, offset: i64, roles: Vec<&'static str>) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let claims = TestClaims { sub: "alice", roles, exp: Some((now + offset) as u64) };
+        jsonwebtoken::encode(&Header::new(algorithm), &claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap()
+    }
+
+    fn bearer(token: &str) -> String {
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn hmac_tokens_carry_the_user_and_roles() {
+        let auth = authenticator();
+        for algorithm in [Algorithm::HS256, Algorithm::HS384, Algorithm::HS512] {
+            let principal = auth.authenticate(Some(&bearer(&token(algorithm, 3600, vec!["admin"]))), None).unwrap();
+            assert_eq!(principal.id, "user:alice");
+            assert_eq!(principal.user_id.as_deref(), Some("alice"));
+            assert!(principal.privileged);
+            assert_eq!(principal.owner(), None);
+        }
+
+        let principal = auth.authenticate(Some(&bearer(&token(Algorithm::HS256, 3600, vec![]))), None).unwrap();
+        assert_eq!(principal.owner(), Some("alice"));
+    }
+
+    #[test]
+    fn tokens_with_other_algorithms_or_signatures_are_rejected() {
+        let auth = authenticator();
+
+        // 署名なし（alg: none）
+        let signed = token(Algorithm::HS256, 3600, vec![]);
+        let (_, rest) = signed.split_once('.').unwrap();
+        let (payload, _) = rest.split_once('.').unwrap();
+        let unsigned = format!("eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.{}.", payload);
+        assert!(matches!(auth.authenticate(Some(&bearer(&unsigned)), None), Err(AuthError::Invalid(_))));
+
+        let other_key = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &TestClaims { sub: "alice", roles: vec!["admin"], exp: Some(u64::MAX / 2) },
+            &EncodingKey::from_secret(b"another-secret"),
+        ).unwrap();
+        assert!(matches!(
+            auth.authenticate(Some(&bearer(&other_key)), None),
+            Err(AuthError::Invalid("the token signature is invalid"))
+        ));
+    }
+
+    #[test]
+    fn expired_tokens_and_tokens_without_exp_are_rejected() {
+        let auth = authenticator();
+
+        assert!(matches!(
+            auth.authenticate(Some(&bearer(&token(Algorithm::HS256, -3600, vec![]))), None),
+            Err(AuthError::Invalid("the token has expired"))
+        ));
+
+        let no_exp = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &TestClaims { sub: "alice", roles: vec![], exp: None },
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        ).unwrap();
+        assert!(matches!(
+            auth.authenticate(Some(&bearer(&no_exp)), None),
+            Err(AuthError::Invalid("the token must carry `sub` and `exp`"))
+        ));
+    }
+
+    #[test]
+    fn access_levels_follow_the_roles() {
+        let auth = authenticator();
+        let user = bearer(&token(Algorithm::HS256, 3600, vec![]));
+
+        assert_eq!(auth.authorize(Access::Public, None, None).unwrap(), None);
+        assert!(matches!(auth.authorize(Access::Authenticated, None, None), Err(AuthError::Missing)));
+        assert!(auth.authorize(Access::Authenticated, Some(&user), None).unwrap().is_some());
+        assert!(matches!(auth.authorize(Access::Admin, Some(&user), None), Err(AuthError::Forbidden)));
+        assert!(matches!(
+            auth.authorize(Access::Authenticated, Some("Basic abc"), None),
+            Err(AuthError::Invalid(_))
+        ));
+
+        // APIキーは既定でサービスのロールを持つ
+        let service = auth.authorize(Access::Authenticated, None, Some("test-api-key-0123456789")).unwrap().unwrap();
+        assert_eq!(service.id, "api-key:0");
+        assert!(service.privileged);
+    }
+}
@@ -0,0 +1,22 @@
+use warp::{Filter, Rejection, Reply};
+
+/// `docs.html` が読み込めるのは同じオリジンの `openapi.json` と、ページに埋め込んだスクリプトだけ
+const CONTENT_SECURITY_POLICY: &str =
+    "default-src 'none'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; connect-src 'self'";
+
+/// `/openapi.json` を表示する `/docs` ページ。
+///
+/// 表示用のスクリプトはページに埋め込んでバイナリに同梱するので、
+/// CDN に依存せずオフラインでも見られる。
+pub fn route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| {
+            warp::reply::with_header(
+                warp::reply::html(include_str!("docs.html")),
+                "content-security-policy",
+                CONTENT_SECURITY_POLICY,
+            )
+        })
+}
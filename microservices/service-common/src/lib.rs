@@ -0,0 +1,11 @@
+//! philosophy-ai と rust-perfection が共有する HTTP の部品。
+//!
+//! 認証・レート制限・リクエストの追跡・HTTP のメトリクスと、その設定、
+//! OpenAPI を表示する `/docs` ページ。
+
+pub mod auth;
+pub mod docs;
+pub mod metrics;
+pub mod rate_limit;
+pub mod settings;
+pub mod telemetry;
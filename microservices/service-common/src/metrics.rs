@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Prometheus のテキスト形式の Content-Type
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// 処理時間のヒストグラムの区切り（秒）
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    count: u64,
+    sum: f64,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+/// ルート・メソッド・ステータスごとのリクエスト数と処理時間
+#[derive(Default)]
+pub struct HttpMetrics {
+    series: Mutex<BTreeMap<(String, String, u16), Histogram>>,
+}
+
+impl HttpMetrics {
+    /// `route` はルートのテンプレート（`telemetry::serve` がパスから決める）
+    pub fn observe(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut series = self.series.lock().unwrap();
+        let histogram = series.entry((route.to_string(), method.to_string(), status)).or_default();
+        let seconds = elapsed.as_secs_f64();
+        histogram.count += 1;
+        histogram.sum += seconds;
+        for (bucket, le) in histogram.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// `{prefix}_http_requests_total` と `{prefix}_http_request_duration_seconds` を書き出す
+    pub fn render(&self, out: &mut String, prefix: &str) {
+        let series = self.series.lock().unwrap();
+        let requests = format!("{}_http_requests_total", prefix);
+        header(out, &requests, "counter", "HTTP requests by route, method and status.");
+        for ((route, method, status), histogram) in series.iter() {
+            let labels = format!("route=\"{}\",method=\"{}\",status=\"{}\"", escape(route), method, status);
+            let _ = writeln!(out, "{}{{{}}} {}", requests, labels, histogram.count);
+        }
+        let duration = format!("{}_http_request_duration_seconds", prefix);
+        header(out, &duration, "histogram", "HTTP request latency by route, method and status.");
+        for ((route, method, status), histogram) in series.iter() {
+            let labels = format!("route=\"{}\",method=\"{}\",status=\"{}\"", escape(route), method, status);
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", duration, labels, le, count);
+            }
+            let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", duration, labels, histogram.count);
+            let _ = writeln!(out, "{}_sum{{{}}} {}", duration, labels, histogram.sum);
+            let _ = writeln!(out, "{}_count{{{}}} {}", duration, labels, histogram.count);
+        }
+    }
+}
+
+static HTTP_METRICS: OnceLock<HttpMetrics> = OnceLock::new();
+
+/// プロセス全体で共有する HTTP のメトリクス（`telemetry::serve` が記録する）
+pub fn http() -> &'static HttpMetrics {
+    HTTP_METRICS.get_or_init(HttpMetrics::default)
+}
+
+/// `# HELP` と `# TYPE` の行
+pub fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// ラベルの値のエスケープ
+pub fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+use crate::auth::{self, Access, Authenticator, Principal};
+use crate::settings::{BucketSettings, RateLimitSettings};
+use crate::telemetry::RemoteAddr;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// 制限を超えたときのリジェクション（429 と `Retry-After` になる）
+#[derive(Debug)]
+pub struct RateLimited {
+    /// 次のリクエストを受け付けられるまでの秒数
+    pub retry_after: u64,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Table {
+    buckets: HashMap<String, Bucket>,
+    swept: Instant,
+}
+
+struct Buckets {
+    settings: BucketSettings,
+    // 空のバケットが満杯に戻るまでの時間。この間隔で満杯のバケットを捨てる
+    sweep_interval: Duration,
+    table: Mutex<Table>,
+}
+
+impl Buckets {
+    fn new(settings: BucketSettings) -> Self {
+        let refill = settings.burst as f64 / settings.per_second;
+        let sweep_interval = Duration::from_secs_f64(refill.clamp(1.0, 3600.0));
+        Buckets {
+            settings,
+            sweep_interval,
+            table: Mutex::new(Table { buckets: HashMap::new(), swept: Instant::now() }),
+        }
+    }
+
+    // 1つ使えたら `Ok`、使えなければ次に使えるまでの秒数
+    fn take(&self, key: &str, now: Instant) -> Result<(), f64> {
+        let BucketSettings { per_second, burst } = self.settings;
+        let capacity = burst as f64;
+        let refilled = |bucket: &Bucket| {
+            (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second).min(capacity)
+        };
+
+        let mut table = self.table.lock().unwrap();
+        if now.duration_since(table.swept) >= self.sweep_interval {
+            // 満杯のバケットは新しく作るのと同じなので捨ててよい。前回から
+            // 満杯に戻るだけの時間が経っているので、その間に使われなかった
+            // クライアントは全て消え、表の大きさはこの間隔に来たクライアントの数で収まる
+            table.buckets.retain(|_, bucket| refilled(bucket) < capacity);
+            table.swept = now;
+        }
+        let bucket = table.buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - bucket.tokens) / per_second)
+        }
+    }
+}
+
+fn rate_limited(seconds: f64) -> RateLimited {
+    RateLimited { retry_after: (seconds.ceil() as u64).max(1) }
+}
+
+/// クライアントIPごと・認証した相手ごとのトークンバケット。
+///
+/// 認証の前にIPのバケット、認証の後に相手（JWT の `sub` かAPIキー）の
+/// バケットから1つずつ使う。信頼するプロキシ（Go のバックエンドなど）からの
+/// リクエストは `X-Forwarded-For` のうちプロキシでない一番右のIPで数え、
+/// ヘッダーが無ければIPでは制限しない。
+pub struct RateLimiter {
+    enabled: bool,
+    per_ip: Buckets,
+    per_principal: Buckets,
+    trusted_proxies: Vec<(IpAddr, u8)>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        RateLimiter {
+            enabled: settings.enabled,
+            per_ip: Buckets::new(settings.per_ip),
+            per_principal: Buckets::new(settings.per_principal),
+            trusted_proxies: settings.trusted_proxies.iter().filter_map(|proxy| parse_network(proxy)).collect(),
+        }
+    }
+
+    /// クライアントIPのバケットから1つ使う
+    pub fn check_ip(&self, remote: Option<IpAddr>, forwarded_for: Option<&str>) -> Result<(), RateLimited> {
+        if !self.enabled {
+            return Ok(());
+        }
+        match remote.and_then(|remote| self.client_ip(remote, forwarded_for)) {
+            Some(client) => self.per_ip.take(&client.to_string(), Instant::now()).map_err(rate_limited),
+            None => Ok(()),
+        }
+    }
+
+    /// 認証した相手のバケットから1つ使う
+    pub fn check_principal(&self, principal: &Principal) -> Result<(), RateLimited> {
+        if !self.enabled {
+            return Ok(());
+        }
+        self.per_principal.take(&principal.id, Instant::now()).map_err(rate_limited)
+    }
+
+    fn client_ip(&self, remote: IpAddr, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        if !self.is_trusted(remote) {
+            return Some(remote);
+        }
+        forwarded_for?
+            .split(',')
+            .rev()
+            .filter_map(|address| address.trim().parse::<IpAddr>().ok())
+            .find(|address| !self.is_trusted(*address))
+    }
+
+    fn is_trusted(&self, address: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|&(network, prefix)| contains(network, prefix, address))
+    }
+}
+
+/// `10.0.0.1` や `10.0.0.0/8`・`fd00::/8` を (アドレス, プレフィックス長) にする
+pub fn parse_network(value: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match value.trim().split_once('/') {
+        Some((address, prefix)) => (address.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+        None => (value.trim().parse::<IpAddr>().ok()?, None),
+    };
+    let bits = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(bits);
+    (prefix <= bits).then_some((address, prefix))
+}
+
+fn contains(network: IpAddr, prefix: u8, address: IpAddr) -> bool {
+    let (network, address, bits) = match (network, address.to_canonical()) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => (u32::from(network) as u128, u32::from(address) as u128, 32),
+        (IpAddr::V6(network), IpAddr::V6(address)) => (u128::from(network), u128::from(address), 128),
+        _ => return false,
+    };
+    let shift = bits - prefix as u32;
+    shift >= bits || network >> shift == address >> shift
+}
+
+/// 認証とレート制限をまとめて掛けるフィルターを作る。
+///
+/// ルートのパスとメソッドの後に置くので、どのルートにも当たらない
+/// リクエスト（404）ではトークンを使わない。
+#[derive(Clone)]
+pub struct Gate {
+    auth: Arc<Authenticator>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Gate {
+    pub fn new(auth: Arc<Authenticator>, limiter: Arc<RateLimiter>) -> Self {
+        Gate { auth, limiter }
+    }
+
+    /// IPごとの制限 → `auth::require` → 相手ごとの制限の順に確かめ、認証した相手を取り出す
+    pub fn require(&self, access: Access) -> impl Filter<Extract = (Option<Principal>,), Error = Rejection> + Clone {
+        let limiter = self.limiter.clone();
+        let per_principal = self.limiter.clone();
+        warp::ext::optional::<RemoteAddr>()
+            .and(warp::addr::remote())
+            .and(warp::header::optional::<String>("x-forwarded-for"))
+            .and_then(move |traced: Option<RemoteAddr>, direct: Option<SocketAddr>, forwarded_for: Option<String>| {
+                let limiter = limiter.clone();
+                async move {
+                    let remote = traced.map(|RemoteAddr(addr)| addr).or(direct).map(|addr| addr.ip().to_canonical());
+                    limiter.check_ip(remote, forwarded_for.as_deref()).map_err(warp::reject::custom)
+                }
+            })
+            .untuple_one()
+            .and(auth::require(self.auth.clone(), access))
+            .and_then(move |principal: Option<Principal>| {
+                let limiter = per_principal.clone();
+                async move {
+                    if let Some(principal) = &principal {
+                        limiter.check_principal(principal).map_err(warp::reject::custom)?;
+                    }
+                    Ok::<_, Rejection>(principal)
+                }
+            })
+    }
+
+    /// 相手を使わないルート向けの `require`
+    pub fn guard(&self, access: Access) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        self.require(access).map(|_| ()).untuple_one()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(trusted_proxies: &[&str]) -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            per_ip: BucketSettings { per_second: 1.0, burst: 2 },
+            trusted_proxies: trusted_proxies.iter().map(|proxy| proxy.to_string()).collect(),
+            ..RateLimitSettings::default()
+        })
+    }
+
+    #[test]
+    fn buckets_refill_at_the_configured_rate() {
+        let buckets = Buckets::new(BucketSettings { per_second: 2.0, burst: 2 });
+        let start = Instant::now();
+
+        assert!(buckets.take("a", start).is_ok());
+        assert!(buckets.take("a", start).is_ok());
+        let wait = buckets.take("a", start).unwrap_err();
+        assert!((wait - 0.5).abs() < 1e-9, "{}", wait);
+        assert_eq!(rate_limited(wait).retry_after, 1);
+
+        // 0.5秒で1つ戻り、満杯より多くは貯まらない
+        assert!(buckets.take("a", start + Duration::from_millis(500)).is_ok());
+        assert!(buckets.take("a", start + Duration::from_millis(500)).is_err());
+        let later = start + Duration::from_secs(60);
+        assert!(buckets.take("a", later).is_ok());
+        assert!(buckets.take("a", later).is_ok());
+        assert!(buckets.take("a", later).is_err());
+
+        // 別のキーは別に数える
+        assert!(buckets.take("b", start).is_ok());
+    }
+
+    #[test]
+    fn full_buckets_are_swept() {
+        let buckets = Buckets::new(BucketSettings { per_second: 1.0, burst: 2 });
+        let start = Instant::now();
+        buckets.take("a", start).unwrap();
+        buckets.take("b", start + Duration::from_millis(1500)).unwrap();
+        buckets.take("b", start + Duration::from_millis(1500)).unwrap();
+
+        // 満杯に戻るまでの2秒が経つと掃除する。"a" は満杯に戻っているが "b" はまだ
+        buckets.take("c", start + Duration::from_millis(2500)).unwrap();
+        let table = buckets.table.lock().unwrap();
+        assert!(!table.buckets.contains_key("a"));
+        assert!(table.buckets.contains_key("b"));
+        assert!(table.buckets.contains_key("c"));
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_from_proxies() {
+        let limiter = limiter(&["10.0.0.0/8", "fd00::/8"]);
+        let ip = |value: &str| value.parse::<IpAddr>().unwrap();
+
+        // プロキシでない相手のヘッダーは偽装できるので見ない
+        assert_eq!(limiter.client_ip(ip("192.0.2.1"), Some("198.51.100.7")), Some(ip("192.0.2.1")));
+        // プロキシでない一番右のアドレス
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), Some("203.0.113.9, 198.51.100.7, 10.0.0.2")), Some(ip("198.51.100.7")));
+        assert_eq!(limiter.client_ip(ip("fd00::1"), Some("garbage, 198.51.100.7")), Some(ip("198.51.100.7")));
+        // ヘッダーが無いか全てプロキシならIPでは数えない
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), None), None);
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), Some("10.1.2.3")), None);
+
+        assert!(limiter.check_ip(Some(ip("10.0.0.1")), None).is_ok());
+        for _ in 0..2 {
+            assert!(limiter.check_ip(Some(ip("10.0.0.1")), Some("198.51.100.7")).is_ok());
+        }
+        assert!(limiter.check_ip(Some(ip("10.0.0.2")), Some("198.51.100.7")).is_err());
+        assert!(limiter.check_ip(Some(ip("10.0.0.2")), Some("198.51.100.8")).is_ok());
+    }
+
+    #[test]
+    fn networks_are_parsed_with_optional_prefixes() {
+        assert_eq!(parse_network("10.0.0.1"), Some(("10.0.0.1".parse().unwrap(), 32)));
+        assert_eq!(parse_network(" 10.0.0.0/8 "), Some(("10.0.0.0".parse().unwrap(), 8)));
+        assert_eq!(parse_network("::1"), Some(("::1".parse().unwrap(), 128)));
+        assert_eq!(parse_network("10.0.0.0/33"), None);
+        assert_eq!(parse_network("proxy.internal"), None);
+
+        assert!(contains("0.0.0.0".parse().unwrap(), 0, "203.0.113.9".parse().unwrap()));
+        assert!(contains("10.0.0.0".parse().unwrap(), 8, "::ffff:10.9.8.7".parse().unwrap()));
+        assert!(!contains("10.0.0.0".parse().unwrap(), 8, "11.0.0.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::auth::{self, Access};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    pub level: String,
+    /// `text`（人が読む形式）か `json`（1行1イベント）
+    pub format: String,
+}
+
+/// クライアントごとのレート制限（トークンバケット）。`/health` などは対象外
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// クライアントIPごとの制限
+    pub per_ip: BucketSettings,
+    /// 認証した相手（JWT の `sub` かAPIキー）ごとの制限
+    #[serde(alias = "per_api_key")]
+    pub per_principal: BucketSettings,
+    /// 手前のプロキシ（IPかCIDR）。ここからのリクエストは `X-Forwarded-For` の
+    /// クライアントIPで数え、ヘッダーが無ければIPでは制限しない
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketSettings {
+    /// 1秒あたりに補充するリクエスト数
+    pub per_second: f64,
+    /// 続けて受け付けられる数（バケットの容量）
+    pub burst: u32,
+}
+
+/// APIキーかJWTによる認証。`/health` とドキュメントは常に公開
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    pub enabled: bool,
+    /// サービス用の固定のAPIキー（Go のバックエンドなど）
+    pub api_keys: Vec<String>,
+    /// APIキーで認証した相手のロール
+    pub api_key_roles: Vec<String>,
+    /// JWT（HS256/384/512）の署名を確かめる共有の秘密鍵
+    pub jwt_secret: Option<String>,
+    /// 指定すると JWT の `iss` がこれと一致する必要がある
+    pub jwt_issuer: Option<String>,
+    /// 指定すると JWT の `aud` にこれが含まれる必要がある
+    pub jwt_audience: Option<String>,
+    /// `admin` のルートを呼べるロール
+    pub admin_role: String,
+    /// ルートごとの権限（ルート名はサービスごとに決まる）。無いルートは既定の権限
+    pub routes: BTreeMap<String, Access>,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        LoggingSettings {
+            level: "info".to_string(),
+            format: "text".to_string(),
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            enabled: true,
+            per_ip: BucketSettings { per_second: 5.0, burst: 30 },
+            per_principal: BucketSettings { per_second: 50.0, burst: 200 },
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        AuthSettings {
+            enabled: false,
+            api_keys: Vec::new(),
+            api_key_roles: vec![auth::SERVICE_ROLE.to_string()],
+            jwt_secret: None,
+            jwt_issuer: None,
+            jwt_audience: None,
+            admin_role: "admin".to_string(),
+            routes: BTreeMap::new(),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::convert::Infallible;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::time::Instant;
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Method, Request, Response};
+use tracing::Instrument;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+use crate::settings::LoggingSettings;
+use crate::metrics;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// W3C Trace Context のヘッダー
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// どのルートにも合わないパスのメトリクスの `route` ラベル
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// 接続元のアドレス（`serve` がリクエストの拡張に入れる。`warp::addr::remote()` の代わり）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteAddr(pub SocketAddr);
+
+// 受け取る `X-Request-Id` の最大長（超えたら新しく振る）
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// `logging.level` と `logging.format` に従ってログを初期化する。
+///
+/// `json` は1行1イベントのJSONで、リクエストのスパン（`request_id`・`trace_id` など）を
+/// `span` に含める。hyper の内部ログは `info` より細かくしない。
+pub fn init(settings: &LoggingSettings) {
+    let level: LevelFilter = settings.level.parse().unwrap_or(LevelFilter::INFO);
+    let filter = Targets::new()
+        .with_default(level)
+        .with_target("hyper", level.min(LevelFilter::INFO))
+        .with_target("h2", level.min(LevelFilter::INFO));
+    let registry = tracing_subscriber::registry().with(filter);
+
+    let result = if settings.format == "json" {
+        registry
+            .with(tracing_subscriber::fmt::layer().json().flatten_event(true).with_current_span(true).with_span_list(false))
+            .try_init()
+    } else {
+        // パイプやファイルに出すときは色を付けない
+        registry.with(tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal())).try_init()
+    };
+    if let Err(e) = result {
+        eprintln!("⚠️ Failed to initialize logging: {}", e);
+    }
+}
+
+/// リクエストを追跡するためのID。
+///
+/// 呼び出し元（Go のバックエンドなど）から `X-Request-Id` と `traceparent` を受け取り、
+/// 無いか不正なときは新しく振る。`span_id` はこのサービスでの処理を表す。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub request_id: String,
+    /// 32桁の16進数
+    pub trace_id: String,
+    /// 呼び出し元のスパンID（`traceparent` を受け取ったとき）
+    pub parent_id: Option<String>,
+    /// 16桁の16進数
+    pub span_id: String,
+    pub flags: String,
+}
+
+impl TraceContext {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let request_id = headers.get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.chars().all(|c| c.is_ascii_graphic()))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let parent = headers.get(TRACEPARENT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_traceparent);
+        let span_id = Uuid::new_v4().simple().to_string()[..16].to_string();
+
+        match parent {
+            Some((trace_id, parent_id, flags)) => TraceContext { request_id, trace_id, parent_id: Some(parent_id), span_id, flags },
+            None => TraceContext {
+                request_id,
+                trace_id: Uuid::new_v4().simple().to_string(),
+                parent_id: None,
+                span_id,
+                flags: "01".to_string(),
+            },
+        }
+    }
+
+    /// 下流に渡す・レスポンスで返す `traceparent`（親はこのサービスのスパン）
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+// `{version}-{trace-id}-{parent-id}-{flags}`。全て0のIDと version `ff` は不正
+fn parse_traceparent(value: &str) -> Option<(String, String, String)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    let is_hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    let is_zero = |s: &str| s.bytes().all(|b| b == b'0');
+    match parts.as_slice() {
+        [version, trace_id, parent_id, flags, ..]
+            if is_hex(version, 2) && *version != "ff" && (*version != "00" || parts.len() == 4)
+                && is_hex(trace_id, 32) && !is_zero(trace_id)
+                && is_hex(parent_id, 16) && !is_zero(parent_id)
+                && is_hex(flags, 2) =>
+        {
+            Some((trace_id.to_string(), parent_id.to_string(), flags.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// パスがルートのテンプレートに合うか（`{id}` のような要素は空でない任意の1要素に合う）
+pub fn matches_template(template: &str, path: &str) -> bool {
+    let (mut template, mut path) = (template.split('/'), path.split('/'));
+    loop {
+        match (template.next(), path.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(actual)) if expected == actual || (expected.starts_with('{') && !actual.is_empty()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// `warp::serve` の代わりに、リクエストごとのスパンを付けてサーブする。
+///
+/// スパンには `request_id`・`trace_id`・`span_id`・メソッド・パスを載せ、
+/// 終わったときにステータスと処理時間をログとメトリクスに記録する。メトリクスは
+/// `route_label` がパスから決めるルートのテンプレートごとに数える（合わなければ
+/// `UNMATCHED_ROUTE`）ので、でたらめなパスが来てもラベルの種類は増えない。`X-Request-Id` は
+/// リクエストにも書き戻すのでハンドラーからも読め、`TraceContext` は
+/// `warp::ext::get::<TraceContext>()` で取り出せる。レスポンスには
+/// `X-Request-Id` と `traceparent` を付けて返す。接続元は `RemoteAddr` で取り出せる。
+/// 認証した相手はフィルターがスパンの `user_id` に記録する。
+pub async fn serve<F, R>(filter: F, addr: SocketAddr, route_label: fn(&str) -> String) -> Result<(), hyper::Error>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let service = warp::service(filter);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let service = service.clone();
+        let remote = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| traced(service.clone(), request, remote, route_label)))
+        }
+    });
+    hyper::Server::try_bind(&addr)?.serve(make_service).await
+}
+
+async fn traced<S>(mut service: S, mut request: Request<Body>, remote: SocketAddr, route_label: fn(&str) -> String) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let context = TraceContext::from_headers(request.headers());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %context.request_id,
+        trace_id = %context.trace_id,
+        span_id = %context.span_id,
+        parent_id = context.parent_id.as_deref(),
+        method = %request.method(),
+        path = %request.uri().path(),
+        remote_addr = %remote,
+        user_id = tracing::field::Empty,
+    );
+    let route = route_label(request.uri().path());
+    // 標準以外のメソッドもラベルを増やさないようにまとめる
+    let method = match *request.method() {
+        Method::GET | Method::POST | Method::PUT | Method::DELETE | Method::PATCH | Method::HEAD | Method::OPTIONS => request.method().to_string(),
+        _ => "other".to_string(),
+    };
+    let request_id = HeaderValue::from_str(&context.request_id).expect("request ids are visible ASCII");
+    request.headers_mut().insert(REQUEST_ID_HEADER, request_id.clone());
+    request.extensions_mut().insert(context.clone());
+    request.extensions_mut().insert(RemoteAddr(remote));
+
+    async move {
+        let started = Instant::now();
+        tracing::debug!("request started");
+        let mut response = service.call(request).await?;
+
+        let status = response.status().as_u16();
+        let elapsed = started.elapsed();
+        metrics::http().observe(&method, &route, status, elapsed);
+        let elapsed_ms = (elapsed.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+        if response.status().is_server_error() {
+            tracing::error!(status, elapsed_ms, "request failed");
+        } else if response.status().is_client_error() {
+            tracing::warn!(status, elapsed_ms, "request rejected");
+        } else {
+            tracing::info!(status, elapsed_ms, "request completed");
+        }
+
+        let headers = response.headers_mut();
+        headers.insert(REQUEST_ID_HEADER, request_id);
+        if let Ok(traceparent) = HeaderValue::from_str(&context.traceparent()) {
+            headers.insert(TRACEPARENT_HEADER, traceparent);
+        }
+        Ok(response)
+    }
+    .instrument(span)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn valid_traceparents_are_parsed() {
+        let expected = Some((TRACE_ID.to_string(), PARENT_ID.to_string(), "01".to_string()));
+
+        assert_eq!(parse_traceparent(&format!("00-{}-{}-01", TRACE_ID, PARENT_ID)), expected);
+        assert_eq!(parse_traceparent(&format!(" 00-{}-{}-01 ", TRACE_ID, PARENT_ID)), expected);
+        // 将来のバージョンは後ろに続きがあってもよい
+        assert_eq!(parse_traceparent(&format!("01-{}-{}-01-extra", TRACE_ID, PARENT_ID)), expected);
+    }
+
+    #[test]
+    fn invalid_traceparents_are_ignored() {
+        let zero_trace = "0".repeat(32);
+        let zero_parent = "0".repeat(16);
+        let upper = TRACE_ID.to_uppercase();
+        for value in [
+            format!("ff-{}-{}-01", TRACE_ID, PARENT_ID),
+            format!("00-{}-{}-01-extra", TRACE_ID, PARENT_ID),
+            format!("00-{}-{}-01", zero_trace, PARENT_ID),
+            format!("00-{}-{}-01", TRACE_ID, zero_parent),
+            format!("00-{}-{}-01", upper, PARENT_ID),
+            format!("00-{}-{}-1", TRACE_ID, PARENT_ID),
+            format!("00-{}-{}", TRACE_ID, PARENT_ID),
+            "garbage".to_string(),
+        ] {
+            assert_eq!(parse_traceparent(&value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn trace_context_continues_the_callers_trace() {
+        let traceparent = format!("00-{}-{}-00", TRACE_ID, PARENT_ID);
+        let context = TraceContext::from_headers(&headers(&[(REQUEST_ID_HEADER, "req-123"), (TRACEPARENT_HEADER, &traceparent)]));
+        assert_eq!(context.request_id, "req-123");
+        assert_eq!(context.trace_id, TRACE_ID);
+        assert_eq!(context.parent_id.as_deref(), Some(PARENT_ID));
+        assert_eq!(context.flags, "00");
+        assert_eq!(context.span_id.len(), 16);
+        assert_ne!(context.span_id, PARENT_ID);
+        assert_eq!(context.traceparent(), format!("00-{}-{}-00", TRACE_ID, context.span_id));
+        assert!(parse_traceparent(&context.traceparent()).is_some());
+    }
+
+    #[test]
+    fn missing_or_invalid_ids_start_a_new_trace() {
+        let long_id = "x".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        let context = TraceContext::from_headers(&headers(&[(REQUEST_ID_HEADER, &long_id), (TRACEPARENT_HEADER, "garbage")]));
+        assert_ne!(context.request_id, long_id);
+        assert!(Uuid::parse_str(&context.request_id).is_ok());
+        assert_eq!(context.parent_id, None);
+        assert_eq!(context.flags, "01");
+        assert!(parse_traceparent(&context.traceparent()).is_some());
+    }
+
+    #[test]
+    fn templates_match_one_segment_per_parameter() {
+        assert!(matches_template("/analysis/{id}", "/analysis/abc"));
+        assert!(!matches_template("/analysis/{id}", "/analysis/"));
+        assert!(!matches_template("/analysis/{id}", "/analysis/abc/def"));
+        assert!(matches_template("/health", "/health"));
+        assert!(!matches_template("/health", "/healthz"));
+    }
+}